@@ -1,4 +1,8 @@
-use std::io;
+use std::{
+    io,
+    net::Shutdown,
+    os::unix::net::{UnixListener, UnixStream},
+};
 
 pub struct Capture<S, O> {
     source: S,
@@ -41,6 +45,74 @@ where
     }
 }
 
+/// Forwards data received `from` one side of connection `to` the other side, captures it
+/// into `output`
+fn forward(
+    from: &UnixStream,
+    to: &UnixStream,
+    output: impl io::Write,
+    prepend: &'static [u8],
+) -> io::Result<()> {
+    let mut capture = Capture {
+        source: from,
+        output,
+        prepend,
+        buffer: Vec::with_capacity(1000),
+    };
+    io::copy(&mut capture, &mut &*to)?;
+    // Let the other side know that nothing else is coming. It might have closed the connection
+    // already, so error is ignored.
+    let _ = to.shutdown(Shutdown::Write);
+    Ok(())
+}
+
+/// Proxies traffic between the client and the server until both close the connection
+fn proxy_connection(
+    client: UnixStream,
+    server: UnixStream,
+    out_reqs: impl io::Write + Send,
+    out_resps: impl io::Write + Send,
+) -> io::Result<()> {
+    let (client, server) = (&client, &server);
+    std::thread::scope(|s| {
+        let requests = s.spawn(move || forward(client, server, out_reqs, b"C: "));
+        let responses = s.spawn(move || forward(server, client, out_resps, b"S: "));
+        let requests = requests.join().expect("handle requests panicked");
+        let responses = responses.join().expect("handle responses panicked");
+        requests.and(responses)
+    })
+}
+
+/// Listens on `listen_path` and proxies every accepted client to the server listening on
+/// `server_path`
+fn proxy_socket(
+    listen_path: &str,
+    server_path: &str,
+    output: impl Fn() -> std::fs::File,
+) -> io::Result<()> {
+    let listener = UnixListener::bind(listen_path)?;
+    for client in listener.incoming() {
+        let client = client?;
+        let server = match UnixStream::connect(server_path) {
+            Ok(server) => server,
+            Err(err) => {
+                eprintln!("couldn't connect to {server_path}: {err}");
+                continue;
+            }
+        };
+        let (out_reqs, out_resps) = (output(), output());
+        std::thread::spawn(move || {
+            if let Err(err) = proxy_connection(client, server, out_reqs, out_resps) {
+                eprintln!("proxying failed: {err}");
+            }
+        });
+    }
+    Ok(())
+}
+
+const USAGE: &str = "Usage: ./assuan-hijack OUTPUT_PATH EXECUTABLE_PATH [--] [args..]
+       ./assuan-hijack OUTPUT_PATH --socket LISTEN_SOCKET_PATH SERVER_SOCKET_PATH";
+
 fn main() {
     let mut args = std::env::args().peekable();
     let _prog = args.next().unwrap();
@@ -48,23 +120,32 @@ fn main() {
     let (output, executable) = match (args.next(), args.next()) {
         (Some(a), Some(b)) => (a, b),
         _ => {
-            eprintln!("Usage: ./assuan-hijack OUTPUT_PATH EXECUTABLE_PATH [--] [args..]");
+            eprintln!("{USAGE}");
             std::process::exit(1);
         }
     };
 
-    if args.peek().map(String::as_str) == Some("--") {
-        let _ = args.next();
-    }
-
     let output = || {
         std::fs::OpenOptions::new()
-            .write(true)
             .append(true)
             .create(true)
             .open(&output)
             .expect("couldn't open output file")
     };
+
+    if executable == "--socket" {
+        let (Some(listen_path), Some(server_path)) = (args.next(), args.next()) else {
+            eprintln!("{USAGE}");
+            std::process::exit(1);
+        };
+        proxy_socket(&listen_path, &server_path, output).expect("proxying socket failed");
+        return;
+    }
+
+    if args.peek().map(String::as_str) == Some("--") {
+        let _ = args.next();
+    }
+
     let out_reqs = output();
     let out_resps = output();
 
@@ -102,4 +183,94 @@ fn main() {
 
     handle_requests.join().expect("handle requests error");
     handle_responses.join().expect("handle responses error");
+    child.wait().expect("wait for child");
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        io::{self, BufRead, Write},
+        os::unix::net::{UnixListener, UnixStream},
+        sync::{Arc, Mutex},
+    };
+
+    use super::proxy_connection;
+
+    /// Log shared between the threads
+    #[derive(Clone, Default)]
+    struct Log(Arc<Mutex<Vec<u8>>>);
+
+    impl io::Write for Log {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.lock().unwrap().write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn proxies_between_sockets() {
+        let dir = std::env::temp_dir().join(format!("assuan-hijack-test-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let server_path = dir.join("server");
+        let proxy_path = dir.join("proxy");
+        let _ = std::fs::remove_file(&server_path);
+        let _ = std::fs::remove_file(&proxy_path);
+
+        let server_listener = UnixListener::bind(&server_path).unwrap();
+        let proxy_listener = UnixListener::bind(&proxy_path).unwrap();
+
+        let server = std::thread::spawn(move || {
+            let (conn, _) = server_listener.accept().unwrap();
+            let mut write = &conn;
+            write.write_all(b"OK ready\n").unwrap();
+            for line in io::BufReader::new(&conn).lines() {
+                match line.unwrap().as_str() {
+                    "BYE" => {
+                        write.write_all(b"OK closing\n").unwrap();
+                        break;
+                    }
+                    _ => write.write_all(b"OK success\n").unwrap(),
+                }
+            }
+        });
+        let log = Log::default();
+        let proxy = std::thread::spawn({
+            let log = log.clone();
+            move || {
+                let (client, _) = proxy_listener.accept().unwrap();
+                let server = UnixStream::connect(&server_path).unwrap();
+                proxy_connection(client, server, log.clone(), log).unwrap();
+            }
+        });
+
+        let client = UnixStream::connect(&proxy_path).unwrap();
+        let mut responses = io::BufReader::new(&client).lines();
+        let mut write = &client;
+        assert_eq!(responses.next().unwrap().unwrap(), "OK ready");
+        write.write_all(b"NOP\n").unwrap();
+        assert_eq!(responses.next().unwrap().unwrap(), "OK success");
+        write.write_all(b"BYE\n").unwrap();
+        assert_eq!(responses.next().unwrap().unwrap(), "OK closing");
+        // Server closed the connection
+        assert!(responses.next().is_none());
+        drop(responses);
+        drop(client);
+
+        server.join().unwrap();
+        proxy.join().unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        let log = String::from_utf8(log.0.lock().unwrap().clone()).unwrap();
+        assert_eq!(
+            log,
+            "S: OK ready\\n\n\
+            C: NOP\\n\n\
+            S: OK success\\n\n\
+            C: BYE\\n\n\
+            S: OK closing\\n\n"
+        );
+    }
 }
@@ -0,0 +1,34 @@
+//! Measures performance of building data responses
+//!
+//! Run via:
+//! ```bash
+//! cargo bench -p assuan --bench append
+//! ```
+
+use std::hint::black_box;
+use std::time::Instant;
+
+use assuan::response::Data;
+
+const ITERATIONS: u32 = 100_000;
+
+fn bench(name: &str, data: &str) {
+    let started = Instant::now();
+    for _ in 0..ITERATIONS {
+        let resp = Data::new(black_box(data)).unwrap();
+        black_box(resp);
+    }
+    let per_iter = started.elapsed() / ITERATIONS;
+    println!("{name:<30} {per_iter:>10?} per response");
+}
+
+fn main() {
+    let ascii = "0123456789ABCDEF".repeat(60);
+    let escaped = "a\nb%c\\d\r".repeat(60);
+    let multibyte = "Γ🍆Жࢨ".repeat(60);
+
+    bench("ascii (960 bytes)", &ascii);
+    bench("pin (8 bytes)", "12345678");
+    bench("escape-heavy (480 bytes)", &escaped);
+    bench("multibyte (720 bytes)", &multibyte);
+}
@@ -19,5 +19,5 @@ fn main() -> std::io::Result<()> {
 
     assuan::AssuanServer::new(greeter)
         .add_command("GREET", Greeter::greet)
-        .serve_client(std::io::stdin(), std::io::stdout())
+        .run_stdio()
 }
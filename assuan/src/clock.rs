@@ -0,0 +1,164 @@
+//! Clock abstraction
+//!
+//! Time-dependent features (such as timeouts) never query the system time directly. Instead,
+//! they consult a [`Clock`], which makes it possible to substitute [`SystemClock`] with
+//! [`MockClock`] in tests and make all time-dependent behavior deterministic.
+
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// Source of time
+pub trait Clock {
+    /// Returns the current instant
+    fn now(&self) -> Instant;
+
+    /// Blocks the current thread for the given `duration`
+    fn sleep(&self, duration: Duration);
+}
+
+impl<C: Clock + ?Sized> Clock for &C {
+    fn now(&self) -> Instant {
+        (**self).now()
+    }
+
+    fn sleep(&self, duration: Duration) {
+        (**self).sleep(duration)
+    }
+}
+
+impl<C: Clock + ?Sized> Clock for Box<C> {
+    fn now(&self) -> Instant {
+        (**self).now()
+    }
+
+    fn sleep(&self, duration: Duration) {
+        (**self).sleep(duration)
+    }
+}
+
+/// Clock backed by the system time
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> Instant {
+        Instant::now()
+    }
+
+    fn sleep(&self, duration: Duration) {
+        std::thread::sleep(duration)
+    }
+}
+
+/// Clock that only moves when it's told to
+///
+/// Intended to be used in tests. Clones of the mock clock share the same time, so one copy can
+/// be given to the code under test while another one is used to [advance](Self::advance) the time.
+/// [`sleep`](Clock::sleep) doesn't block, it advances the time instead.
+///
+/// ### Example
+/// ```rust
+/// use std::time::Duration;
+/// use assuan::clock::{Clock, Deadline, MockClock};
+///
+/// let clock = MockClock::new();
+/// let deadline = Deadline::after(&clock, Duration::from_secs(5));
+/// assert!(!deadline.is_expired(&clock));
+///
+/// clock.advance(Duration::from_secs(5));
+/// assert!(deadline.is_expired(&clock));
+/// ```
+#[derive(Debug, Clone)]
+pub struct MockClock {
+    now: Arc<Mutex<Instant>>,
+}
+
+impl MockClock {
+    /// Constructs a mock clock frozen at the current instant
+    pub fn new() -> Self {
+        Self {
+            now: Arc::new(Mutex::new(Instant::now())),
+        }
+    }
+
+    /// Moves the clock forward by `duration`
+    pub fn advance(&self, duration: Duration) {
+        let mut now = self.now.lock().unwrap_or_else(|err| err.into_inner());
+        *now += duration;
+    }
+}
+
+impl Default for MockClock {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Clock for MockClock {
+    fn now(&self) -> Instant {
+        *self.now.lock().unwrap_or_else(|err| err.into_inner())
+    }
+
+    fn sleep(&self, duration: Duration) {
+        self.advance(duration)
+    }
+}
+
+/// Point in time after which an operation is considered to be timed out
+#[derive(Debug, Clone, Copy)]
+pub struct Deadline(Instant);
+
+impl Deadline {
+    /// Constructs a deadline that expires once `timeout` elapses from now
+    pub fn after(clock: &impl Clock, timeout: Duration) -> Self {
+        Self(clock.now() + timeout)
+    }
+
+    /// Indicates whether the deadline has been reached
+    pub fn is_expired(&self, clock: &impl Clock) -> bool {
+        clock.now() >= self.0
+    }
+
+    /// Time left until the deadline is reached
+    ///
+    /// Returns zero if deadline has already expired
+    pub fn remaining(&self, clock: &impl Clock) -> Duration {
+        self.0.saturating_duration_since(clock.now())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::{Clock, Deadline, MockClock};
+
+    #[test]
+    fn mock_clock_triggers_timeout_without_sleeping() {
+        let clock = MockClock::new();
+        let started = std::time::Instant::now();
+
+        let deadline = Deadline::after(&clock, Duration::from_secs(60));
+        assert!(!deadline.is_expired(&clock));
+        assert_eq!(deadline.remaining(&clock), Duration::from_secs(60));
+
+        clock.advance(Duration::from_secs(59));
+        assert!(!deadline.is_expired(&clock));
+        assert_eq!(deadline.remaining(&clock), Duration::from_secs(1));
+
+        clock.sleep(Duration::from_secs(2));
+        assert!(deadline.is_expired(&clock));
+        assert_eq!(deadline.remaining(&clock), Duration::ZERO);
+
+        assert!(started.elapsed() < Duration::from_secs(1));
+    }
+
+    #[test]
+    fn clones_share_time() {
+        let clock = MockClock::new();
+        let clone = clock.clone();
+
+        clock.advance(Duration::from_secs(10));
+        assert_eq!(clock.now(), clone.now());
+    }
+}
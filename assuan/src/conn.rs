@@ -0,0 +1,327 @@
+//! Connection wrappers
+
+use std::io;
+
+use zeroize::Zeroize;
+
+/// Connection that can be both read and written
+///
+/// Implemented for every type that implements [`io::Read`] and [`io::Write`]. Exists to make
+/// possible `&mut dyn ReadWrite` trait objects.
+pub trait ReadWrite: io::Read + io::Write {}
+
+impl<T: io::Read + io::Write + ?Sized> ReadWrite for T {}
+
+/// Joins separate reader and writer into a single connection
+pub(crate) struct Conn<R, W> {
+    pub read: R,
+    pub write: W,
+}
+
+impl<R: io::Read, W> io::Read for Conn<R, W> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.read.read(buf)
+    }
+}
+
+impl<R, W: io::Write> io::Write for Conn<R, W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.write.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.write.flush()
+    }
+}
+
+/// Connection that can't be communicated over
+///
+/// Given to command handlers when the client is served asynchronously: handlers are synchronous,
+/// so they can't wait for the client without blocking the executor.
+#[cfg(feature = "async")]
+pub(crate) struct Detached;
+
+#[cfg(feature = "async")]
+impl Detached {
+    fn error() -> io::Error {
+        io::Error::new(
+            io::ErrorKind::Unsupported,
+            "connection isn't available to command handlers when serving asynchronously",
+        )
+    }
+}
+
+#[cfg(feature = "async")]
+impl io::Read for Detached {
+    fn read(&mut self, _buf: &mut [u8]) -> io::Result<usize> {
+        Err(Self::error())
+    }
+}
+
+#[cfg(feature = "async")]
+impl io::Write for Detached {
+    fn write(&mut self, _buf: &[u8]) -> io::Result<usize> {
+        Err(Self::error())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Err(Self::error())
+    }
+}
+
+/// Connection that mirrors all the traffic into a log
+///
+/// Wraps a connection `C`, forwards all reads and writes to it, and copies every line
+/// that passed through the connection into `log`. Lines received from the client are
+/// prefixed with `C: `, lines sent by the server are prefixed with `S: `:
+///
+/// ```text
+/// S: OK how can I serve you?
+/// C: NOP
+/// S: OK success
+/// ```
+///
+/// Tee can be passed to [`AssuanServer::serve_client_conn`](crate::AssuanServer::serve_client_conn)
+/// to debug the server without running it behind `assuan-hijack`.
+///
+/// Note that the log contains the whole traffic including sensitive data like PINs, so it
+/// must never be used in production.
+///
+/// Errors occurred while writing to the log never affect the connection: traffic keeps flowing,
+/// but nothing is logged anymore and the buffered traffic is wiped. The error can be retrieved
+/// via [`Tee::log_error`].
+///
+/// ### Example
+/// ```rust,no_run
+/// use std::os::unix::net::UnixStream;
+/// use assuan::conn::Tee;
+///
+/// # fn serve(server: &mut assuan::AssuanServer<(), impl assuan::router::CmdList<()>>, stream: UnixStream) -> std::io::Result<()> {
+/// let mut conn = Tee::new(stream, std::io::stderr());
+/// server.serve_client_conn(&mut conn)?;
+/// # Ok(())
+/// # }
+/// ```
+pub struct Tee<C, W> {
+    conn: C,
+    log: W,
+    received: Vec<u8>,
+    sent: Vec<u8>,
+    log_error: Option<io::Error>,
+}
+
+impl<C, W: io::Write> Tee<C, W> {
+    /// Wraps a connection, logs the traffic into `log`
+    pub fn new(conn: C, log: W) -> Self {
+        Self {
+            conn,
+            log,
+            received: Vec::new(),
+            sent: Vec::new(),
+            log_error: None,
+        }
+    }
+
+    /// Returns the error that stopped logging, if any
+    pub fn log_error(&self) -> Option<&io::Error> {
+        self.log_error.as_ref()
+    }
+
+    /// Logs complete lines of `data` that passed through the connection
+    ///
+    /// Failure to log is never propagated as the data has already been sent or consumed.
+    /// Once logging fails, it stops for good.
+    fn log_traffic(&mut self, received: bool, data: &[u8]) {
+        if self.log_error.is_some() {
+            return;
+        }
+        let (prefix, buffer) = if received {
+            (b"C: ", &mut self.received)
+        } else {
+            (b"S: ", &mut self.sent)
+        };
+        if let Err(err) = log_lines(&mut self.log, prefix, buffer, data) {
+            self.log_error = Some(err);
+            // Buffers may contain sensitive data that would never be logged
+            self.received.zeroize();
+            self.sent.zeroize();
+        }
+    }
+
+    /// Returns the wrapped connection and the log
+    ///
+    /// Incomplete lines that haven't been logged yet are discarded
+    pub fn into_parts(self) -> (C, W) {
+        (self.conn, self.log)
+    }
+}
+
+/// Appends `data` to the `buffer` and writes all complete lines from the buffer into the `log`
+///
+/// Lines are removed from the buffer once they're attempted to be written, even if writing
+/// fails, so they're never logged twice.
+fn log_lines(
+    log: &mut impl io::Write,
+    prefix: &[u8],
+    buffer: &mut Vec<u8>,
+    data: &[u8],
+) -> io::Result<()> {
+    buffer.extend_from_slice(data);
+
+    let mut logged = 0;
+    let mut result = Ok(());
+    while let Some(pos) = buffer[logged..].iter().position(|x| *x == b'\n') {
+        let line = logged..=logged + pos;
+        logged += pos + 1;
+        result = log
+            .write_all(prefix)
+            .and_then(|()| log.write_all(&buffer[line]));
+        if result.is_err() {
+            break;
+        }
+    }
+    if logged > 0 {
+        buffer.drain(..logged);
+        result = result.and_then(|()| log.flush());
+    }
+    result
+}
+
+impl<C: io::Read, W: io::Write> io::Read for Tee<C, W> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let bytes = self.conn.read(buf)?;
+        self.log_traffic(true, &buf[..bytes]);
+        Ok(bytes)
+    }
+}
+
+impl<C: io::Write, W: io::Write> io::Write for Tee<C, W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let bytes = self.conn.write(buf)?;
+        self.log_traffic(false, &buf[..bytes]);
+        Ok(bytes)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.conn.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Conn, Tee};
+
+    #[test]
+    fn tee_mirrors_session() {
+        let mut output = vec![];
+        let mut log = vec![];
+        let mut conn = Tee::new(
+            Conn {
+                read: "NOP\nBYE\n".as_bytes(),
+                write: &mut output,
+            },
+            &mut log,
+        );
+
+        crate::AssuanServer::new(())
+            .serve_client_conn(&mut conn)
+            .unwrap();
+
+        assert_eq!(
+            std::str::from_utf8(&output).unwrap(),
+            "OK how can I serve you?\nOK success\nOK success\n"
+        );
+        assert_eq!(
+            std::str::from_utf8(&log).unwrap(),
+            "S: OK how can I serve you?\n\
+            C: NOP\n\
+            C: BYE\n\
+            S: OK success\n\
+            S: OK success\n"
+        );
+    }
+
+    #[test]
+    fn log_errors_dont_break_connection() {
+        struct BrokenLog;
+        impl std::io::Write for BrokenLog {
+            fn write(&mut self, _buf: &[u8]) -> std::io::Result<usize> {
+                Err(std::io::Error::other("log is broken"))
+            }
+            fn flush(&mut self) -> std::io::Result<()> {
+                Ok(())
+            }
+        }
+
+        let mut output = vec![];
+        let mut conn = Tee::new(
+            Conn {
+                read: "NOP\nBYE\n".as_bytes(),
+                write: &mut output,
+            },
+            BrokenLog,
+        );
+
+        crate::AssuanServer::new(())
+            .serve_client_conn(&mut conn)
+            .unwrap();
+        assert_eq!(conn.log_error().unwrap().to_string(), "log is broken");
+        drop(conn);
+
+        assert_eq!(
+            std::str::from_utf8(&output).unwrap(),
+            "OK how can I serve you?\nOK success\nOK success\n"
+        );
+    }
+
+    #[test]
+    fn logging_stops_after_error() {
+        /// Log that fails once it's got `capacity` bytes
+        struct SmallLog {
+            written: Vec<u8>,
+            capacity: usize,
+        }
+        impl std::io::Write for SmallLog {
+            fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+                let len = buf.len().min(self.capacity - self.written.len());
+                if len == 0 {
+                    return Err(std::io::Error::other("log is full"));
+                }
+                self.written.extend_from_slice(&buf[..len]);
+                Ok(len)
+            }
+            fn flush(&mut self) -> std::io::Result<()> {
+                Ok(())
+            }
+        }
+
+        let greeting = "S: OK how can I serve you?\n";
+        let mut output = vec![];
+        let mut conn = Tee::new(
+            Conn {
+                read: "NOP\nNOP\nBYE\n".as_bytes(),
+                write: &mut output,
+            },
+            SmallLog {
+                written: vec![],
+                capacity: greeting.len() + 3,
+            },
+        );
+
+        crate::AssuanServer::new(())
+            .serve_client_conn(&mut conn)
+            .unwrap();
+        assert_eq!(conn.log_error().unwrap().to_string(), "log is full");
+        assert!(conn.received.is_empty() && conn.sent.is_empty());
+
+        let (_, log) = conn.into_parts();
+        assert_eq!(
+            std::str::from_utf8(&log.written).unwrap(),
+            format!("{greeting}C: ")
+        );
+        assert_eq!(
+            std::str::from_utf8(&output).unwrap(),
+            "OK how can I serve you?\nOK success\nOK success\nOK success\n"
+        );
+    }
+}
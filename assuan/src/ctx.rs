@@ -0,0 +1,262 @@
+//! Access to the connection from within command handlers
+
+use std::io;
+
+use zeroize::Zeroizing;
+
+use crate::{
+    conn::ReadWrite,
+    inquire,
+    line_reader::LineReader,
+    response::{Inquire, SecretData},
+};
+
+/// Context of the command being processed
+///
+/// Handlers registered via [`AssuanServer::add_command_with_ctx`](crate::AssuanServer::add_command_with_ctx)
+/// receive the context that lets them communicate with the client before producing the final
+/// response.
+pub struct CmdCtx<'a> {
+    line_reader: &'a mut LineReader,
+    conn: &'a mut dyn ReadWrite,
+    fatal_error: Option<inquire::ReplyError>,
+    response_suppressed: bool,
+    max_status_lines: Option<usize>,
+    status_lines_sent: usize,
+}
+
+impl<'a> CmdCtx<'a> {
+    pub(crate) fn new(
+        line_reader: &'a mut LineReader,
+        conn: &'a mut dyn ReadWrite,
+        max_status_lines: Option<usize>,
+    ) -> Self {
+        Self {
+            line_reader,
+            conn,
+            fatal_error: None,
+            response_suppressed: false,
+            max_status_lines,
+            status_lines_sent: 0,
+        }
+    }
+
+    /// Number of status lines the handler attempted to send, including the refused ones
+    pub(crate) fn status_lines_sent(&self) -> usize {
+        self.status_lines_sent
+    }
+
+    /// Returns the connection for exchanging arbitrary data with the client
+    ///
+    /// Reading from the connection first returns the bytes that were already received from
+    /// the client but not processed by the server yet (client may send data without waiting
+    /// for the command response).
+    pub(crate) fn raw_conn(&mut self) -> RawConn<'_> {
+        RawConn {
+            line_reader: self.line_reader,
+            conn: self.conn,
+        }
+    }
+
+    /// Tells the server not to send a response for the command as the handler has already
+    /// communicated with the client on its own
+    pub(crate) fn suppress_response(&mut self) {
+        self.response_suppressed = true;
+    }
+
+    /// Indicates whether [`suppress_response`](Self::suppress_response) was called
+    pub(crate) fn response_suppressed(&self) -> bool {
+        self.response_suppressed
+    }
+
+    /// Takes an error after which the connection can't be served anymore, if any occurred
+    ///
+    /// Such error is reported to the handler as well, but regardless of what the handler
+    /// returns, server sends the error to the client and closes the connection.
+    pub(crate) fn take_fatal_error(&mut self) -> Option<inquire::ReplyError> {
+        self.fatal_error.take()
+    }
+
+    /// Sends `INQUIRE keyword` to the client
+    fn send_inquiry(&mut self, keyword: &str) -> io::Result<()> {
+        let line = Inquire::line(keyword)
+            .map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "keyword is too long"))?;
+        line.write(&mut self.conn)?;
+        self.conn.flush()
+    }
+
+    /// Converts error occurred while reading the reply into IO error
+    ///
+    /// Fatal errors are remembered, so the server closes the connection once handler returns
+    fn reply_error(&mut self, err: inquire::ReplyError) -> io::Error {
+        if err.is_fatal() {
+            let io_err = match &err {
+                inquire::ReplyError::Read(crate::line_reader::ReadLineError::Read(io_err)) => {
+                    io::Error::new(io_err.kind(), err.to_string())
+                }
+                _ => io::Error::new(io::ErrorKind::InvalidData, err.to_string()),
+            };
+            self.fatal_error = Some(err);
+            io_err
+        } else {
+            io::Error::other(err)
+        }
+    }
+
+    /// Sends `S keyword args` status line to the client
+    ///
+    /// Status is written and flushed immediately, i.e. client receives it before the final
+    /// response. `args` are percent-encoded, if needed. Returns
+    /// [`io::ErrorKind::InvalidInput`] error if the line exceeds
+    /// [`MAX_LINE_SIZE`](crate::MAX_LINE_SIZE).
+    ///
+    /// If the server [limits](crate::AssuanServer::with_max_status_lines) number of status
+    /// lines and the limit is reached, the status isn't sent and an error is returned. The
+    /// command fails with [`INTERNAL`](crate::ErrorCode::INTERNAL) error then, regardless of
+    /// what the handler returns.
+    ///
+    /// ### Example
+    /// ```rust
+    /// use assuan::{AssuanServer, CmdCtx, Response};
+    ///
+    /// let server = AssuanServer::new(()).add_command_with_ctx(
+    ///     "LEARN",
+    ///     |_: &mut (), _args: Option<&str>, ctx: &mut CmdCtx| {
+    ///         ctx.status("PROGRESS", "learncard k 0 0")?;
+    ///         // read the card
+    ///         Ok::<_, std::io::Error>(Response::ok())
+    ///     },
+    /// );
+    /// ```
+    pub fn status(&mut self, keyword: &str, args: &str) -> io::Result<()> {
+        let line = crate::response::status_line(keyword, args)
+            .map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "status is too long"))?;
+        self.status_lines_sent += 1;
+        if self
+            .max_status_lines
+            .is_some_and(|max| self.status_lines_sent > max)
+        {
+            return Err(io::Error::other("too many status lines"));
+        }
+        line.write(&mut self.conn)?;
+        self.conn.flush()
+    }
+
+    /// Inquires data from the client
+    ///
+    /// Sends `INQUIRE keyword` to the client and reads its reply. Data is considered to be
+    /// sensitive, so it's returned as [`SecretData`] which is zeroized on drop. Lines received
+    /// from the client are zeroized as soon as they're processed. Reply is limited by
+    /// [`Data::MAX_BYTES`](crate::response::Data::MAX_BYTES).
+    ///
+    /// Errors are handled the same way as in [`inquire_bytes`](Self::inquire_bytes).
+    ///
+    /// ### Example
+    /// ```rust
+    /// use assuan::{AssuanServer, CmdCtx, Response};
+    ///
+    /// let server = AssuanServer::new(()).add_command_with_ctx(
+    ///     "UNLOCK",
+    ///     |_: &mut (), _args: Option<&str>, ctx: &mut CmdCtx| {
+    ///         let passphrase = ctx.inquire("PASSPHRASE")?;
+    ///         // unlock the key
+    ///         # let _ = passphrase;
+    ///         Ok::<_, std::io::Error>(Response::ok())
+    ///     },
+    /// );
+    /// ```
+    pub fn inquire(&mut self, keyword: &str) -> io::Result<SecretData> {
+        self.send_inquiry(keyword)?;
+        inquire::read_reply(self.line_reader, &mut self.conn).map_err(|err| self.reply_error(err))
+    }
+
+    /// Inquires sensitive data of limited size from the client
+    ///
+    /// Same as [`inquire`](Self::inquire), but if client sends more than `capacity` bytes
+    /// (after decoding), error with [`ASS_TOO_MUCH_DATA`](crate::ErrorCode::ASS_TOO_MUCH_DATA)
+    /// code is returned. The reply is accumulated in a fixed-size buffer that's never
+    /// reallocated, so the secret doesn't leave copies in memory. `capacity` can't exceed
+    /// [`Data::MAX_BYTES`](crate::response::Data::MAX_BYTES) as data is stored escaped.
+    ///
+    /// ### Example
+    /// ```rust
+    /// use assuan::{AssuanServer, CmdCtx, Response};
+    ///
+    /// let server = AssuanServer::new(()).add_command_with_ctx(
+    ///     "CHECKPIN",
+    ///     |_: &mut (), _args: Option<&str>, ctx: &mut CmdCtx| {
+    ///         let pin = ctx.inquire_with_capacity("PIN", 8)?;
+    ///         // verify the pin
+    ///         # let _ = pin;
+    ///         Ok::<_, std::io::Error>(Response::ok())
+    ///     },
+    /// );
+    /// ```
+    pub fn inquire_with_capacity(
+        &mut self,
+        keyword: &str,
+        capacity: usize,
+    ) -> io::Result<SecretData> {
+        self.send_inquiry(keyword)?;
+        inquire::read_reply_with_capacity(self.line_reader, &mut self.conn, capacity)
+            .map_err(|err| self.reply_error(err))
+    }
+
+    /// Inquires binary data from the client
+    ///
+    /// Sends `INQUIRE keyword` to the client and reads its reply. Data is decoded as raw bytes,
+    /// i.e. it may contain arbitrary binary content, and it's zeroized on drop. If client sends
+    /// more than `max` bytes, error is returned.
+    ///
+    /// Returned errors have [error code](crate::HasErrorCode) assigned, e.g. if client cancels
+    /// the inquiry, error has [`ASS_CANCELED`](crate::ErrorCode::ASS_CANCELED) code. If client
+    /// replies with `ERR` line, error has the code sent by the client. Handler may simply
+    /// propagate them. If reading from the client fails, the connection is closed once
+    /// the handler returns, the same way as if the failure occurred outside of the handler.
+    ///
+    /// ### Example
+    /// ```rust
+    /// use assuan::{AssuanServer, CmdCtx, Response};
+    ///
+    /// let server = AssuanServer::new(()).add_command_with_ctx(
+    ///     "PKDECRYPT",
+    ///     |_: &mut (), _args: Option<&str>, ctx: &mut CmdCtx| {
+    ///         let ciphertext = ctx.inquire_bytes("CIPHERTEXT", 4096)?;
+    ///         // decrypt the ciphertext
+    ///         # let _ = ciphertext;
+    ///         Ok::<_, std::io::Error>(Response::ok())
+    ///     },
+    /// );
+    /// ```
+    pub fn inquire_bytes(&mut self, keyword: &str, max: usize) -> io::Result<Zeroizing<Vec<u8>>> {
+        self.send_inquiry(keyword)?;
+        inquire::read_reply_bytes(self.line_reader, &mut self.conn, max)
+            .map_err(|err| self.reply_error(err))
+    }
+}
+
+/// Connection given to handlers registered via
+/// [`AssuanServer::add_command_interactive`](crate::AssuanServer::add_command_interactive)
+pub(crate) struct RawConn<'a> {
+    line_reader: &'a mut LineReader,
+    conn: &'a mut dyn ReadWrite,
+}
+
+impl io::Read for RawConn<'_> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match self.line_reader.take_buffered(buf) {
+            0 => self.conn.read(buf),
+            taken => Ok(taken),
+        }
+    }
+}
+
+impl io::Write for RawConn<'_> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.conn.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.conn.flush()
+    }
+}
@@ -1,3 +1,5 @@
+//! Error codes and their sources defined by GPG library
+
 use core::fmt;
 
 /// Error that has an error code assigned to it
@@ -12,6 +14,20 @@ impl HasErrorCode for core::convert::Infallible {
     }
 }
 
+/// IO errors produced by [`CmdCtx`](crate::CmdCtx) retain the error code of the underlying
+/// protocol error. Other IO errors have [`ASS_GENERAL`](ErrorCode::ASS_GENERAL) code.
+impl HasErrorCode for std::io::Error {
+    fn code(&self) -> ErrorCode {
+        match self
+            .get_ref()
+            .and_then(|err| err.downcast_ref::<crate::inquire::ReplyError>())
+        {
+            Some(err) => err.code(),
+            None => ErrorCode::ASS_GENERAL,
+        }
+    }
+}
+
 /// Wraps an error and assigns an error code to it
 pub struct WithErrorCode<E> {
     /// Error code assigned to the error
@@ -36,10 +52,73 @@ macro_rules! define_error_code {
     ($($code:literal $name:ident $comment_line:expr),+$(,)*) => {
         /// Error code defined by GPG library
         ///
+        /// All codes defined by libgpg-error are available as named constants, e.g.
+        /// [`ErrorCode::BAD_PASSPHRASE`]. Any other value can be used via
+        /// [`ErrorCode::from_raw`].
+        ///
         /// List of error codes was taken from here: <https://github.com/gpg/libgpg-error/blob/4a9def77488f2631f71737357d9e9dd874c9b302/src/err-codes.h.in>
-        #[derive(Debug, Clone, Copy)]
+        #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
         pub struct ErrorCode(pub u32);
 
+        impl ErrorCode {
+            /// Constructs an error code from its numeric value
+            ///
+            /// Useful for returning codes that don't have a named constant, or when the code is
+            /// received from elsewhere and needs to be passed to the client verbatim.
+            ///
+            /// ```rust
+            /// use assuan::ErrorCode;
+            ///
+            /// let code = ErrorCode::from_raw(11);
+            /// assert_eq!(code, ErrorCode::BAD_PASSPHRASE);
+            /// assert_eq!(code.raw(), 11);
+            /// ```
+            pub const fn from_raw(code: u32) -> Self {
+                Self(code)
+            }
+
+            /// Returns numeric value of the error code
+            pub const fn raw(self) -> u32 {
+                self.0
+            }
+
+            /// Attributes the error to the given source component
+            ///
+            /// libgpg-error packs the component the error originates from into the high bits of
+            /// the error value, so the value sent to the client is `source << 24 | code`. Source
+            /// previously assigned to the code is replaced.
+            ///
+            /// ```rust
+            /// use assuan::{error_code::Source, ErrorCode};
+            ///
+            /// let code = ErrorCode::CANCELED.with_source(Source::PINENTRY);
+            /// assert_eq!(code.raw(), 83886179);
+            /// assert_eq!(code.source(), Source::PINENTRY);
+            /// assert_eq!(code.without_source(), ErrorCode::CANCELED);
+            /// ```
+            pub const fn with_source(self, source: Source) -> Self {
+                Self(
+                    (source.0 & Source::MASK) << Source::SHIFT
+                        | self.without_source().0,
+                )
+            }
+
+            /// Returns the source component the error is attributed to
+            ///
+            /// [`Source::UNKNOWN`] if no source was assigned.
+            pub const fn source(self) -> Source {
+                Source((self.0 >> Source::SHIFT) & Source::MASK)
+            }
+
+            /// Returns the error code without the source component
+            pub const fn without_source(self) -> Self {
+                Self(self.0 & Self::CODE_MASK)
+            }
+
+            /// Bits of the error value occupied by the code itself
+            const CODE_MASK: u32 = 0xFFFF;
+        }
+
         impl ErrorCode {$(
             #[doc = $comment_line]
             pub const $name: Self = Self($code);
@@ -47,6 +126,54 @@ macro_rules! define_error_code {
     };
 }
 
+/// Component the error originates from, defined by GPG library
+///
+/// Assigned to [`ErrorCode`] via [`ErrorCode::with_source`]. List of sources was taken from here:
+/// <https://github.com/gpg/libgpg-error/blob/4a9def77488f2631f71737357d9e9dd874c9b302/src/err-sources.h.in>
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Source(pub u32);
+
+impl Source {
+    const SHIFT: u32 = 24;
+    const MASK: u32 = 0x7F;
+}
+
+macro_rules! define_source {
+    ($($code:literal $name:ident $comment_line:expr),+$(,)*) => {
+        impl Source {$(
+            #[doc = $comment_line]
+            pub const $name: Self = Self($code);
+        )+}
+    };
+}
+
+define_source! {
+    0	UNKNOWN		"Unspecified source",
+    1	GCRYPT		"gcrypt",
+    2	GPG		"GnuPG",
+    3	GPGSM		"GpgSM",
+    4	GPGAGENT	"GPG Agent",
+    5	PINENTRY	"Pinentry",
+    6	SCD		"SCD",
+    7	GPGME		"GPGME",
+    8	KEYBOX		"Keybox",
+    9	KSBA		"KSBA",
+    10	DIRMNGR		"Dirmngr",
+    11	GSTI		"GSTI",
+    12	GPA		"GPA",
+    13	KLEO		"Kleopatra",
+    14	G13		"G13",
+    15	ASSUAN		"Assuan",
+    16	TPM2D		"TPM2d",
+    17	TLS		"TLS",
+    18	TKD		"TKD",
+    31	ANY		"Any source",
+    32	USER_1		"User defined source 1",
+    33	USER_2		"User defined source 2",
+    34	USER_3		"User defined source 3",
+    35	USER_4		"User defined source 4",
+}
+
 define_error_code! {
     0	NO_ERROR		"Success",
     1	GENERAL			"General error",
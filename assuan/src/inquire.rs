@@ -0,0 +1,366 @@
+//! Reads client's reply to the server inquiry
+
+use std::{fmt, io};
+
+use zeroize::Zeroizing;
+
+use crate::{
+    line_reader::LineReader,
+    percent_decode::{percent_decode, percent_decode_bytes, MalformedEncoding},
+    response::SecretData,
+    ErrorCode, HasErrorCode,
+};
+
+/// Reads a reply to [inquiry](crate::response::Inquire) from the client
+///
+/// Accumulates data sent via `D` lines until client sends `END`. Empty lines and comments
+/// are ignored. Received data lines are zeroized right after they're processed.
+pub fn read_reply(
+    line_reader: &mut LineReader,
+    conn: &mut impl io::Read,
+) -> Result<SecretData, ReplyError> {
+    read_reply_with_capacity(line_reader, conn, usize::MAX)
+}
+
+/// Reads a reply to inquiry limited by `capacity` bytes of decoded data
+///
+/// Same as [`read_reply`], but if the client sends more than `capacity` bytes, the reply is
+/// rejected with [`ReplyError::TooMuchData`]. Data is accumulated in [`SecretData`] that is
+/// never reallocated, so no copies of the secret are left in memory.
+pub fn read_reply_with_capacity(
+    line_reader: &mut LineReader,
+    conn: &mut impl io::Read,
+    capacity: usize,
+) -> Result<SecretData, ReplyError> {
+    let mut data = SecretData::default();
+    let mut remaining = capacity;
+    read_data_lines(line_reader, conn, |escaped| {
+        append_reply(&mut data, &mut remaining, escaped)
+    })?;
+    Ok(data)
+}
+
+/// Reads a reply to inquiry from the async `conn`
+///
+/// Same as [`read_reply`], but awaits on the connection instead of blocking
+#[cfg(feature = "async")]
+pub async fn read_reply_async(
+    line_reader: &mut LineReader,
+    conn: &mut (impl tokio::io::AsyncRead + Unpin),
+) -> Result<SecretData, ReplyError> {
+    let mut data = SecretData::default();
+    let mut remaining = usize::MAX;
+    let mut reply = Reply::default();
+    loop {
+        let line = line_reader
+            .read_line_async(conn)
+            .await
+            .map_err(ReplyError::Read)?
+            .ok_or_else(|| ReplyError::Read(io::ErrorKind::UnexpectedEof.into()))?;
+        let result = reply.process_line(line, |escaped| {
+            append_reply(&mut data, &mut remaining, escaped)
+        });
+        line_reader.zeroize_line();
+        if let Some(result) = result {
+            return result.map(|()| data);
+        }
+    }
+}
+
+/// Decodes a data line and appends it to the reply
+///
+/// `remaining` is the number of decoded bytes the reply may still take
+fn append_reply(
+    data: &mut SecretData,
+    remaining: &mut usize,
+    escaped: &[u8],
+) -> Result<(), ReplyError> {
+    let escaped = std::str::from_utf8(escaped).map_err(ReplyError::MalformedUtf8)?;
+    percent_decode(escaped).try_for_each(|x| {
+        let x = x.map_err(ReplyError::MalformedPercentEncoding)?;
+        *remaining = remaining
+            .checked_sub(x.len_utf8())
+            .ok_or(ReplyError::TooMuchData)?;
+        data.push(x).map_err(|_| ReplyError::TooMuchData)
+    })
+}
+
+/// Reads a reply to inquiry as raw bytes
+///
+/// Same as [`read_reply`], but data isn't required to be a valid UTF-8 string. Data is limited
+/// by `max` bytes.
+pub fn read_reply_bytes(
+    line_reader: &mut LineReader,
+    conn: &mut impl io::Read,
+    max: usize,
+) -> Result<Zeroizing<Vec<u8>>, ReplyError> {
+    let mut data = Zeroizing::new(Vec::new());
+    read_data_lines(line_reader, conn, |escaped| {
+        percent_decode_bytes(escaped).try_for_each(|x| {
+            let x = x.map_err(ReplyError::MalformedPercentEncoding)?;
+            if data.len() >= max {
+                return Err(ReplyError::TooMuchData);
+            }
+            if data.len() == data.capacity() {
+                // Grow the buffer manually: reallocation by `Vec` itself would leave a copy of
+                // the data in memory that's never zeroized
+                let mut grown =
+                    Zeroizing::new(Vec::with_capacity((data.len() * 2).max(64).min(max)));
+                grown.extend_from_slice(&data);
+                data = grown;
+            }
+            data.push(x);
+            Ok(())
+        })
+    })?;
+    Ok(data)
+}
+
+/// Reads `D` lines until `END` and feeds their escaped content to `on_data`
+///
+/// If reply turns out to be malformed (`on_data` returns error or client sends unexpected
+/// line), the rest of the reply is still consumed up to `END` or `CAN`, so the following
+/// client's requests aren't mistaken for the reply and vice versa. The first error is
+/// returned in that case.
+fn read_data_lines(
+    line_reader: &mut LineReader,
+    conn: &mut impl io::Read,
+    mut on_data: impl FnMut(&[u8]) -> Result<(), ReplyError>,
+) -> Result<(), ReplyError> {
+    let mut reply = Reply::default();
+    loop {
+        let line = line_reader
+            .read_line(conn)
+            .map_err(ReplyError::Read)?
+            .ok_or_else(|| ReplyError::Read(io::ErrorKind::UnexpectedEof.into()))?;
+        let result = reply.process_line(line, &mut on_data);
+        line_reader.zeroize_line();
+        if let Some(result) = result {
+            return result;
+        }
+    }
+}
+
+/// State of the reply being received
+#[derive(Default)]
+struct Reply {
+    /// The first error occurred while processing the reply
+    error: Option<ReplyError>,
+}
+
+impl Reply {
+    /// Processes a line received from the client
+    ///
+    /// Returns `Some(_)` once the reply is complete
+    fn process_line(
+        &mut self,
+        line: &[u8],
+        on_data: impl FnOnce(&[u8]) -> Result<(), ReplyError>,
+    ) -> Option<Result<(), ReplyError>> {
+        if line.starts_with(b"#") || line.is_empty() {
+            return None;
+        }
+
+        let (cmd, args) = match line.iter().position(|x| *x == b' ') {
+            Some(pos) => (&line[..pos], &line[pos + 1..]),
+            None => (line, &[][..]),
+        };
+        match cmd {
+            b"END" => return Some(self.error.take().map_or(Ok(()), Err)),
+            b"CAN" => return Some(Err(self.error.take().unwrap_or(ReplyError::Canceled))),
+            b"ERR" => {
+                let rejected = ReplyError::rejected(args);
+                return Some(Err(self.error.take().unwrap_or(rejected)));
+            }
+            b"D" => {
+                if self.error.is_none() {
+                    self.error = on_data(args).err();
+                }
+            }
+            _ => {
+                self.error.get_or_insert(ReplyError::UnexpectedCmd);
+            }
+        }
+        None
+    }
+}
+
+/// Explains why reading the reply failed
+#[derive(Debug)]
+pub enum ReplyError {
+    /// Client canceled the inquiry
+    Canceled,
+    /// Client replied with `ERR` line
+    Rejected {
+        /// Error code sent by the client
+        code: ErrorCode,
+        /// Error description sent by the client
+        description: String,
+    },
+    /// Client sent something else than `D`, `END` or `CAN`
+    UnexpectedCmd,
+    /// Client sent more data than fits into [`SecretData`]
+    TooMuchData,
+    /// Client sent data line with malformed percent encoding
+    MalformedPercentEncoding(MalformedEncoding),
+    /// Client sent line that's not a valid UTF-8 string
+    MalformedUtf8(std::str::Utf8Error),
+    /// Couldn't read a line
+    Read(crate::line_reader::ReadLineError),
+}
+
+impl ReplyError {
+    /// Parses arguments of `ERR` line sent by the client: error code followed by optional
+    /// description
+    ///
+    /// If error code is missing or malformed, [`ASS_GENERAL`](ErrorCode::ASS_GENERAL) is used
+    fn rejected(args: &[u8]) -> Self {
+        let args = String::from_utf8_lossy(args);
+        let (code, description) = args.split_once(' ').unwrap_or((&args, ""));
+        Self::Rejected {
+            code: code
+                .parse()
+                .map(ErrorCode)
+                .unwrap_or(ErrorCode::ASS_GENERAL),
+            description: description.to_owned(),
+        }
+    }
+
+    /// Indicates whether the connection can't be served after the error
+    pub fn is_fatal(&self) -> bool {
+        matches!(self, Self::Read(_) | Self::MalformedUtf8(_))
+    }
+}
+
+impl fmt::Display for ReplyError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Canceled => f.write_str("inquiry canceled by client"),
+            Self::Rejected { description, .. } => {
+                write!(f, "client failed to reply to inquiry: {description}")
+            }
+            Self::UnexpectedCmd => f.write_str("unexpected command in reply to inquiry"),
+            Self::TooMuchData => f.write_str("too much data"),
+            Self::MalformedPercentEncoding(err) => err.fmt(f),
+            Self::MalformedUtf8(err) => err.fmt(f),
+            Self::Read(crate::line_reader::ReadLineError::Read(err)) => err.fmt(f),
+            Self::Read(crate::line_reader::ReadLineError::LineTooLong) => {
+                f.write_str("line is too long")
+            }
+        }
+    }
+}
+
+impl HasErrorCode for ReplyError {
+    fn code(&self) -> ErrorCode {
+        match self {
+            Self::Canceled => ErrorCode::ASS_CANCELED,
+            Self::Rejected { code, .. } => *code,
+            Self::UnexpectedCmd => ErrorCode::ASS_UNEXPECTED_CMD,
+            Self::TooMuchData => ErrorCode::ASS_TOO_MUCH_DATA,
+            Self::MalformedPercentEncoding(err) => err.code(),
+            Self::MalformedUtf8(_) => ErrorCode::ASS_INV_VALUE,
+            Self::Read(crate::line_reader::ReadLineError::Read(_)) => ErrorCode::ASS_READ_ERROR,
+            Self::Read(crate::line_reader::ReadLineError::LineTooLong) => {
+                ErrorCode::ASS_LINE_TOO_LONG
+            }
+        }
+    }
+}
+
+impl std::error::Error for ReplyError {}
+
+#[cfg(test)]
+mod tests {
+    use zeroize::Zeroize;
+
+    use super::{read_reply, read_reply_bytes, read_reply_with_capacity, ReplyError};
+    use crate::{line_reader::LineReader, ErrorCode, HasErrorCode};
+
+    #[test]
+    fn reads_secret_reply() {
+        let mut line_reader = LineReader::new();
+        let mut conn = "D correct%25horse\n# comment\nD  battery\nEND\n".as_bytes();
+
+        let mut data = read_reply(&mut line_reader, &mut conn).unwrap();
+        assert!(data.chars().eq("correct%horse battery".chars()));
+
+        // That's what happens on drop
+        data.zeroize();
+        assert_eq!(data.size(), 0);
+        assert_eq!(data.chars().next(), None);
+    }
+
+    #[test]
+    fn reads_replies_ending_early() {
+        let mut line_reader = LineReader::new();
+        let mut conn = "END\nCAN\nERR 99 no key\nD %GG\nERR 99 no key\nERR\n".as_bytes();
+
+        let data = read_reply(&mut line_reader, &mut conn).unwrap();
+        assert_eq!(data.size(), 0);
+
+        let err = read_reply(&mut line_reader, &mut conn).err().unwrap();
+        assert!(matches!(err, ReplyError::Canceled), "{err:?}");
+
+        let err = read_reply(&mut line_reader, &mut conn).err().unwrap();
+        assert_eq!(err.code().0, 99);
+        assert_eq!(err.to_string(), "client failed to reply to inquiry: no key");
+
+        // The first error takes precedence
+        let err = read_reply(&mut line_reader, &mut conn).err().unwrap();
+        assert!(
+            matches!(err, ReplyError::MalformedPercentEncoding(_)),
+            "{err:?}"
+        );
+
+        let err = read_reply(&mut line_reader, &mut conn).err().unwrap();
+        assert_eq!(err.code().0, ErrorCode::ASS_GENERAL.0);
+    }
+
+    #[test]
+    fn rejects_too_much_data() {
+        let mut line_reader = LineReader::new();
+        let line = format!("D {}\n", "a".repeat(900));
+        let input = format!("{line}{line}END\n");
+        let mut conn = input.as_bytes();
+
+        let err = read_reply(&mut line_reader, &mut conn).err().unwrap();
+        assert!(matches!(err, ReplyError::TooMuchData), "{err:?}");
+    }
+
+    #[test]
+    fn reply_is_limited_by_capacity() {
+        let mut line_reader = LineReader::new();
+        let mut conn = "D 12%0A\nD \u{e9}\nEND\nD 12345\nD 6\nEND\nD \u{e9}\nEND\nNOP\n".as_bytes();
+
+        let data = read_reply_with_capacity(&mut line_reader, &mut conn, 5).unwrap();
+        assert!(data.chars().eq("12\n\u{e9}".chars()));
+
+        // The rest of the reply is consumed anyway
+        let err = read_reply_with_capacity(&mut line_reader, &mut conn, 5)
+            .err()
+            .unwrap();
+        assert!(matches!(err, ReplyError::TooMuchData), "{err:?}");
+
+        // Multibyte character doesn't fit
+        let err = read_reply_with_capacity(&mut line_reader, &mut conn, 1)
+            .err()
+            .unwrap();
+        assert!(matches!(err, ReplyError::TooMuchData), "{err:?}");
+
+        assert_eq!(line_reader.read_line(&mut conn).unwrap(), Some(&b"NOP"[..]));
+    }
+
+    #[test]
+    fn reads_binary_reply() {
+        let mut line_reader = LineReader::new();
+        let mut conn = "D %00%FF\nD  a%0A\nEND\n".as_bytes();
+
+        let data = read_reply_bytes(&mut line_reader, &mut conn, 5).unwrap();
+        assert_eq!(*data, b"\x00\xFF a\n");
+
+        let mut conn = "D %00%FF\nD  a%0A\nEND\n".as_bytes();
+        let err = read_reply_bytes(&mut line_reader, &mut conn, 4).unwrap_err();
+        assert!(matches!(err, ReplyError::TooMuchData), "{err:?}");
+    }
+}
@@ -23,7 +23,7 @@
 //! * Percent-encoding and decoding certain characters of requests and responses
 //! * Enforcing limitations set by the assuan spec, such as the [max line size](MAX_LINE_SIZE)
 //! * Understanding which command is being called by the client and invoking appropriate method
-//! * Zeroizing responses in memory that contain sensitive data
+//! * Zeroizing responses and received requests in memory that contain sensitive data
 //! * Handling common assuan commands such as `BYE` and `NOP`
 //!
 //! ### Minimal example
@@ -52,22 +52,30 @@
 #![deny(missing_docs)]
 
 use core::fmt;
-use std::io;
+use std::{io, time::Duration};
 
 use response::ResponseLine;
 
 use self::line_reader::LineReader;
 
 pub use self::{
+    ctx::CmdCtx,
     error_code::{ErrorCode, HasErrorCode, WithErrorCode},
     response::Response,
 };
 
-mod error_code;
+pub mod clock;
+pub mod conn;
+pub mod ctx;
+pub mod error_code;
+mod inquire;
 mod line_reader;
-mod percent_decode;
+pub mod percent_decode;
 pub mod response;
 pub mod router;
+mod strict;
+#[cfg(unix)]
+pub mod unix_socket;
 
 /// Maximum size of a line following the assuan specs
 pub const MAX_LINE_SIZE: usize = 1000;
@@ -78,10 +86,99 @@ pub const MAX_LINE_SIZE: usize = 1000;
 /// [`AssuanServer::serve_client`], it routes incoming requests between commands registered
 /// via [`AssuanServer::add_command`]. Out-of-box, it recognizes some
 /// [predefined commands](router::PredefinedCmds) like `BYE` (can be disabled by using
-/// [`AssuanServer::without_predefined_cmds`]).
+/// [`AssuanServer::without_predefined_cmds`] or replaced via [`AssuanServer::with_base`]).
 pub struct AssuanServer<S, L> {
     service: S,
     cmd_handlers: L,
+    config: Config,
+    /// Set once a response [shutting down the server](response::Ok::shutdown_server) is sent
+    shutdown_requested: bool,
+}
+
+/// Tells how serving a client ended
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum ServeOutcome {
+    /// Session is over: client ended it (e.g. by sending `BYE`) or closed the connection
+    /// between requests
+    Finished,
+    /// Client closed the connection while the response was being written to it, i.e. writing
+    /// failed with [`BrokenPipe`](io::ErrorKind::BrokenPipe) or
+    /// [`ConnectionReset`](io::ErrorKind::ConnectionReset)
+    Disconnected,
+}
+
+/// Server settings that don't depend on the service type
+struct Config {
+    clock: Box<dyn clock::Clock + Send>,
+    max_connection_duration: Option<Duration>,
+    read_timeout: Option<Duration>,
+    strict: bool,
+    info: Vec<(&'static str, String)>,
+    max_consecutive_ignored_lines: Option<usize>,
+    max_line_size: usize,
+    max_line_sizes: Vec<(&'static str, usize)>,
+    greeting: ResponseLine,
+    max_status_lines: Option<usize>,
+    require_greeting_sent: bool,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            clock: Box::new(clock::SystemClock),
+            max_connection_duration: None,
+            read_timeout: None,
+            strict: false,
+            info: Vec::new(),
+            max_consecutive_ignored_lines: None,
+            max_line_size: MAX_LINE_SIZE,
+            max_line_sizes: Vec::new(),
+            greeting: ResponseLine::new()
+                .chain(DEFAULT_GREETING)
+                .expect("greeting is much smaller than the limit"),
+            max_status_lines: None,
+            require_greeting_sent: false,
+        }
+    }
+}
+
+impl Config {
+    /// Fails the command if it sent more status lines than allowed
+    ///
+    /// `sent` is number of status lines the handler sent via [`CmdCtx::status`]
+    fn limit_status_lines(&self, result: CmdResult, sent: usize) -> CmdResult {
+        let count = match &result {
+            Ok(resp) => sent + resp.status_count(),
+            Err(_) => sent,
+        };
+        match self.max_status_lines {
+            Some(max) if count > max => {
+                Err((ErrorCode::INTERNAL, "Too many status lines".to_string()))
+            }
+            _ => result,
+        }
+    }
+
+    /// Answers `GETINFO` request with [static info](AssuanServer::set_info)
+    fn get_info(&self, key: Option<&str>) -> Result<Response, (ErrorCode, String)> {
+        let key = percent_decode::percent_decode(key.unwrap_or_default())
+            .collect::<Result<String, _>>()
+            .map_err(|err| (err.code(), err.to_string()))?;
+        match self.info.iter().find(|(k, _)| *k == key) {
+            Some((_, value)) => Ok(Response::data_stream(value)),
+            None if key == "pid" => Ok(Response::data_stream(&std::process::id().to_string())),
+            None => Err((ErrorCode::ASS_PARAMETER, "unknown info key".to_string())),
+        }
+    }
+
+    /// Returns max size of request line of the command
+    fn max_line_size(&self, cmd: &[u8]) -> usize {
+        self.max_line_sizes
+            .iter()
+            .find(|(name, _)| name.as_bytes().eq_ignore_ascii_case(cmd))
+            .map_or(self.max_line_size, |(_, max)| *max)
+    }
 }
 
 impl<S> AssuanServer<S, router::PredefinedCmds> {
@@ -92,23 +189,62 @@ impl<S> AssuanServer<S, router::PredefinedCmds> {
     ///
     /// Commands can be registered via [.add_command](AssuanServer::add_command) method.
     pub fn new(service: S) -> Self {
-        Self {
-            service,
-            cmd_handlers: router::PredefinedCmds::new(),
-        }
+        Self::with_base(service, router::PredefinedCmds::new())
     }
 }
 
 impl<S> AssuanServer<S, router::Nil> {
     /// Constructs a new assuan server without any [predefined commands](router::PredefinedCmds)
     pub fn without_predefined_cmds(service: S) -> Self {
+        Self::with_base(service, router::Nil)
+    }
+}
+
+impl<S, L: router::CmdList<S>> AssuanServer<S, L> {
+    /// Constructs a new assuan server that starts with `base` list of commands instead of
+    /// [predefined ones](router::PredefinedCmds)
+    ///
+    /// Useful for bundling commands that every server of an application supports. Custom list
+    /// can keep the predefined commands by being a [tail](router::PredefinedCmds::with_tail) of
+    /// them. Commands registered via [.add_command](AssuanServer::add_command) take precedence
+    /// over the base ones.
+    pub fn with_base(service: S, base: L) -> Self {
         Self {
             service,
-            cmd_handlers: router::Nil,
+            cmd_handlers: base,
+            config: Config::default(),
+            shutdown_requested: false,
         }
     }
 }
 
+impl<S, L> AssuanServer<S, L> {
+    /// Returns the service
+    ///
+    /// Useful for inspecting the service state after the client is served, e.g. in tests
+    pub fn service(&self) -> &S {
+        &self.service
+    }
+
+    /// Returns mutable reference to the service
+    pub fn service_mut(&mut self) -> &mut S {
+        &mut self.service
+    }
+
+    /// Destroys the server, returns the service
+    pub fn into_service(self) -> S {
+        self.service
+    }
+
+    /// Indicates whether a command handler [shut down the server](response::Ok::shutdown_server)
+    ///
+    /// Once it's set, the server stops serving: the current session is closed and no further
+    /// sessions are started.
+    pub fn is_shutdown_requested(&self) -> bool {
+        self.shutdown_requested
+    }
+}
+
 impl<S, L: router::CmdList<S>> AssuanServer<S, L> {
     /// Registers a new command
     ///
@@ -122,171 +258,2326 @@ impl<S, L: router::CmdList<S>> AssuanServer<S, L> {
     where
         E: fmt::Display + HasErrorCode,
     {
+        self.add_handler(cmd_name, router::StrHandler(handler))
+    }
+
+    /// Registers a new command which handler has access to the [command context](CmdCtx)
+    ///
+    /// Context lets the handler communicate with the client while the command is being
+    /// processed, e.g. [inquire binary data](CmdCtx::inquire_bytes).
+    pub fn add_command_with_ctx<E>(
+        self,
+        cmd_name: &'static str,
+        handler: impl FnMut(&mut S, Option<&str>, &mut CmdCtx<'_>) -> Result<Response, E>,
+    ) -> AssuanServer<S, impl router::CmdList<S>>
+    where
+        E: fmt::Display + HasErrorCode,
+    {
+        self.add_handler(cmd_name, router::CtxHandler(handler))
+    }
+
+    /// Registers a new command which handler exchanges data with the client on its own
+    ///
+    /// Handler has full read/write access to the connection for the duration of the command,
+    /// so it can implement arbitrary exchanges, e.g. bulk transfers or custom sub-protocols.
+    /// Reading from the connection returns data the client sent right after the command, even
+    /// if the server has already received it.
+    ///
+    /// If handler returns `Ok(None)`, server doesn't send any response: it's assumed that the
+    /// handler has completed the exchange. Otherwise, the returned response or error is sent
+    /// to the client as usual.
+    ///
+    /// ### Example
+    /// ```rust
+    /// use std::io::Write;
+    /// use assuan::{AssuanServer, Response};
+    ///
+    /// let server = AssuanServer::new(()).add_command_interactive(
+    ///     "HELLO",
+    ///     |_: &mut (), _args: Option<&str>, conn: &mut dyn assuan::conn::ReadWrite| {
+    ///         conn.write_all(b"# custom exchange\nOK hello\n")?;
+    ///         Ok::<Option<Response>, std::io::Error>(None)
+    ///     },
+    /// );
+    /// ```
+    pub fn add_command_interactive<E>(
+        self,
+        cmd_name: &'static str,
+        handler: impl FnMut(
+            &mut S,
+            Option<&str>,
+            &mut dyn conn::ReadWrite,
+        ) -> Result<Option<Response>, E>,
+    ) -> AssuanServer<S, impl router::CmdList<S>>
+    where
+        E: fmt::Display + HasErrorCode,
+    {
+        self.add_handler(cmd_name, router::InteractiveHandler(handler))
+    }
+
+    /// Registers a handler called when client sends `RESET`
+    ///
+    /// Clients send `RESET` between operations to clear the state configured for the previous
+    /// operation. `RESET` is a [predefined command](router::PredefinedCmds) that doesn't do
+    /// anything on its own, so the service should use this hook to clear its per-request state.
+    /// Server responds with `OK` once the handler returns.
+    ///
+    /// ### Example
+    /// ```rust
+    /// use assuan::AssuanServer;
+    ///
+    /// let server = AssuanServer::new(None::<String>)
+    ///     .add_reset_handler(|desc: &mut Option<String>| *desc = None);
+    /// ```
+    pub fn add_reset_handler(
+        self,
+        mut handler: impl FnMut(&mut S),
+    ) -> AssuanServer<S, impl router::CmdList<S>> {
+        self.add_command("RESET", move |service: &mut S, _args: Option<&str>| {
+            handler(service);
+            Ok::<_, std::convert::Infallible>(Response::ok())
+        })
+    }
+
+    /// Registers a new command which handler takes arguments as bytes
+    ///
+    /// Similar to [`add_command`](Self::add_command), but percent-encoded arguments are decoded
+    /// into raw bytes instead of a string, so it's suitable for commands carrying binary data.
+    /// Decoded arguments are zeroized once the handler returns.
+    ///
+    /// ### Example
+    /// ```rust
+    /// use assuan::{AssuanServer, Response};
+    ///
+    /// let server = AssuanServer::new(Vec::new()).add_command_bytes(
+    ///     "SETNONCE",
+    ///     |nonce: &mut Vec<u8>, args: Option<&[u8]>| {
+    ///         *nonce = args.unwrap_or_default().to_vec();
+    ///         Ok::<_, std::convert::Infallible>(Response::ok())
+    ///     },
+    /// );
+    /// ```
+    pub fn add_command_bytes<E>(
+        self,
+        cmd_name: &'static str,
+        handler: impl FnMut(&mut S, Option<&[u8]>) -> Result<Response, E>,
+    ) -> AssuanServer<S, impl router::CmdList<S>>
+    where
+        E: fmt::Display + HasErrorCode,
+    {
+        self.add_handler(cmd_name, router::BytesHandler(handler))
+    }
+
+    /// Registers a new command which handler takes arguments undecoded
+    ///
+    /// Similar to [`add_command`](Self::add_command), but arguments are passed as they were
+    /// sent by the client, i.e. percent-encoded. Handler may decode them selectively, e.g.
+    /// if some of the arguments are percent-encoded binary values.
+    ///
+    /// ### Example
+    /// ```rust
+    /// use assuan::{AssuanServer, Response};
+    ///
+    /// let server = AssuanServer::new(String::new()).add_command_raw(
+    ///     "SETKEYINFO",
+    ///     |keyinfo: &mut String, args: Option<&str>| {
+    ///         // `%` in the arguments is still escaped as `%25`
+    ///         *keyinfo = args.unwrap_or_default().to_owned();
+    ///         Ok::<_, std::convert::Infallible>(Response::ok())
+    ///     },
+    /// );
+    /// ```
+    pub fn add_command_raw<E>(
+        self,
+        cmd_name: &'static str,
+        handler: impl FnMut(&mut S, Option<&str>) -> Result<Response, E>,
+    ) -> AssuanServer<S, impl router::CmdList<S>>
+    where
+        E: fmt::Display + HasErrorCode,
+    {
+        self.add_handler(cmd_name, router::RawHandler(handler))
+    }
+
+    /// Registers a handler of namespaced commands, e.g. `SCD SERIALNO` and `SCD GETINFO version`
+    /// handled by `SCD` prefix handler
+    ///
+    /// Handler receives the rest of the request line after the `prefix` (e.g. `SERIALNO`), or
+    /// empty string if there's nothing after the prefix. The rest of the line is passed as it
+    /// was sent by the client, i.e. percent-encoded, so it can be forwarded as is.
+    ///
+    /// ### Example
+    /// ```rust
+    /// use assuan::{AssuanServer, Response};
+    ///
+    /// struct Agent {
+    ///     scd_requests: Vec<String>,
+    /// }
+    ///
+    /// let server = AssuanServer::new(Agent { scd_requests: vec![] }).add_command_prefix(
+    ///     "SCD",
+    ///     |agent: &mut Agent, request: &str| {
+    ///         // Forward the request to the smartcard daemon
+    ///         agent.scd_requests.push(request.to_owned());
+    ///         Ok::<_, std::convert::Infallible>(Response::ok())
+    ///     },
+    /// );
+    /// ```
+    pub fn add_command_prefix<E>(
+        self,
+        prefix: &'static str,
+        mut handler: impl FnMut(&mut S, &str) -> Result<Response, E>,
+    ) -> AssuanServer<S, impl router::CmdList<S>>
+    where
+        E: fmt::Display + HasErrorCode,
+    {
+        self.add_command_raw(prefix, move |service: &mut S, rest: Option<&str>| {
+            handler(service, rest.unwrap_or_default())
+        })
+    }
+
+    /// Registers a handler that receives arguments as they were sent by the client, i.e.
+    /// percent-encoded
+    fn add_handler<H: router::Handler<S>>(
+        self,
+        cmd_name: &'static str,
+        handler: H,
+    ) -> AssuanServer<S, router::Cons<H, L>> {
         AssuanServer {
             service: self.service,
             cmd_handlers: router::Cons::new(cmd_name, handler, self.cmd_handlers),
+            config: self.config,
+            shutdown_requested: self.shutdown_requested,
         }
     }
 
-    /// Serves a client: reads the requests from `read` and writes the responses to `write`
+    /// Registers a new command that can only be called when `precondition` holds
+    ///
+    /// Before invoking the `handler`, `precondition` is checked against the service state. If it
+    /// returns an error, the error is sent to the client and the `handler` isn't called. That's
+    /// useful for commands that must only be called in certain order.
+    ///
+    /// ### Example
+    /// ```rust
+    /// use assuan::{AssuanServer, ErrorCode, Response, WithErrorCode};
     ///
-    /// Incoming requests will be routed between registered commands
-    pub fn serve_client<R, W>(&mut self, read: R, write: W) -> io::Result<()>
+    /// #[derive(Default)]
+    /// struct Signer {
+    ///     key: Option<String>,
+    /// }
+    ///
+    /// let server = AssuanServer::new(Signer::default())
+    ///     .add_command("SETKEY", |signer: &mut Signer, key: Option<&str>| {
+    ///         signer.key = key.map(String::from);
+    ///         Ok::<_, std::convert::Infallible>(Response::ok())
+    ///     })
+    ///     .add_command_with_precondition(
+    ///         "SIGN",
+    ///         |signer: &Signer| match signer.key {
+    ///             Some(_) => Ok(()),
+    ///             None => Err(WithErrorCode {
+    ///                 code: ErrorCode::MISSING_VALUE,
+    ///                 error: "SETKEY must be called first",
+    ///             }),
+    ///         },
+    ///         |_signer: &mut Signer, _data: Option<&str>| {
+    ///             // signer.key is guaranteed to be set at this point
+    ///             Ok::<_, std::convert::Infallible>(Response::ok())
+    ///         },
+    ///     );
+    /// ```
+    pub fn add_command_with_precondition<P, E>(
+        self,
+        cmd_name: &'static str,
+        precondition: impl Fn(&S) -> Result<(), P>,
+        handler: impl FnMut(&mut S, Option<&str>) -> Result<Response, E>,
+    ) -> AssuanServer<S, impl router::CmdList<S>>
     where
-        R: io::Read,
-        W: io::Write,
+        P: fmt::Display + HasErrorCode,
+        E: fmt::Display + HasErrorCode,
     {
-        self.serve_client_conn(&mut Conn { read, write })
+        self.add_handler(
+            cmd_name,
+            router::Guarded {
+                precondition,
+                handler: router::StrHandler(handler),
+            },
+        )
     }
 
-    /// Server a client: reads the requests and writes the responses to `conn`
-    pub fn serve_client_conn<C>(&mut self, conn: &mut C) -> io::Result<()>
+    /// Registers a provider answering `GETINFO key` request
+    ///
+    /// Unlike [static info](Self::set_info), the value is obtained from the service state on
+    /// every request. Provider's value is sent as a data response, provider's error is sent
+    /// as an error response.
+    ///
+    /// ### Example
+    /// ```rust
+    /// use assuan::AssuanServer;
+    ///
+    /// struct Agent {
+    ///     keys_cached: usize,
+    /// }
+    ///
+    /// let server = AssuanServer::new(Agent { keys_cached: 0 })
+    ///     .set_info("flavor", "agent")
+    ///     .add_getinfo("keys_cached", |agent: &Agent| {
+    ///         Ok::<_, std::convert::Infallible>(agent.keys_cached.to_string())
+    ///     });
+    /// ```
+    pub fn add_getinfo<E>(
+        self,
+        key: &'static str,
+        provider: impl Fn(&S) -> Result<String, E>,
+    ) -> AssuanServer<S, impl router::CmdList<S>>
     where
-        C: io::Read + io::Write,
+        E: fmt::Display + HasErrorCode,
     {
-        // Greet client
-        conn.write_all(b"OK how can I serve you?\n")?;
-
-        fn write_error(out: &mut impl io::Write, code: ErrorCode, desc: &str) -> io::Result<()> {
-            let resp = error(code, desc).map_err(|_err| io::Error::other("error is too long"))?;
-            resp.write(out)
+        AssuanServer {
+            service: self.service,
+            cmd_handlers: router::GetInfo::new(key, provider, self.cmd_handlers),
+            config: self.config,
+            shutdown_requested: self.shutdown_requested,
         }
+    }
 
-        // Serve client's requests
-        loop {
-            match self.serve_request(conn) {
-                Ok(true) => continue,
-                Ok(false) => break,
-                Err(ServeError::MalformedUtf8(err)) => {
-                    return write_error(conn, ErrorCode::ASS_INV_VALUE, &err.to_string())
-                }
-                Err(ServeError::MalformedPercentEncoding) => {
-                    return write_error(
-                        conn,
-                        ErrorCode::ASS_PARAMETER,
-                        "malformed percent encoding",
-                    )
-                }
-                Err(ServeError::ErrorTooLong(_err)) => {
-                    return write_error(conn, ErrorCode::INTERNAL, "error is too long")
-                }
-                Err(ServeError::Read(err)) => {
-                    return write_error(conn, ErrorCode::ASS_READ_ERROR, &err.to_string())
-                }
-                Err(ServeError::Write(err)) => {
-                    // we can't really send error to the client as write call already resulted
-                    // into error
-                    return Err(err);
-                }
-                Err(ServeError::ReceivedLineTooLong) => {
-                    return write_error(conn, ErrorCode::ASS_LINE_TOO_LONG, "line is too long")
-                }
-            }
+    /// Sets a fallback handling commands that aren't registered
+    ///
+    /// Fallback is called with the command name and its arguments as they were sent by the
+    /// client, i.e. percent-encoded, which makes it possible to forward the commands as is,
+    /// e.g. when proxying them to another server. `GETINFO` and `HELP` are still answered by
+    /// the server. Without fallback, unknown commands fail with
+    /// [`ASS_UNKNOWN_CMD`](ErrorCode::ASS_UNKNOWN_CMD) error.
+    ///
+    /// Commands registered before and after the fallback take precedence over it. If
+    /// fallback is set several times, the first one is used.
+    ///
+    /// ### Example
+    /// ```rust
+    /// use assuan::{AssuanServer, Response};
+    ///
+    /// struct Proxy {
+    ///     forwarded: Vec<String>,
+    /// }
+    ///
+    /// let server = AssuanServer::new(Proxy { forwarded: vec![] }).with_fallback(
+    ///     |proxy: &mut Proxy, cmd: &str, args: Option<&str>| {
+    ///         let request = match args {
+    ///             Some(args) => format!("{cmd} {args}"),
+    ///             None => cmd.to_owned(),
+    ///         };
+    ///         proxy.forwarded.push(request);
+    ///         Ok::<_, std::convert::Infallible>(Response::ok())
+    ///     },
+    /// );
+    /// ```
+    pub fn with_fallback<E>(
+        self,
+        fallback: impl FnMut(&mut S, &str, Option<&str>) -> Result<Response, E>,
+    ) -> AssuanServer<S, impl router::CmdList<S>>
+    where
+        E: fmt::Display + HasErrorCode,
+    {
+        AssuanServer {
+            service: self.service,
+            cmd_handlers: router::Fallback::new(fallback, self.cmd_handlers),
+            config: self.config,
+            shutdown_requested: self.shutdown_requested,
         }
-
-        Ok(())
     }
 
-    fn serve_request<C>(&mut self, conn: &mut C) -> Result<bool, ServeError>
+    /// Registers a new command which arguments are limited by `max_len` bytes after decoding
+    ///
+    /// If client sends longer arguments, the command fails with
+    /// [`TOO_LARGE`](ErrorCode::TOO_LARGE) error and the `handler` isn't called.
+    ///
+    /// ### Example
+    /// ```rust
+    /// use assuan::{AssuanServer, Response};
+    ///
+    /// let server = AssuanServer::new(String::new()).add_command_with_max_args_len(
+    ///     "SETKEYDESC",
+    ///     100,
+    ///     |desc: &mut String, args: Option<&str>| {
+    ///         *desc = args.unwrap_or_default().to_owned();
+    ///         Ok::<_, std::convert::Infallible>(Response::ok())
+    ///     },
+    /// );
+    /// ```
+    pub fn add_command_with_max_args_len<E>(
+        self,
+        cmd_name: &'static str,
+        max_len: usize,
+        handler: impl FnMut(&mut S, Option<&str>) -> Result<Response, E>,
+    ) -> AssuanServer<S, impl router::CmdList<S>>
     where
-        C: io::Read + io::Write,
+        E: fmt::Display + HasErrorCode,
     {
-        // Receive a line from the client
-        let mut line_reader = LineReader::new();
-        let Some(line) = line_reader.read_line(conn)? else {
-            return Ok(false);
-        };
+        self.add_handler(
+            cmd_name,
+            router::Limited {
+                max_args_len: max_len,
+                handler: router::StrHandler(handler),
+            },
+        )
+    }
 
-        // Line must be a valid UTF-8 string
-        let line = std::str::from_utf8(line).map_err(ServeError::MalformedUtf8)?;
+    /// Sets the clock used by time-dependent features
+    ///
+    /// By default, [system clock](clock::SystemClock) is used. Custom clock is mainly useful in
+    /// tests, see [`MockClock`](clock::MockClock).
+    pub fn with_clock(mut self, clock: impl clock::Clock + Send + 'static) -> Self {
+        self.config.clock = Box::new(clock);
+        self
+    }
 
-        if line.starts_with('#') || line.is_empty() {
-            // Lines beginning with a # or empty lines are ignored
-            return Ok(true);
-        }
+    /// Limits total duration of a connection
+    ///
+    /// Once the connection lasts longer than `duration`, server responds to the client with
+    /// [`TIMEOUT`](ErrorCode::TIMEOUT) error and closes the connection. Limit is enforced between
+    /// the requests, i.e. server never interrupts a command that's being processed.
+    ///
+    /// By default, connection duration is unlimited.
+    pub fn with_max_connection_duration(mut self, duration: Duration) -> Self {
+        self.config.max_connection_duration = Some(duration);
+        self
+    }
 
-        // Parse command
-        let (cmd, args) = line
-            .split_once(' ')
-            .map(|(cmd, args)| (cmd, Some(args)))
-            .unwrap_or_else(|| (line, None));
+    /// Limits how long server waits for the client to send a line
+    ///
+    /// Once the client is silent for longer than `timeout`, server responds with
+    /// [`TIMEOUT`](ErrorCode::TIMEOUT) error and closes the connection, so a client that died
+    /// mid-conversation doesn't leave the server hanging.
+    ///
+    /// Timeout only takes effect if the connection supports it: it's set on the socket served
+    /// via [`serve_unix_stream`](Self::serve_unix_stream). It doesn't apply to stdin, e.g. in
+    /// [`run_stdio`](Self::run_stdio). Custom connections may implement timeouts on their own:
+    /// reads failing with [`TimedOut`](io::ErrorKind::TimedOut) or
+    /// [`WouldBlock`](io::ErrorKind::WouldBlock) error are handled the same way.
+    ///
+    /// By default, there's no timeout.
+    pub fn with_read_timeout(mut self, timeout: Duration) -> Self {
+        self.config.read_timeout = Some(timeout);
+        self
+    }
 
-        // Decode percent encoding of args
-        let args = args
-            .map(|args| percent_decode::percent_decode(args).collect::<Result<String, _>>())
-            .transpose()
-            .map_err(|_| ServeError::MalformedPercentEncoding)?;
-        let args = args.as_deref();
+    /// Limits number of consecutive empty and comment lines sent by the client
+    ///
+    /// Such lines are ignored by the server, so a client flooding the server with them keeps
+    /// it busy for free. Once the client sends more than `max` of them in a row, server responds
+    /// with [`ASS_GENERAL`](ErrorCode::ASS_GENERAL) error and closes the connection.
+    ///
+    /// By default, number of such lines is unlimited.
+    pub fn with_max_consecutive_ignored_lines(mut self, max: usize) -> Self {
+        self.config.max_consecutive_ignored_lines = Some(max);
+        self
+    }
 
-        // Route and execute the command
-        let response = self.cmd_handlers.handle(cmd, &mut self.service, args);
+    /// Limits number of status lines sent in response to a single command
+    ///
+    /// Counts both lines sent via [`CmdCtx::status`] and the ones attached to the response via
+    /// [`Response::with_status`]. Once a command exceeds the limit, further statuses aren't
+    /// sent, and the command fails with [`INTERNAL`](ErrorCode::INTERNAL) error. Protects the
+    /// client from a buggy handler flooding it with statuses.
+    ///
+    /// By default, number of status lines is unlimited.
+    pub fn with_max_status_lines(mut self, max: usize) -> Self {
+        self.config.max_status_lines = Some(max);
+        self
+    }
+
+    /// Requires the greeting to be sent to the client before any request is served
+    ///
+    /// Client isn't supposed to send requests before it read the greeting, but nothing stops
+    /// it from doing so, e.g. a client may pipeline the next session's requests right after
+    /// `BYE`. By default, the greeting is flushed along with the responses right before the
+    /// server waits for the client, so pipelined requests are served before the client
+    /// received the greeting. When required, the greeting is flushed right away, and if it
+    /// can't be sent, the session ends without serving any request. Pipelined requests are
+    /// still served once the greeting is sent.
+    ///
+    /// Useful for protocol conformance testing, and for commands with side effects, like
+    /// asking the user for a PIN, which shouldn't happen if the client is already gone.
+    pub fn require_greeting_sent(mut self, v: bool) -> Self {
+        self.config.require_greeting_sent = v;
+        self
+    }
 
-        // Convert error to string
-        let response = response.map(|resp| resp.map_err(|err| (err.code(), err.to_string())));
-        let response = response
-            .as_ref()
-            .map(|resp| resp.as_ref().map_err(|(code, desc)| (*code, desc.as_str())));
+    /// Sets max size of request lines, including the final `\n`
+    ///
+    /// Assuan spec limits lines by [`MAX_LINE_SIZE`] which is the default, and the server
+    /// rejects longer requests with [`ASS_LINE_TOO_LONG`](ErrorCode::ASS_LINE_TOO_LONG)
+    /// error. Some clients negotiate larger lines. Once the line exceeds [`MAX_LINE_SIZE`],
+    /// a larger buffer is allocated for it, and it's freed (and zeroized) after the line is
+    /// processed. Limit may be overridden for specific commands via
+    /// [`with_max_line_size_for`](Self::with_max_line_size_for).
+    ///
+    /// Responses are still limited by [`MAX_LINE_SIZE`]: they're built by handlers regardless
+    /// of the server configuration.
+    pub fn with_max_line_size(mut self, max_line_size: usize) -> Self {
+        self.config.max_line_size = max_line_size;
+        self
+    }
 
-        // Handle `unknown command` error
-        let response = response.unwrap_or(Err((ErrorCode::ASS_UNKNOWN_CMD, "Unknown command")));
+    /// Allows request lines of `cmd` command to be up to `max_line_size` bytes long
+    ///
+    /// Assuan spec limits lines by [`MAX_LINE_SIZE`], and the server rejects longer requests.
+    /// However, some non-standard extensions carry large payloads in arguments of specific
+    /// commands. Once the line of such command exceeds [`MAX_LINE_SIZE`], a larger buffer is
+    /// allocated for it, and it's freed (and zeroized) after the line is processed. Lines of
+    /// other commands are still limited by [server-wide limit](Self::with_max_line_size).
+    /// Limit includes the final `\n`.
+    pub fn with_max_line_size_for(mut self, cmd: &'static str, max_line_size: usize) -> Self {
+        self.config.max_line_sizes.push((cmd, max_line_size));
+        self
+    }
 
-        match response {
-            Ok(resp) => {
-                resp.write(conn).map_err(ServeError::Write)?;
-                Ok(!resp.connection_needs_be_closed())
-            }
-            Err((code, err)) => {
-                let resp = error(code, err).map_err(ServeError::ErrorTooLong)?;
-                resp.write(conn).map_err(ServeError::Write)?;
-                Ok(true)
-            }
+    /// Sets the greeting sent to the client once it's connected
+    ///
+    /// Greeting is an `OK` line, `info` is sent as its debug info, e.g. `Some("Pleased to meet
+    /// you")` makes the server greet clients with `OK Pleased to meet you`. If `info` is `None`
+    /// or empty, bare `OK` is sent. By default, clients are greeted with
+    /// `OK how can I serve you?`.
+    ///
+    /// Returns error if `info`, once percent-encoded, exceeds the limit set by the assuan spec
+    /// (see [`response::Ok::MAX_BYTES`]). The greeting is validated here, so it can't fail to be
+    /// sent later on.
+    ///
+    /// ### Example
+    /// ```rust
+    /// use assuan::AssuanServer;
+    ///
+    /// let greeting = format!("Pleased to meet you, process {}", std::process::id());
+    /// let server = AssuanServer::new(()).with_greeting(Some(&greeting))?;
+    /// # Ok::<_, assuan::response::TooLong>(())
+    /// ```
+    pub fn with_greeting(mut self, info: Option<&str>) -> Result<Self, response::TooLong> {
+        let mut greeting = ResponseLine::new().chain("OK")?;
+        if let Some(info) = info.filter(|info| !info.is_empty()) {
+            greeting.append(" ")?;
+            greeting.append(info)?;
         }
+        self.config.greeting = greeting;
+        Ok(self)
     }
-}
-
-fn error(code: ErrorCode, desc: impl AsRef<str>) -> Result<ResponseLine, response::TooLong> {
-    response::ResponseLine::new()
-        .chain("ERR ")?
-        .chain(&code.0.to_string())?
-        .chain(" ")?
-        .chain(desc.as_ref())
-}
 
-enum ServeError {
-    MalformedUtf8(std::str::Utf8Error),
-    MalformedPercentEncoding,
-    ErrorTooLong(response::TooLong),
-    Read(io::Error),
-    Write(io::Error),
-    ReceivedLineTooLong,
-}
+    /// Enables or disables strict mode
+    ///
+    /// By default, server tolerates some client behavior that doesn't conform to the assuan spec.
+    /// In strict mode, such requests are rejected with an error:
+    /// * Line containing NUL character (including comment lines) is rejected with
+    ///   [`ASS_INV_VALUE`](ErrorCode::ASS_INV_VALUE)
+    /// * Command containing control characters is rejected with [`ASS_SYNTAX`](ErrorCode::ASS_SYNTAX)
+    /// * Command containing lowercase characters is rejected with [`ASS_SYNTAX`](ErrorCode::ASS_SYNTAX)
+    /// * `OPTION` command which doesn't follow `OPTION name [[=] value]` form is rejected with
+    ///   [`ASS_PARAMETER`](ErrorCode::ASS_PARAMETER)
+    ///
+    /// Strict mode is mainly useful for testing conformance of the clients.
+    pub fn strict(mut self, v: bool) -> Self {
+        self.config.strict = v;
+        self
+    }
 
-impl From<line_reader::ReadLineError> for ServeError {
-    fn from(err: line_reader::ReadLineError) -> Self {
-        match err {
-            line_reader::ReadLineError::Read(err) => Self::Read(err),
-            line_reader::ReadLineError::LineTooLong => Self::ReceivedLineTooLong,
+    /// Sets a static response to `GETINFO key` request
+    ///
+    /// Server answers `GETINFO key` with `value` sent as a data response. `GETINFO` with an
+    /// unknown key is rejected with [`ASS_PARAMETER`](ErrorCode::ASS_PARAMETER), so clients
+    /// can probe for the keys. `GETINFO pid` is answered with the process id unless it's set
+    /// explicitly. Setting the same key twice overwrites the value.
+    ///
+    /// Info [providers](Self::add_getinfo) take precedence over the static info. If
+    /// a `GETINFO` command is [registered](Self::add_command) explicitly, it takes precedence
+    /// over both.
+    ///
+    /// ### Example
+    /// ```rust
+    /// use assuan::AssuanServer;
+    ///
+    /// let server = AssuanServer::new(())
+    ///     .set_info("flavor", "tty")
+    ///     .set_info("version", env!("CARGO_PKG_VERSION"));
+    /// ```
+    pub fn set_info(mut self, key: &'static str, value: impl Into<String>) -> Self {
+        let value = value.into();
+        match self.config.info.iter_mut().find(|(k, _)| *k == key) {
+            Some((_, existing)) => *existing = value,
+            None => self.config.info.push((key, value)),
         }
+        self
     }
-}
 
-struct Conn<R, W> {
-    read: R,
-    write: W,
-}
+    /// Serves a client: reads the requests from `read` and writes the responses to `write`
+    ///
+    /// Incoming requests will be routed between registered commands.
+    ///
+    /// Client closing the connection isn't an error. If it happens while the response is being
+    /// written, [`ServeOutcome::Disconnected`] is returned, so it can be told apart from
+    /// the client ending the session properly. Other write errors are returned as is.
+    pub fn serve_client<R, W>(&mut self, read: R, write: W) -> io::Result<ServeOutcome>
+    where
+        R: io::Read,
+        W: io::Write,
+    {
+        self.serve_client_conn(&mut conn::Conn { read, write })
+    }
+
+    /// Server a client: reads the requests and writes the responses to `conn`
+    ///
+    /// Client disconnecting is handled the same way as in [`serve_client`](Self::serve_client)
+    pub fn serve_client_conn<C>(&mut self, conn: &mut C) -> io::Result<ServeOutcome>
+    where
+        C: io::Read + io::Write,
+    {
+        outcome(self.serve_session(conn, &mut LineReader::new()).map(|_| ()))
+    }
 
-impl<R: io::Read, W> io::Read for Conn<R, W> {
-    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
-        self.read.read(buf)
+    /// Serves a client sending `input`, returns the exact bytes written by the server
+    ///
+    /// Captured output includes the greeting, all responses and errors, which makes it suitable
+    /// for wire format regression tests.
+    ///
+    /// ### Example
+    /// ```rust
+    /// use assuan::AssuanServer;
+    ///
+    /// let output = AssuanServer::new(()).capture_output(b"NOP\nBYE\n")?;
+    /// assert_eq!(output, b"OK how can I serve you?\nOK success\nOK success\n");
+    /// # Ok::<_, std::io::Error>(())
+    /// ```
+    pub fn capture_output(&mut self, input: &[u8]) -> io::Result<Vec<u8>> {
+        let mut output = vec![];
+        self.serve_client(input, &mut output)?;
+        Ok(output)
     }
-}
 
-impl<R, W: io::Write> io::Write for Conn<R, W> {
-    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
-        self.write.write(buf)
+    /// Serves a client connected over the unix socket
+    ///
+    /// Once the session is over (e.g. client sent `BYE`), the final response is flushed and
+    /// the writing half of the socket is shut down, so the client observes the end of
+    /// the connection promptly, even if the `stream` is still kept open by the caller.
+    ///
+    /// [Read timeout](Self::with_read_timeout), if set, is applied to the `stream`.
+    ///
+    /// Client disconnecting is reported the same way as in [`serve_client`](Self::serve_client).
+    #[cfg(unix)]
+    pub fn serve_unix_stream(
+        &mut self,
+        stream: &std::os::unix::net::UnixStream,
+    ) -> io::Result<ServeOutcome> {
+        if self.config.read_timeout.is_some() {
+            stream.set_read_timeout(self.config.read_timeout)?;
+        }
+        let mut conn = stream;
+        let result = self.serve_client_conn(&mut conn);
+        let shutdown =
+            io::Write::flush(&mut conn).and_then(|()| stream.shutdown(std::net::Shutdown::Write));
+        let outcome = result?;
+        match shutdown {
+            // Client has already closed the connection
+            Err(err) if err.kind() == io::ErrorKind::NotConnected => Ok(outcome),
+            result => result.map(|()| outcome),
+        }
     }
 
-    fn flush(&mut self) -> io::Result<()> {
-        self.write.flush()
+    /// Serves a single client over stdin and stdout
+    ///
+    /// Convenience for servers launched by the client as a subprocess, like pinentry. Client
+    /// closing the pipe before the session is over isn't considered an error, same as in
+    /// [`serve_client`](Self::serve_client).
+    ///
+    /// ### Example
+    /// ```rust,no_run
+    /// use assuan::AssuanServer;
+    ///
+    /// AssuanServer::new(()).run_stdio()?;
+    /// # Ok::<_, std::io::Error>(())
+    /// ```
+    pub fn run_stdio(&mut self) -> io::Result<()> {
+        self.serve_client(io::stdin().lock(), io::stdout().lock())
+            .map(|_| ())
+    }
+
+    /// Serves clients over stdin and stdout one after another until stdin is closed or the
+    /// server is [shut down](response::Ok::shutdown_server)
+    ///
+    /// Same as [`run_stdio`](Self::run_stdio), but once a client ends the session (e.g. by
+    /// sending `BYE`), server greets the next client instead of exiting. Suitable for persistent
+    /// servers.
+    pub fn run_stdio_loop(&mut self) -> io::Result<()> {
+        let mut conn = conn::Conn {
+            read: io::stdin().lock(),
+            write: io::stdout().lock(),
+        };
+        self.serve_sessions(&mut conn)
+    }
+
+    /// Serves sessions over `conn` one after another until client closes the connection
+    fn serve_sessions<C>(&mut self, conn: &mut C) -> io::Result<()>
+    where
+        C: io::Read + io::Write,
+    {
+        // Line reader is shared between the sessions as client may start the next session
+        // without waiting for the previous one to be closed
+        let mut line_reader = LineReader::new();
+        loop {
+            match self.serve_session(conn, &mut line_reader) {
+                Ok(true) => continue,
+                result => return outcome(result.map(|_| ())).map(|_| ()),
+            }
+        }
+    }
+
+    /// Serves a client session
+    ///
+    /// Returns `true` if the session was closed by the response (e.g. to `BYE`), so the client
+    /// may start a new session over the same connection.
+    ///
+    /// Errors that occurred while reading the requests are reported to the client, so only
+    /// write errors are returned.
+    fn serve_session<C>(&mut self, conn: &mut C, line_reader: &mut LineReader) -> io::Result<bool>
+    where
+        C: io::Read + io::Write,
+    {
+        let connection_deadline = self.connection_deadline();
+
+        // Greet client
+        self.config.greeting.write(conn)?;
+        if self.config.require_greeting_sent {
+            conn.flush()?;
+        }
+
+        // Serve client's requests. Line reader is shared between the requests as client may
+        // send several requests at once.
+        let mut ignored_lines = 0;
+        let result = loop {
+            if self.is_expired(connection_deadline) {
+                break Err(ServeError::ConnectionLifetimeExceeded);
+            }
+
+            // Client may pipeline requests, i.e. send several of them without waiting for
+            // responses. Responses are flushed only when there's no buffered request left,
+            // right before we block waiting for the client
+            if !line_reader.has_pending_line() {
+                conn.flush()?;
+            }
+
+            match self.serve_request(line_reader, conn, &mut ignored_lines) {
+                Ok(Served::Continue) => continue,
+                Ok(served) => {
+                    self.shutdown_requested |= served == Served::ServerShutdown;
+                    break Ok(served == Served::SessionClosed);
+                }
+                Err(err) => break Err(err),
+            }
+        };
+
+        let session_closed = match result {
+            Ok(session_closed) => session_closed,
+            Err(err) => {
+                err.into_response()?.write(conn)?;
+                false
+            }
+        };
+        conn.flush()?;
+        Ok(session_closed)
+    }
+
+    /// Serves a client asynchronously: reads the requests from `read` and writes the
+    /// responses to `write`
+    ///
+    /// Behaves the same way as [`serve_client`](Self::serve_client), but awaits on the
+    /// connection instead of blocking, so it can be used within tokio runtime without
+    /// spawning a blocking thread per connection.
+    ///
+    /// Command handlers are still called synchronously, so they must not block for long.
+    /// They also can't communicate with the client on their own: [`CmdCtx::inquire`],
+    /// [`CmdCtx::status`] and reading or writing the connection in
+    /// [interactive commands](Self::add_command_interactive) fail with [`io::ErrorKind::Unsupported`] error. Commands that need data from the client
+    /// should respond with [`Response::inquire`] instead.
+    ///
+    /// Client closing the connection while the response is being written is reported as
+    /// [`ServeOutcome::Disconnected`], same as in [`serve_client`](Self::serve_client).
+    ///
+    /// Requires `async` feature to be enabled.
+    #[cfg(feature = "async")]
+    pub async fn serve_client_async<R, W>(
+        &mut self,
+        mut read: R,
+        mut write: W,
+    ) -> io::Result<ServeOutcome>
+    where
+        R: tokio::io::AsyncRead + Unpin,
+        W: tokio::io::AsyncWrite + Unpin,
+    {
+        outcome(self.serve_session_async(&mut read, &mut write).await)
+    }
+
+    /// Serves a client session asynchronously
+    #[cfg(feature = "async")]
+    async fn serve_session_async(
+        &mut self,
+        read: &mut (impl tokio::io::AsyncRead + Unpin),
+        write: &mut (impl tokio::io::AsyncWrite + Unpin),
+    ) -> io::Result<()> {
+        let connection_deadline = self.connection_deadline();
+
+        // Responses are serialized synchronously, so they're written into the buffer first
+        let mut out = zeroize::Zeroizing::new(Vec::new());
+        self.config.greeting.write(&mut *out)?;
+        if self.config.require_greeting_sent {
+            send(write, &mut out).await?;
+        }
+
+        let mut line_reader = LineReader::new();
+        let mut ignored_lines = 0;
+        let result = loop {
+            if self.is_expired(connection_deadline) {
+                break Err(ServeError::ConnectionLifetimeExceeded);
+            }
+
+            if !line_reader.has_pending_line() {
+                send(write, &mut out).await?;
+            }
+
+            let served = self
+                .serve_request_async(&mut line_reader, read, write, &mut out, &mut ignored_lines)
+                .await;
+            match served {
+                Ok(Served::Continue) => continue,
+                Ok(served) => {
+                    self.shutdown_requested |= served == Served::ServerShutdown;
+                    break Ok(());
+                }
+                Err(err) => break Err(err),
+            }
+        };
+
+        if let Err(err) = result {
+            err.into_response()?.write(&mut *out)?;
+        }
+        send(write, &mut out).await
+    }
+
+    fn connection_deadline(&self) -> Option<clock::Deadline> {
+        self.config
+            .max_connection_duration
+            .map(|duration| clock::Deadline::after(&self.config.clock, duration))
+    }
+
+    fn is_expired(&self, deadline: Option<clock::Deadline>) -> bool {
+        deadline.is_some_and(|d| d.is_expired(&self.config.clock))
+    }
+
+    /// Serves a single request
+    ///
+    /// `ignored_lines` counts empty and comment lines received in a row
+    fn serve_request<C>(
+        &mut self,
+        line_reader: &mut LineReader,
+        conn: &mut C,
+        ignored_lines: &mut usize,
+    ) -> Result<Served, ServeError>
+    where
+        C: io::Read + io::Write,
+    {
+        // Receive a line from the client
+        let max_line_size = |cmd: &[u8]| self.config.max_line_size(cmd);
+        let Some(line) = line_reader.read_line_with_limit(conn, max_line_size)? else {
+            return Ok(Served::Disconnected);
+        };
+
+        let (cmd, args) = match self.parse_request(line, ignored_lines)? {
+            Request::Ignored => return Ok(Served::Continue),
+            Request::Rejected(violation) => {
+                write_spec_violation(conn, violation)?;
+                return Ok(Served::Continue);
+            }
+            Request::Cmd { cmd, args } => (cmd, args),
+        };
+
+        let Some(mut response) = self.execute(&cmd, args.as_deref(), line_reader, conn)? else {
+            // Handler has already communicated with the client on its own
+            return Ok(Served::Continue);
+        };
+        loop {
+            match response {
+                Ok(Response::Inquire(inquire)) => {
+                    // Ask client for data and resume the command once it's received
+                    inquire.write(conn).map_err(ServeError::Write)?;
+                    conn.flush().map_err(ServeError::Write)?;
+                    let reply = inquire::read_reply(line_reader, conn);
+                    let resumed = resume_inquiry(inquire, reply)?;
+                    response = self.config.limit_status_lines(resumed, 0);
+                }
+                response => return write_response(conn, response),
+            }
+        }
+    }
+
+    /// Serves a single request asynchronously
+    ///
+    /// Responses are written into `out`, they're sent to the client before reading anything
+    /// from it.
+    #[cfg(feature = "async")]
+    async fn serve_request_async(
+        &mut self,
+        line_reader: &mut LineReader,
+        read: &mut (impl tokio::io::AsyncRead + Unpin),
+        write: &mut (impl tokio::io::AsyncWrite + Unpin),
+        out: &mut Vec<u8>,
+        ignored_lines: &mut usize,
+    ) -> Result<Served, ServeError> {
+        let max_line_size = |cmd: &[u8]| self.config.max_line_size(cmd);
+        let Some(line) = line_reader
+            .read_line_async_with_limit(read, max_line_size)
+            .await?
+        else {
+            return Ok(Served::Disconnected);
+        };
+
+        let (cmd, args) = match self.parse_request(line, ignored_lines)? {
+            Request::Ignored => return Ok(Served::Continue),
+            Request::Rejected(violation) => {
+                write_spec_violation(out, violation)?;
+                return Ok(Served::Continue);
+            }
+            Request::Cmd { cmd, args } => (cmd, args),
+        };
+
+        // Handlers are synchronous, they're given a connection they can't communicate over.
+        // Line reader is separate too, so they can't consume requests pipelined by the client.
+        let Some(mut response) = self.execute(
+            &cmd,
+            args.as_deref(),
+            &mut LineReader::new(),
+            &mut conn::Detached,
+        )?
+        else {
+            return Ok(Served::Continue);
+        };
+        loop {
+            match response {
+                Ok(Response::Inquire(inquire)) => {
+                    inquire.write(out).map_err(ServeError::Write)?;
+                    send(write, out).await.map_err(ServeError::Write)?;
+                    let reply = inquire::read_reply_async(line_reader, read).await;
+                    let resumed = resume_inquiry(inquire, reply)?;
+                    response = self.config.limit_status_lines(resumed, 0);
+                }
+                response => return write_response(out, response),
+            }
+        }
+    }
+
+    /// Parses a request line received from the client
+    ///
+    /// `ignored_lines` counts empty and comment lines received in a row
+    fn parse_request(&self, line: &[u8], ignored_lines: &mut usize) -> Result<Request, ServeError> {
+        // Line must be a valid UTF-8 string
+        let line = std::str::from_utf8(line).map_err(ServeError::MalformedUtf8)?;
+
+        if self.config.strict {
+            if let Err(violation) = strict::check_line(line) {
+                return Ok(Request::Rejected(violation));
+            }
+        }
+
+        if line.starts_with('#') || line.is_empty() {
+            // Lines beginning with a # or empty lines are ignored
+            *ignored_lines += 1;
+            if self
+                .config
+                .max_consecutive_ignored_lines
+                .is_some_and(|max| *ignored_lines > max)
+            {
+                return Err(ServeError::TooManyIgnoredLines);
+            }
+            return Ok(Request::Ignored);
+        }
+        *ignored_lines = 0;
+
+        // Parse command
+        let (cmd, args) = line
+            .split_once(' ')
+            .map(|(cmd, args)| (cmd, Some(args)))
+            .unwrap_or_else(|| (line, None));
+
+        if self.config.strict {
+            if let Err(violation) = strict::check_request(cmd, args) {
+                return Ok(Request::Rejected(violation));
+            }
+        }
+
+        // Command and args are copied as the line is borrowed from the line reader which is
+        // handed over to the command handler. Args are decoded by the handler.
+        Ok(Request::Cmd {
+            cmd: cmd.to_owned(),
+            args: args.map(String::from),
+        })
+    }
+
+    /// Answers `HELP` request by listing the commands, one per comment line
+    ///
+    /// Predefined commands go first, followed by registered commands in order of registration
+    fn help(&self) -> CmdResult {
+        let names = self.cmd_handlers.command_names();
+        // Command may be registered several times, e.g. to override a predefined one
+        let mut listed = Vec::new();
+        for name in names.chain(["GETINFO", "HELP"]) {
+            if !listed.contains(&name) {
+                listed.push(name);
+            }
+        }
+        Response::ok_with_comments(&listed.join("\n")).map_err(|err| (err.code(), err.to_string()))
+    }
+
+    /// Routes the command to its handler and executes it
+    ///
+    /// Returns `None` if handler has already responded to the client on its own
+    fn execute(
+        &mut self,
+        cmd: &str,
+        args: Option<&str>,
+        line_reader: &mut LineReader,
+        conn: &mut dyn conn::ReadWrite,
+    ) -> Result<Option<CmdResult>, ServeError> {
+        let mut ctx = CmdCtx::new(line_reader, conn, self.config.max_status_lines);
+
+        // Route and execute the command
+        let handled = self
+            .cmd_handlers
+            .handle_encoded(cmd, &mut self.service, args, &mut ctx);
+        if let Some(err) = ctx.take_fatal_error() {
+            fatal_reply_error(err)?;
+        }
+        let result = match handled {
+            Some(Ok(_)) if ctx.response_suppressed() => return Ok(None),
+            Some(Ok(resp)) => Ok(resp),
+            Some(Err(err)) => Err((err.code(), err.to_string())),
+            None if cmd.eq_ignore_ascii_case("GETINFO") => self.config.get_info(args),
+            None if cmd.eq_ignore_ascii_case("HELP") => self.help(),
+            // Handle `unknown command` error
+            None => Err((ErrorCode::ASS_UNKNOWN_CMD, "Unknown command".to_string())),
+        };
+        Ok(Some(
+            self.config
+                .limit_status_lines(result, ctx.status_lines_sent()),
+        ))
+    }
+}
+
+/// Greeting sent to the client once it's connected, unless
+/// [customized](AssuanServer::with_greeting)
+const DEFAULT_GREETING: &str = "OK how can I serve you?";
+
+/// State of the connection once a request is served
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Served {
+    /// Server keeps serving the client
+    Continue,
+    /// Session was closed by the response, e.g. to `BYE`
+    SessionClosed,
+    /// Server was shut down by the response, no further sessions are served
+    ServerShutdown,
+    /// Client closed the connection
+    Disconnected,
+}
+
+/// Response to the command, or error code and description if command failed
+type CmdResult = Result<Response, (ErrorCode, String)>;
+
+/// Request received from the client
+enum Request {
+    /// Empty line or comment
+    Ignored,
+    /// Request violates the spec, server responds with the error
+    Rejected(strict::SpecViolation),
+    /// Command to be executed
+    Cmd { cmd: String, args: Option<String> },
+}
+
+/// Resumes the command once the reply to its inquiry is received
+fn resume_inquiry(
+    inquire: response::Inquire,
+    reply: Result<response::SecretData, inquire::ReplyError>,
+) -> Result<CmdResult, ServeError> {
+    Ok(match reply {
+        Ok(data) => inquire.resume(data).map_err(|err| (err.code, err.error)),
+        Err(err) => {
+            let err = fatal_reply_error(err)?;
+            Err((err.code(), err.to_string()))
+        }
+    })
+}
+
+/// Writes the final response to the command
+fn write_response(out: &mut impl io::Write, response: CmdResult) -> Result<Served, ServeError> {
+    match response {
+        Ok(resp) => {
+            resp.write(out).map_err(ServeError::Write)?;
+            if resp.server_needs_be_shut_down() {
+                Ok(Served::ServerShutdown)
+            } else if resp.connection_needs_be_closed() {
+                Ok(Served::SessionClosed)
+            } else {
+                Ok(Served::Continue)
+            }
+        }
+        Err((code, err)) => {
+            let resp = response::error_line(code, &err).map_err(ServeError::ErrorTooLong)?;
+            resp.write(out).map_err(ServeError::Write)?;
+            Ok(Served::Continue)
+        }
+    }
+}
+
+/// Tells client closing the connection apart from other errors occurred while serving it
+fn outcome(result: io::Result<()>) -> io::Result<ServeOutcome> {
+    match result {
+        Ok(()) => Ok(ServeOutcome::Finished),
+        Err(err)
+            if matches!(
+                err.kind(),
+                io::ErrorKind::BrokenPipe | io::ErrorKind::ConnectionReset
+            ) =>
+        {
+            Ok(ServeOutcome::Disconnected)
+        }
+        Err(err) => Err(err),
+    }
+}
+
+/// Sends responses buffered in `out` to the client
+#[cfg(feature = "async")]
+async fn send(
+    write: &mut (impl tokio::io::AsyncWrite + Unpin),
+    out: &mut Vec<u8>,
+) -> io::Result<()> {
+    use tokio::io::AsyncWriteExt;
+    use zeroize::Zeroize;
+
+    write.write_all(out).await?;
+    out.zeroize();
+    write.flush().await
+}
+
+/// Returns `Err(_)` if error occurred while reading the reply to inquiry doesn't let the server
+/// to proceed serving the client, otherwise returns the error back
+fn fatal_reply_error(err: inquire::ReplyError) -> Result<inquire::ReplyError, ServeError> {
+    match err {
+        inquire::ReplyError::Read(err) => Err(err.into()),
+        inquire::ReplyError::MalformedUtf8(err) => Err(ServeError::MalformedUtf8(err)),
+        err => Ok(err),
+    }
+}
+
+fn write_spec_violation(
+    out: &mut impl io::Write,
+    violation: strict::SpecViolation,
+) -> Result<(), ServeError> {
+    let resp = response::error_line(violation.code(), &violation.to_string())
+        .map_err(ServeError::ErrorTooLong)?;
+    resp.write(out).map_err(ServeError::Write)
+}
+
+enum ServeError {
+    MalformedUtf8(std::str::Utf8Error),
+    ErrorTooLong(response::TooLong),
+    Read(io::Error),
+    Write(io::Error),
+    ReceivedLineTooLong,
+    TooManyIgnoredLines,
+    ConnectionLifetimeExceeded,
+    ReadTimeout,
+}
+
+impl ServeError {
+    /// Converts the error into the response sent to the client right before the connection
+    /// is closed
+    fn into_response(self) -> io::Result<ResponseLine> {
+        let (code, desc) = match self {
+            Self::MalformedUtf8(err) => (ErrorCode::ASS_INV_VALUE, err.to_string()),
+            Self::ErrorTooLong(_err) => (ErrorCode::INTERNAL, "error is too long".to_owned()),
+            Self::Read(err) => (ErrorCode::ASS_READ_ERROR, err.to_string()),
+            Self::Write(err) => {
+                // we can't really send error to the client as write call already resulted
+                // into error
+                return Err(err);
+            }
+            Self::ReceivedLineTooLong => (ErrorCode::ASS_LINE_TOO_LONG, "line is too long".into()),
+            Self::TooManyIgnoredLines => (
+                ErrorCode::ASS_GENERAL,
+                "too many consecutive empty or comment lines".into(),
+            ),
+            Self::ConnectionLifetimeExceeded => {
+                (ErrorCode::TIMEOUT, "connection lifetime exceeded".into())
+            }
+            Self::ReadTimeout => (ErrorCode::TIMEOUT, "client is idle for too long".into()),
+        };
+        response::error_line(code, &desc).map_err(|_err| io::Error::other("error is too long"))
+    }
+}
+
+impl From<line_reader::ReadLineError> for ServeError {
+    fn from(err: line_reader::ReadLineError) -> Self {
+        match err {
+            line_reader::ReadLineError::Read(err)
+                if matches!(
+                    err.kind(),
+                    io::ErrorKind::TimedOut | io::ErrorKind::WouldBlock
+                ) =>
+            {
+                Self::ReadTimeout
+            }
+            line_reader::ReadLineError::Read(err) => Self::Read(err),
+            line_reader::ReadLineError::LineTooLong => Self::ReceivedLineTooLong,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use crate::{
+        clock::MockClock,
+        response::Response,
+        router::{self, CmdList},
+        AssuanServer, CmdCtx, ErrorCode, ServeOutcome, WithErrorCode,
+    };
+
+    fn serve<S, L: CmdList<S>>(server: &mut AssuanServer<S, L>, input: &str) -> String {
+        String::from_utf8(server.capture_output(input.as_bytes()).unwrap()).unwrap()
+    }
+
+    fn greet(_: &mut (), _args: Option<&str>) -> Result<Response, crate::response::TooLong> {
+        Response::inquire("NAME", |name| {
+            if name.size() == 0 {
+                Err(WithErrorCode {
+                    code: ErrorCode::NO_VALUE,
+                    error: "no name given",
+                })
+            } else {
+                let name: String = name.chars().collect();
+                Ok(Response::data(&format!("Hello, {name}!")).unwrap())
+            }
+        })
+    }
+
+    #[test]
+    fn declarative_inquire() {
+        let mut server = AssuanServer::new(()).add_command("GREET", greet);
+
+        let output = serve(&mut server, "GREET\nD Bob\nD  and%0AAlice\nEND\nBYE\n");
+        assert_eq!(
+            output,
+            "OK how can I serve you?\n\
+            INQUIRE NAME\n\
+            D Hello, Bob and%0AAlice!\n\
+            OK success\n\
+            OK success\n"
+        );
+    }
+
+    #[test]
+    fn declarative_inquire_errors() {
+        let mut server = AssuanServer::new(()).add_command("GREET", greet);
+
+        let output = serve(
+            &mut server,
+            "GREET\nEND\nGREET\nCAN\nGREET\nNOP\nD Bob\nEND\nGREET\nD %GG\nD Bob\nEND\nBYE\n",
+        );
+        assert_eq!(
+            output,
+            "OK how can I serve you?\n\
+            INQUIRE NAME\n\
+            ERR 26 no name given\n\
+            INQUIRE NAME\n\
+            ERR 277 inquiry canceled by client\n\
+            INQUIRE NAME\n\
+            ERR 274 unexpected command in reply to inquiry\n\
+            INQUIRE NAME\n\
+            ERR 280 malformed percent encoding: non-hex digit at 1\n\
+            OK success\n"
+        );
+    }
+
+    #[test]
+    fn inquire_binary_data() {
+        let mut server = AssuanServer::new(Vec::new()).add_command_with_ctx(
+            "PKDECRYPT",
+            |ciphertext: &mut Vec<u8>, _args: Option<&str>, ctx: &mut CmdCtx| {
+                *ciphertext = ctx.inquire_bytes("CIPHERTEXT", 8)?.to_vec();
+                Ok::<_, std::io::Error>(Response::ok())
+            },
+        );
+
+        let output = serve(
+            &mut server,
+            "PKDECRYPT\nD %00%FF\nD a%00b\nEND\nPKDECRYPT\nCAN\nPKDECRYPT\nD 123456789\nEND\nBYE\n",
+        );
+        assert_eq!(
+            output,
+            "OK how can I serve you?\n\
+            INQUIRE CIPHERTEXT\n\
+            OK success\n\
+            INQUIRE CIPHERTEXT\n\
+            ERR 277 inquiry canceled by client\n\
+            INQUIRE CIPHERTEXT\n\
+            ERR 273 too much data\n\
+            OK success\n"
+        );
+        assert_eq!(server.service, b"\x00\xFFa\x00b");
+    }
+
+    #[test]
+    fn imperative_inquire() {
+        let mut server = AssuanServer::new(String::new()).add_command_with_ctx(
+            "UNLOCK",
+            |passphrase: &mut String, _args: Option<&str>, ctx: &mut CmdCtx| {
+                let data = ctx.inquire("PASSPHRASE")?;
+                *passphrase = data.chars().collect();
+                Ok::<_, std::io::Error>(Response::ok())
+            },
+        );
+
+        let output = serve(
+            &mut server,
+            "UNLOCK\nD correct%25horse\nD  battery\nEND\nUNLOCK\nCAN\nUNLOCK\nERR 99 no pin\nBYE\n",
+        );
+        assert_eq!(
+            output,
+            "OK how can I serve you?\n\
+            INQUIRE PASSPHRASE\n\
+            OK success\n\
+            INQUIRE PASSPHRASE\n\
+            ERR 277 inquiry canceled by client\n\
+            INQUIRE PASSPHRASE\n\
+            ERR 99 client failed to reply to inquiry: no pin\n\
+            OK success\n"
+        );
+        assert_eq!(server.service, "correct%horse battery");
+
+        let output = serve(&mut server, "UNLOCK\nEND\n");
+        assert_eq!(
+            output,
+            "OK how can I serve you?\n\
+            INQUIRE PASSPHRASE\n\
+            OK success\n"
+        );
+        assert_eq!(server.service, "");
+    }
+
+    #[test]
+    fn status_lines() {
+        let mut server = AssuanServer::new(()).add_command_with_ctx(
+            "LEARN",
+            |_: &mut (), args: Option<&str>, ctx: &mut CmdCtx| {
+                ctx.status("PROGRESS", "learncard k 0 0")?;
+                ctx.status("SERIALNO", args.unwrap_or_default())?;
+                let err = ctx.status("PROGRESS", &"a".repeat(1000)).unwrap_err();
+                assert_eq!(err.kind(), std::io::ErrorKind::InvalidInput);
+                Ok::<_, std::io::Error>(Response::ok())
+            },
+        );
+
+        let output = serve(&mut server, "LEARN 100%25\nLEARN\n");
+        assert_eq!(
+            output,
+            "OK how can I serve you?\n\
+            S PROGRESS learncard k 0 0\n\
+            S SERIALNO 100%25\n\
+            OK success\n\
+            S PROGRESS learncard k 0 0\n\
+            S SERIALNO\n\
+            OK success\n"
+        );
+    }
+
+    #[test]
+    fn status_lines_are_limited() {
+        let mut server = AssuanServer::new(())
+            .with_max_status_lines(2)
+            .add_command_with_ctx(
+                "FLOOD",
+                |_: &mut (), _args: Option<&str>, ctx: &mut CmdCtx| {
+                    // Buggy handler ignores the errors
+                    for _ in 0..100 {
+                        let _ = ctx.status("PROGRESS", "flood");
+                    }
+                    Ok::<_, std::io::Error>(Response::ok())
+                },
+            )
+            .add_command_with_ctx(
+                "WARN",
+                |_: &mut (), args: Option<&str>, ctx: &mut CmdCtx| {
+                    ctx.status("PROGRESS", "warn")?;
+                    let mut resp = Response::ok();
+                    for _ in 0..args.unwrap_or_default().parse().unwrap() {
+                        resp = resp.with_status("WARNING", "weak-key").unwrap();
+                    }
+                    Ok::<_, std::io::Error>(resp)
+                },
+            );
+
+        let output = serve(
+            &mut server,
+            "FLOOD
+WARN 1
+WARN 2
+WARN 1
+",
+        );
+        assert_eq!(
+            output,
+            "OK how can I serve you?\n\
+            S PROGRESS flood\n\
+            S PROGRESS flood\n\
+            ERR 63 Too many status lines\n\
+            S PROGRESS warn\n\
+            S WARNING weak-key\n\
+            OK success\n\
+            S PROGRESS warn\n\
+            ERR 63 Too many status lines\n\
+            S PROGRESS warn\n\
+            S WARNING weak-key\n\
+            OK success\n"
+        );
+    }
+
+    #[test]
+    fn captured_output_is_exact_wire_bytes() {
+        let output = AssuanServer::new(()).capture_output(b"NOP\nBYE\n").unwrap();
+        assert_eq!(output, b"OK how can I serve you?\nOK success\nOK success\n");
+    }
+
+    #[test]
+    fn closing_secret_data_response() {
+        let mut server =
+            AssuanServer::new(()).add_command("GETPIN", |_: &mut (), _args: Option<&str>| {
+                let mut secret = crate::response::SecretData::default();
+                secret.append("hunter2")?;
+                Ok::<_, crate::response::TooLong>(Response::from(secret).close_connection(true))
+            });
+
+        let output = serve(&mut server, "GETPIN\nNOP\nBYE\n");
+        assert_eq!(
+            output,
+            "OK how can I serve you?\n\
+            D hunter2\n\
+            OK success\n"
+        );
+    }
+
+    #[test]
+    fn inquire_read_errors_close_connection() {
+        let mut server = AssuanServer::new(())
+            .add_command("GREET", greet)
+            .add_command_with_ctx(
+                "PKDECRYPT",
+                |_: &mut (), _args: Option<&str>, ctx: &mut CmdCtx| {
+                    // Even if handler ignores the error, the connection is closed
+                    let _ = ctx.inquire_bytes("CIPHERTEXT", 8);
+                    Ok::<_, std::io::Error>(Response::ok())
+                },
+            );
+
+        for cmd in ["GREET", "PKDECRYPT"] {
+            let long_line = "a".repeat(crate::MAX_LINE_SIZE);
+            let output = serve(&mut server, &format!("{cmd}\nD {long_line}\nEND\nNOP\n"));
+            assert!(
+                output.ends_with("\nERR 263 line is too long\n"),
+                "{cmd}: {output}"
+            );
+        }
+    }
+
+    #[test]
+    fn commands_are_case_insensitive() {
+        let mut server = AssuanServer::new(())
+            .add_command("GETPIN", |_: &mut (), _args: Option<&str>| {
+                Response::data("1234")
+            })
+            .set_info("flavor", "tty");
+        // Session is closed by `bye`, so the last `NOP` isn't served
+        let output = serve(&mut server, "GETpin\nnop\ngetinfo flavor\nhelp\nbye\nNOP\n");
+        assert_eq!(
+            output,
+            "OK how can I serve you?\n\
+            D 1234\nOK success\n\
+            OK success\n\
+            D tty\nOK success\n\
+            # NOP\n# BYE\n# RESET\n# GETPIN\n# GETINFO\n# HELP\nOK success\n\
+            OK success\n"
+        );
+    }
+
+    #[test]
+    fn long_lines_of_allowed_commands() {
+        let mut server = AssuanServer::new(())
+            .add_command("GREET", greet)
+            .add_command("STORE", |_: &mut (), args: Option<&str>| {
+                Response::ok_with_debug_info(&args.unwrap_or_default().len().to_string())
+            })
+            .with_max_line_size_for("STORE", 4000);
+
+        // Limit includes the command and the final newline
+        let payload = "a".repeat(3993);
+        let output = serve(&mut server, &format!("STORE {payload}\nSTORE {payload}a\n"));
+        assert_eq!(
+            output,
+            "OK how can I serve you?\nOK 3993\nERR 263 line is too long\n"
+        );
+
+        let output = serve(&mut server, &format!("GREET {}\n", &payload[..994]));
+        assert_eq!(
+            output,
+            "OK how can I serve you?\nERR 263 line is too long\n"
+        );
+    }
+
+    #[test]
+    fn max_line_size_is_configurable() {
+        let store = |_: &mut (), args: Option<&str>| {
+            Response::ok_with_debug_info(&args.unwrap_or_default().len().to_string())
+        };
+
+        let mut server = AssuanServer::new(())
+            .add_command("STORE", store)
+            .with_max_line_size(2000);
+        let payload = "a".repeat(1993);
+        let output = serve(&mut server, &format!("STORE {payload}\nSTORE {payload}a\n"));
+        assert_eq!(
+            output,
+            "OK how can I serve you?\nOK 1993\nERR 263 line is too long\n"
+        );
+
+        let mut server = AssuanServer::new(())
+            .add_command("STORE", store)
+            .with_max_line_size(100);
+        let payload = "a".repeat(93);
+        let output = serve(&mut server, &format!("STORE {payload}\nSTORE {payload}a\n"));
+        assert_eq!(
+            output,
+            "OK how can I serve you?\nOK 93\nERR 263 line is too long\n"
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn unix_stream_is_shut_down_after_bye() {
+        use std::io::{Read, Write};
+
+        let mut server = AssuanServer::new(());
+        let (server_end, mut client_end) = std::os::unix::net::UnixStream::pair().unwrap();
+        client_end
+            .set_read_timeout(Some(std::time::Duration::from_secs(5)))
+            .unwrap();
+        client_end.write_all(b"NOP\nBYE\n").unwrap();
+
+        server.serve_unix_stream(&server_end).unwrap();
+
+        // Server end of the socket is still alive, yet the client sees the connection closed
+        let mut output = String::new();
+        client_end.read_to_string(&mut output).unwrap();
+        assert_eq!(output, "OK how can I serve you?\nOK success\nOK success\n");
+        drop(server_end);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn idle_client_times_out() {
+        use std::io::{Read, Write};
+
+        let mut server =
+            AssuanServer::new(()).with_read_timeout(std::time::Duration::from_millis(10));
+        let (server_end, mut client_end) = std::os::unix::net::UnixStream::pair().unwrap();
+        client_end
+            .set_read_timeout(Some(std::time::Duration::from_secs(5)))
+            .unwrap();
+        client_end.write_all(b"NOP\n").unwrap();
+
+        // Client never sends anything else
+        server.serve_unix_stream(&server_end).unwrap();
+
+        let mut output = String::new();
+        client_end.read_to_string(&mut output).unwrap();
+        assert_eq!(
+            output,
+            "OK how can I serve you?\nOK success\nERR 62 client is idle for too long\n"
+        );
+    }
+
+    #[test]
+    fn interactive_command() {
+        /// Reads an extra line from the client and echoes it back
+        fn echo(
+            _: &mut (),
+            args: Option<&str>,
+            conn: &mut dyn crate::conn::ReadWrite,
+        ) -> Result<Option<Response>, std::io::Error> {
+            let mut line = vec![];
+            let mut byte = [0u8];
+            while byte != *b"\n" {
+                conn.read_exact(&mut byte)?;
+                line.extend_from_slice(&byte);
+            }
+            if args == Some("quietly") {
+                return Ok(Some(Response::ok()));
+            }
+            conn.write_all(b"D ")?;
+            conn.write_all(&line)?;
+            conn.write_all(b"OK echoed\n")?;
+            Ok(None)
+        }
+
+        let mut server = AssuanServer::new(()).add_command_interactive("ECHO", echo);
+        let output = serve(
+            &mut server,
+            "ECHO\nhello world\nNOP\nECHO quietly\nNOP\nNOP\nECHO\n",
+        );
+        assert_eq!(
+            output,
+            "OK how can I serve you?\n\
+            D hello world\n\
+            OK echoed\n\
+            OK success\n\
+            OK success\n\
+            OK success\n\
+            ERR 257 failed to fill whole buffer\n"
+        );
+    }
+
+    #[test]
+    fn binary_args() {
+        let mut server = AssuanServer::new(Vec::new())
+            .add_command_bytes("SETNONCE", |nonce: &mut Vec<u8>, args: Option<&[u8]>| {
+                *nonce = args.unwrap_or_default().to_vec();
+                Ok::<_, std::convert::Infallible>(Response::ok())
+            })
+            .add_command("SETTEXT", |text: &mut Vec<u8>, args: Option<&str>| {
+                *text = args.unwrap_or_default().as_bytes().to_vec();
+                Ok::<_, std::convert::Infallible>(Response::ok())
+            });
+
+        let output = serve(
+            &mut server,
+            "SETNONCE a%00%FF b\nSETNONCE %G0\nSETTEXT abc%A\nSETTEXT abc%GG\n",
+        );
+        assert_eq!(
+            output,
+            "OK how can I serve you?\n\
+            OK success\n\
+            ERR 280 malformed percent encoding: non-hex digit at 1\n\
+            ERR 280 malformed percent encoding: truncated escape at 3\n\
+            ERR 280 malformed percent encoding: non-hex digit at 4\n"
+        );
+        assert_eq!(server.service, b"a\x00\xFF b");
+
+        // String args can't carry arbitrary bytes
+        let output = serve(&mut server, "SETTEXT a%00%FF b\n");
+        assert_eq!(output, "OK how can I serve you?\nOK success\n");
+        assert_eq!(server.service, "a\0\u{FF} b".as_bytes());
+    }
+
+    #[test]
+    fn pipelined_responses_are_flushed_before_waiting_for_client() {
+        /// Conn that delivers the input in chunks and checks that all responses are flushed
+        /// before the server asks for more input
+        struct BufferedConn {
+            chunks: std::collections::VecDeque<&'static [u8]>,
+            buffered: Vec<u8>,
+            flushed: Vec<u8>,
+        }
+        impl std::io::Read for BufferedConn {
+            fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+                assert!(
+                    self.buffered.is_empty(),
+                    "server waits for client unflushed"
+                );
+                let Some(chunk) = self.chunks.pop_front() else {
+                    return Ok(0);
+                };
+                buf[..chunk.len()].copy_from_slice(chunk);
+                Ok(chunk.len())
+            }
+        }
+        impl std::io::Write for BufferedConn {
+            fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+                self.buffered.write(buf)
+            }
+            fn flush(&mut self) -> std::io::Result<()> {
+                self.flushed.append(&mut self.buffered);
+                Ok(())
+            }
+        }
+
+        let mut server = AssuanServer::new(()).add_command("GREET", greet);
+        let mut conn = BufferedConn {
+            chunks: [&b"NOP\nNOP\nGREET\n"[..], b"D Bob\nEND\n", b"BYE\n"].into(),
+            buffered: vec![],
+            flushed: vec![],
+        };
+        server.serve_client_conn(&mut conn).unwrap();
+        assert!(conn.buffered.is_empty());
+        assert_eq!(
+            String::from_utf8(conn.flushed).unwrap(),
+            "OK how can I serve you?\n\
+            OK success\n\
+            OK success\n\
+            INQUIRE NAME\n\
+            D Hello, Bob!\n\
+            OK success\n\
+            OK success\n"
+        );
+    }
+
+    #[test]
+    fn client_disconnects_mid_response() {
+        /// Accepts `capacity` bytes, then fails with `kind` error
+        struct FailingWriter {
+            capacity: usize,
+            kind: std::io::ErrorKind,
+        }
+        impl std::io::Write for FailingWriter {
+            fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+                if self.capacity == 0 {
+                    return Err(self.kind.into());
+                }
+                let written = buf.len().min(self.capacity);
+                self.capacity -= written;
+                Ok(written)
+            }
+            fn flush(&mut self) -> std::io::Result<()> {
+                Ok(())
+            }
+        }
+
+        // Response is written in several lines
+        let mut server = AssuanServer::new(()).add_command("DUMP", |_: &mut (), _| {
+            Ok::<_, std::convert::Infallible>(Response::data_stream(&"a".repeat(3000)))
+        });
+        for kind in [
+            std::io::ErrorKind::BrokenPipe,
+            std::io::ErrorKind::ConnectionReset,
+        ] {
+            let write = FailingWriter {
+                capacity: 1500,
+                kind,
+            };
+            let outcome = server.serve_client("DUMP\n".as_bytes(), write).unwrap();
+            assert_eq!(outcome, ServeOutcome::Disconnected);
+        }
+        let outcome = server.serve_client("DUMP\n".as_bytes(), std::io::sink());
+        assert_eq!(outcome.unwrap(), ServeOutcome::Finished);
+
+        // Other errors aren't mistaken for the client disconnecting
+        let write = FailingWriter {
+            capacity: 1500,
+            kind: std::io::ErrorKind::Other,
+        };
+        let err = server.serve_client("DUMP\n".as_bytes(), write).unwrap_err();
+        assert_eq!(err.kind(), std::io::ErrorKind::Other);
+    }
+
+    #[test]
+    fn unknown_commands_fall_back() {
+        let mut server = AssuanServer::new(Vec::new())
+            .add_command("ECHO", |_: &mut Vec<String>, args: Option<&str>| {
+                Response::data(args.unwrap_or_default())
+            })
+            .with_fallback(
+                |forwarded: &mut Vec<String>, cmd: &str, args: Option<&str>| {
+                    forwarded.push(format!("{cmd} {}", args.unwrap_or_default()));
+                    if cmd == "FAIL" {
+                        Err(WithErrorCode {
+                            code: ErrorCode::NOT_SUPPORTED,
+                            error: "not supported by backend",
+                        })
+                    } else {
+                        Ok(Response::ok())
+                    }
+                },
+            )
+            .set_info("flavor", "proxy");
+
+        let output = serve(
+            &mut server,
+            "ECHO hi\nSIGN a%25b\nFAIL\nGETINFO flavor\nGETINFO version\nNOP\n",
+        );
+        assert_eq!(
+            output,
+            format!(
+                "OK how can I serve you?\n\
+                D hi\nOK success\n\
+                OK success\n\
+                ERR {} not supported by backend\n\
+                D proxy\nOK success\n\
+                ERR {} unknown info key\n\
+                OK success\n",
+                ErrorCode::NOT_SUPPORTED.0,
+                ErrorCode::ASS_PARAMETER.0,
+            )
+        );
+        assert_eq!(server.service, ["SIGN a%25b", "FAIL "]);
+    }
+
+    #[test]
+    fn service_is_accessible_after_serving() {
+        let mut server = AssuanServer::new(Vec::new()).add_command(
+            "OPTION",
+            |options: &mut Vec<String>, args: Option<&str>| {
+                options.push(args.unwrap_or_default().to_owned());
+                Ok::<_, std::convert::Infallible>(Response::ok())
+            },
+        );
+        server.service_mut().push("preset".to_owned());
+
+        serve(
+            &mut server,
+            "OPTION ttyname=/dev/pts/1\nOPTION lc-ctype=C\n",
+        );
+        assert_eq!(server.service().len(), 3);
+        assert_eq!(
+            server.into_service(),
+            ["preset", "ttyname=/dev/pts/1", "lc-ctype=C"]
+        );
+    }
+
+    #[test]
+    fn custom_greeting() {
+        let server = AssuanServer::new(());
+        let mut server = server
+            .with_greeting(Some("Pleased to meet you, process 42"))
+            .unwrap();
+        let output = serve(&mut server, "NOP\n");
+        assert_eq!(output, "OK Pleased to meet you, process 42\nOK success\n");
+
+        // Greeting is percent-encoded like any other response
+        let mut server = server.with_greeting(Some("50%\nready")).unwrap();
+        assert_eq!(serve(&mut server, ""), "OK 50%25%0Aready\n");
+
+        let mut server = server.with_greeting(None).unwrap();
+        assert_eq!(serve(&mut server, ""), "OK\n");
+        let mut server = server.with_greeting(Some("")).unwrap();
+        assert_eq!(serve(&mut server, ""), "OK\n");
+
+        let too_long = "a".repeat(crate::response::Ok::MAX_BYTES + 1);
+        assert!(server.with_greeting(Some(&too_long)).is_err());
+        let longest = "a".repeat(crate::response::Ok::MAX_BYTES);
+        assert!(AssuanServer::new(()).with_greeting(Some(&longest)).is_ok());
+        // Limit applies to percent-encoded greeting, so it's rejected right away rather than
+        // when it's sent to the client
+        let escaped = "%".repeat(crate::response::Ok::MAX_BYTES / 3 + 1);
+        assert!(AssuanServer::new(()).with_greeting(Some(&escaped)).is_err());
+    }
+
+    #[test]
+    fn static_info() {
+        let mut server = AssuanServer::new(())
+            .set_info("flavor", "curses")
+            .set_info("version", "1.0")
+            .set_info("flavor", "tty");
+        let output = serve(
+            &mut server,
+            "GETINFO flavor\nGETINFO version\nGETINFO uptime\nGETINFO\n",
+        );
+        assert_eq!(
+            output,
+            "OK how can I serve you?\n\
+            D tty\n\
+            OK success\n\
+            D 1.0\n\
+            OK success\n\
+            ERR 280 unknown info key\n\
+            ERR 280 unknown info key\n"
+        );
+    }
+
+    #[test]
+    fn info_providers() {
+        let mut server = AssuanServer::new(3)
+            .set_info("flavor", "tty")
+            .set_info("version", "1.0")
+            .add_getinfo("version", |_: &i32| {
+                Ok::<_, std::convert::Infallible>("2.0".to_owned())
+            })
+            .add_getinfo("counter", |counter: &i32| match counter {
+                0.. => Ok(counter.to_string()),
+                _ => Err(WithErrorCode {
+                    code: ErrorCode::INV_STATE,
+                    error: "negative counter",
+                }),
+            })
+            .add_command("DEC", |counter: &mut i32, _args: Option<&str>| {
+                *counter -= 4;
+                Ok::<_, std::convert::Infallible>(Response::ok())
+            });
+        let output = serve(
+            &mut server,
+            "GETINFO flavor\nGETINFO version\nGETINFO counter\nDEC\nGETINFO counter\nGETINFO uptime\nGETINFO pid\n",
+        );
+        assert_eq!(
+            output,
+            format!(
+                "OK how can I serve you?\n\
+                D tty\n\
+                OK success\n\
+                D 2.0\n\
+                OK success\n\
+                D 3\n\
+                OK success\n\
+                OK success\n\
+                ERR 156 negative counter\n\
+                ERR 280 unknown info key\n\
+                D {}\n\
+                OK success\n",
+                std::process::id()
+            )
+        );
+    }
+
+    #[test]
+    fn consecutive_ignored_lines_are_limited() {
+        let mut server = AssuanServer::new(()).with_max_consecutive_ignored_lines(3);
+
+        let output = serve(&mut server, "\n# comment\n\nNOP\n\n\n# comment\nNOP\n");
+        assert_eq!(output, "OK how can I serve you?\nOK success\nOK success\n");
+
+        let output = serve(&mut server, "NOP\n\n\n# comment\n\nNOP\n");
+        assert_eq!(
+            output,
+            "OK how can I serve you?\n\
+            OK success\n\
+            ERR 257 too many consecutive empty or comment lines\n"
+        );
+    }
+
+    #[cfg(feature = "async")]
+    fn serve_async<S, L: CmdList<S>>(server: &mut AssuanServer<S, L>, input: &str) -> String {
+        use std::{
+            future::Future,
+            task::{Context, Poll, Waker},
+        };
+
+        let mut output = vec![];
+        {
+            let serving = std::pin::pin!(server.serve_client_async(input.as_bytes(), &mut output));
+            // Reading from a slice and writing into a vec never pend
+            let Poll::Ready(result) = serving.poll(&mut Context::from_waker(Waker::noop())) else {
+                panic!("serving is pending")
+            };
+            result.unwrap();
+        }
+        String::from_utf8(output).unwrap()
+    }
+
+    #[cfg(feature = "async")]
+    #[test]
+    fn serves_client_asynchronously() {
+        let mut server = AssuanServer::new(())
+            .add_command("GREET", greet)
+            .add_command_with_ctx(
+                "UNLOCK",
+                |_: &mut (), _args: Option<&str>, ctx: &mut CmdCtx| {
+                    ctx.inquire("PASSPHRASE")?;
+                    Ok::<_, std::io::Error>(Response::ok())
+                },
+            )
+            .set_info("flavor", "async");
+
+        let input = "GREET\n# comment\nD Bob\nEND\nGETINFO flavor\nUNLOCK\nNOP\nGREET\nCAN\nBYE\n";
+        assert_eq!(
+            serve_async(&mut server, input),
+            "OK how can I serve you?\n\
+            INQUIRE NAME\n\
+            D Hello, Bob!\n\
+            OK success\n\
+            D async\n\
+            OK success\n\
+            ERR 257 connection isn't available to command handlers when serving asynchronously\n\
+            OK success\n\
+            INQUIRE NAME\n\
+            ERR 277 inquiry canceled by client\n\
+            OK success\n"
+        );
+
+        // Without handlers talking to the client, behavior is identical to sync serving
+        let input = "GREET\nD Alice\nEND\nUNKNOWN\nGETINFO\nBYE\n";
+        assert_eq!(serve_async(&mut server, input), serve(&mut server, input));
+    }
+
+    #[test]
+    fn reset_calls_hook() {
+        let mut server = AssuanServer::new(());
+        let output = serve(&mut server, "RESET\n");
+        assert_eq!(output, "OK how can I serve you?\nOK success\n");
+
+        let mut server = AssuanServer::new(Some("stale description".to_owned()))
+            .add_command(
+                "GETDESC",
+                |desc: &mut Option<String>, _args: Option<&str>| {
+                    Response::data(desc.as_deref().unwrap_or("none"))
+                },
+            )
+            .add_reset_handler(|desc: &mut Option<String>| *desc = None);
+        let output = serve(&mut server, "GETDESC\nRESET\nGETDESC\n");
+        assert_eq!(
+            output,
+            "OK how can I serve you?\n\
+            D stale description\n\
+            OK success\n\
+            OK success\n\
+            D none\n\
+            OK success\n"
+        );
+    }
+
+    #[test]
+    fn args_len_is_limited() {
+        let mut server = AssuanServer::new(String::new()).add_command_with_max_args_len(
+            "SETDESC",
+            5,
+            |desc: &mut String, args: Option<&str>| {
+                *desc = args.unwrap_or_default().to_owned();
+                Ok::<_, std::convert::Infallible>(Response::ok())
+            },
+        );
+
+        // Limit applies to decoded args: `%25%25%25` is 3 bytes long
+        let output = serve(
+            &mut server,
+            "SETDESC %25%25%25ab\nSETDESC abcdef\nSETDESC %GG\nSETDESC\n",
+        );
+        assert_eq!(
+            output,
+            "OK how can I serve you?\n\
+            OK success\n\
+            ERR 67 arguments exceed 5 bytes\n\
+            ERR 280 malformed percent encoding: non-hex digit at 1\n\
+            OK success\n"
+        );
+        assert_eq!(server.service, "");
+    }
+
+    #[test]
+    fn raw_args() {
+        let mut server = AssuanServer::new(Vec::new()).add_command_raw(
+            "SETKEYINFO",
+            |keyinfos: &mut Vec<String>, args: Option<&str>| {
+                keyinfos.push(args.unwrap_or_default().to_owned());
+                Ok::<_, std::convert::Infallible>(Response::ok())
+            },
+        );
+
+        // Malformed encoding isn't rejected either: it's up to the handler to decode args
+        let output = serve(
+            &mut server,
+            "SETKEYINFO n/%2541\nSETKEYINFO %GG\nSETKEYINFO\n",
+        );
+        assert_eq!(
+            output,
+            "OK how can I serve you?\nOK success\nOK success\nOK success\n"
+        );
+        assert_eq!(server.service, ["n/%2541", "%GG", ""]);
+    }
+
+    #[test]
+    fn namespaced_commands() {
+        let mut server = AssuanServer::new(Vec::new())
+            .add_command_prefix("SCD", |requests: &mut Vec<String>, request: &str| {
+                requests.push(request.to_owned());
+                Response::data("D2760001240102000005000011480000")
+            })
+            .add_command("SERIALNO", |_: &mut Vec<String>, _args: Option<&str>| {
+                Response::data("local")
+            });
+
+        let output = serve(
+            &mut server,
+            "SCD SERIALNO\nSERIALNO\nSCD GETINFO app%20list\nSCD\n",
+        );
+        assert_eq!(
+            output,
+            "OK how can I serve you?\n\
+            D D2760001240102000005000011480000\nOK success\n\
+            D local\nOK success\n\
+            D D2760001240102000005000011480000\nOK success\n\
+            D D2760001240102000005000011480000\nOK success\n"
+        );
+        assert_eq!(server.service, ["SERIALNO", "GETINFO app%20list", ""]);
+    }
+
+    #[test]
+    fn sessions_are_served_until_client_disconnects() {
+        let mut server = AssuanServer::new(());
+        let mut output = vec![];
+        let mut conn = crate::conn::Conn {
+            read: "NOP\nBYE\nBYE\nNOP\n".as_bytes(),
+            write: &mut output,
+        };
+        server.serve_sessions(&mut conn).unwrap();
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "OK how can I serve you?\n\
+            OK success\n\
+            OK success\n\
+            OK how can I serve you?\n\
+            OK success\n\
+            OK how can I serve you?\n\
+            OK success\n"
+        );
+    }
+
+    #[test]
+    fn greeting_is_sent_before_pipelined_requests() {
+        /// Records what was sent by every flush
+        #[derive(Default)]
+        struct FlushLog {
+            buffered: Vec<u8>,
+            flushes: Vec<String>,
+        }
+        impl std::io::Write for FlushLog {
+            fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+                self.buffered.write(buf)
+            }
+            fn flush(&mut self) -> std::io::Result<()> {
+                if !self.buffered.is_empty() {
+                    let sent = std::mem::take(&mut self.buffered);
+                    self.flushes.push(String::from_utf8(sent).unwrap());
+                }
+                Ok(())
+            }
+        }
+
+        let mut server = AssuanServer::new(()).require_greeting_sent(true);
+        let mut conn = crate::conn::Conn {
+            read: "NOP\nBYE\nNOP\nBYE\n".as_bytes(),
+            write: FlushLog::default(),
+        };
+        server.serve_sessions(&mut conn).unwrap();
+        assert_eq!(
+            conn.write.flushes,
+            [
+                "OK how can I serve you?\n",
+                "OK success\nOK success\n",
+                "OK how can I serve you?\n",
+                "OK success\nOK success\n",
+                "OK how can I serve you?\n",
+            ]
+        );
+    }
+
+    #[test]
+    fn handler_shuts_down_server() {
+        let mut server =
+            AssuanServer::new(()).add_command("SHUTDOWN", |_: &mut (), _args: Option<&str>| {
+                Ok::<_, std::convert::Infallible>(Response::ok_shutting_down())
+            });
+        let mut output = vec![];
+        let mut conn = crate::conn::Conn {
+            read: "NOP\nBYE\nSHUTDOWN\nNOP\n".as_bytes(),
+            write: &mut output,
+        };
+        server.serve_sessions(&mut conn).unwrap();
+        assert!(server.is_shutdown_requested());
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "OK how can I serve you?\n\
+            OK success\n\
+            OK success\n\
+            OK how can I serve you?\n\
+            OK success\n"
+        );
+    }
+
+    #[test]
+    fn help_lists_commands() {
+        let mut server = AssuanServer::new(())
+            .add_command("GREET", greet)
+            .add_reset_handler(|_: &mut ()| ())
+            .add_getinfo("flavor", |_: &()| {
+                Ok::<_, std::convert::Infallible>("test".to_owned())
+            })
+            .add_command_bytes("SETNONCE", |_: &mut (), _args: Option<&[u8]>| {
+                Ok::<_, std::convert::Infallible>(Response::ok())
+            });
+        let output = serve(&mut server, "HELP\n");
+        assert_eq!(
+            output,
+            "OK how can I serve you?\n\
+            # NOP\n\
+            # BYE\n\
+            # RESET\n\
+            # GREET\n\
+            # SETNONCE\n\
+            # GETINFO\n\
+            # HELP\n\
+            OK success\n"
+        );
+
+        let mut server = AssuanServer::without_predefined_cmds(()).add_command("GREET", greet);
+        let output = serve(&mut server, "HELP\n");
+        assert_eq!(
+            output,
+            "OK how can I serve you?\n# GREET\n# GETINFO\n# HELP\nOK success\n"
+        );
+    }
+
+    #[test]
+    fn server_with_custom_base_cmds() {
+        /// Answers `PING` in every server it's a base of
+        struct Ping;
+
+        impl CmdList<()> for Ping {
+            type Error = std::convert::Infallible;
+
+            fn handle_encoded(
+                &mut self,
+                cmd: &str,
+                _state: &mut (),
+                _params: Option<&str>,
+                _ctx: &mut CmdCtx<'_>,
+            ) -> Option<Result<Response, Self::Error>> {
+                cmd.eq_ignore_ascii_case("PING")
+                    .then(|| Ok(Response::ok_with_debug_info("pong").unwrap()))
+            }
+
+            fn command_names(&self) -> impl Iterator<Item = &'static str> {
+                ["PING"].into_iter()
+            }
+        }
+
+        let mut server = AssuanServer::with_base((), Ping).add_command("GREET", greet);
+        let output = serve(&mut server, "PING\nHELP\nNOP\n");
+        assert_eq!(
+            output,
+            "OK how can I serve you?\n\
+            OK pong\n\
+            # PING\n\
+            # GREET\n\
+            # GETINFO\n\
+            # HELP\n\
+            OK success\n\
+            ERR 275 Unknown command\n"
+        );
+
+        let mut server = AssuanServer::with_base((), router::PredefinedCmds::with_tail(Ping));
+        let output = serve(&mut server, "ping\nBYE\n");
+        assert_eq!(output, "OK how can I serve you?\nOK pong\nOK success\n");
+    }
+
+    #[test]
+    fn connection_lifetime_is_limited() {
+        let clock = MockClock::new();
+        let mut server = AssuanServer::new(clock.clone())
+            .add_command("WAIT", |clock: &mut MockClock, _args: Option<&str>| {
+                clock.advance(Duration::from_secs(10));
+                Ok::<_, std::convert::Infallible>(Response::ok())
+            })
+            .with_clock(clock)
+            .with_max_connection_duration(Duration::from_secs(15));
+
+        let output = serve(&mut server, "WAIT\nNOP\nWAIT\nNOP\nBYE\n");
+        assert_eq!(
+            output,
+            "OK how can I serve you?\n\
+            OK success\n\
+            OK success\n\
+            OK success\n\
+            ERR 62 connection lifetime exceeded\n"
+        );
+    }
+
+    #[test]
+    fn unmet_precondition_prevents_handler_invocation() {
+        #[derive(Default)]
+        struct State {
+            desc: Option<String>,
+            pin_asked: bool,
+        }
+
+        let mut server = AssuanServer::new(State::default())
+            .add_command("SETDESC", |state: &mut State, desc: Option<&str>| {
+                state.desc = desc.map(String::from);
+                Ok::<_, std::convert::Infallible>(Response::ok())
+            })
+            .add_command_with_precondition(
+                "GETPIN",
+                |state: &State| {
+                    if state.desc.is_some() {
+                        Ok(())
+                    } else {
+                        Err(WithErrorCode {
+                            code: ErrorCode::MISSING_VALUE,
+                            error: "SETDESC must be called first",
+                        })
+                    }
+                },
+                |state: &mut State, _args: Option<&str>| {
+                    state.pin_asked = true;
+                    Ok::<_, std::convert::Infallible>(Response::ok())
+                },
+            );
+
+        let output = serve(&mut server, "GETPIN\nBYE\n");
+        assert_eq!(
+            output,
+            "OK how can I serve you?\n\
+            ERR 128 SETDESC must be called first\n\
+            OK success\n"
+        );
+        assert!(!server.service.pin_asked);
+
+        let output = serve(&mut server, "SETDESC hi\nGETPIN\nBYE\n");
+        assert_eq!(
+            output,
+            "OK how can I serve you?\n\
+            OK success\n\
+            OK success\n\
+            OK success\n"
+        );
+        assert!(server.service.pin_asked);
+    }
+
+    #[test]
+    fn strict_mode() {
+        let new_server = |strict| {
+            AssuanServer::new(())
+                .add_command("greet", |_: &mut (), _args: Option<&str>| {
+                    Ok::<_, std::convert::Infallible>(Response::ok())
+                })
+                .add_command("OPTION", |_: &mut (), _args: Option<&str>| {
+                    Ok::<_, std::convert::Infallible>(Response::ok())
+                })
+                .strict(strict)
+        };
+        let input = "NOP \0\n# comment \0\nNO\x01P\ngreet\nOPTION =value\nOPTION\nOPTION --name = value\nBYE\n";
+
+        let output = serve(&mut new_server(true), input);
+        assert_eq!(
+            output,
+            "OK how can I serve you?\n\
+            ERR 261 line contains NUL character\n\
+            ERR 261 line contains NUL character\n\
+            ERR 276 command contains control characters\n\
+            ERR 276 command must be in uppercase\n\
+            ERR 280 malformed option\n\
+            ERR 280 malformed option\n\
+            OK success\n\
+            OK success\n"
+        );
+
+        let output = serve(&mut new_server(false), input);
+        assert_eq!(
+            output,
+            "OK how can I serve you?\n\
+            OK success\n\
+            ERR 275 Unknown command\n\
+            OK success\n\
+            OK success\n\
+            OK success\n\
+            OK success\n\
+            OK success\n"
+        );
     }
 }
@@ -1,12 +1,25 @@
 use std::io;
 
+use zeroize::{Zeroize, Zeroizing};
+
 /// Parses lines from the [`io::Read`]
 ///
-/// Lines are restricted to be no more than 1000 bytes long, as specified in assuan specs
+/// Lines are restricted to be no more than 1000 bytes long, as specified in assuan specs,
+/// unless a larger limit is given for the command via
+/// [`read_line_with_limit`](Self::read_line_with_limit)
+///
+/// Received bytes may contain secrets, e.g. replies to inquiries. Once the line is consumed
+/// by the next `read_line` call, its bytes are overwritten. The whole buffer is zeroized
+/// on drop.
 pub struct LineReader {
     bytes_read: usize,
     newline_found: Option<usize>,
     buffer: [u8; crate::MAX_LINE_SIZE],
+    /// Buffer allocated for a line exceeding [`MAX_LINE_SIZE`](crate::MAX_LINE_SIZE)
+    ///
+    /// When it's set, it's used instead of `buffer`. It's freed once bytes left in it fit
+    /// into `buffer`.
+    long_line: Option<Zeroizing<Vec<u8>>>,
 }
 
 impl LineReader {
@@ -16,63 +29,291 @@ impl LineReader {
             bytes_read: 0,
             newline_found: None,
             buffer: [0u8; crate::MAX_LINE_SIZE],
+            long_line: None,
         }
     }
 
     /// Reads a line from the `reader`
     ///
-    /// Returns the line without trailing newline character `\n`. If there's no data available, returns `None`.
+    /// Returns the line without trailing newline character `\n`. Lines ending with `\r\n`
+    /// are tolerated: a single `\r` preceding `\n` is stripped. If there's no data available, returns `None`.
     /// Returns error if `reader` returned error, or if invalid bytes received.
     pub fn read_line(
         &mut self,
         reader: &mut impl io::Read,
     ) -> Result<Option<&[u8]>, ReadLineError> {
+        self.read_line_with_limit(reader, |_| crate::MAX_LINE_SIZE)
+    }
+
+    /// Reads a line from the `reader`, size of the line is limited depending on the command
+    ///
+    /// `max_line_size` is called with the first word of the line (i.e. the command) and
+    /// returns max size of the line including the final `\n`. Once the line exceeds
+    /// [`MAX_LINE_SIZE`](crate::MAX_LINE_SIZE), a larger buffer is allocated if the command
+    /// allows it. Otherwise, it's the same as [`read_line`](Self::read_line).
+    pub fn read_line_with_limit(
+        &mut self,
+        reader: &mut impl io::Read,
+        max_line_size: impl Fn(&[u8]) -> usize,
+    ) -> Result<Option<&[u8]>, ReadLineError> {
+        let line_end = match self.buffered_line() {
+            Some(line_end) => line_end,
+            None => loop {
+                // Read bytes until we find a newline character
+                if self.bytes_read == self.storage().len() {
+                    self.grow(&max_line_size)?;
+                }
+                let chunk_start = self.bytes_read;
+                let chunk_size = reader
+                    .read(&mut self.storage_mut()[chunk_start..])
+                    .map_err(ReadLineError::Read)?;
+                match self.accept_chunk(chunk_size)? {
+                    Chunk::Line(line_end) => break line_end,
+                    Chunk::Eof => return Ok(None),
+                    Chunk::Incomplete => (),
+                }
+            },
+        };
+        self.checked_line(line_end, max_line_size).map(Some)
+    }
+
+    /// Reads a line from the async `reader`
+    ///
+    /// Same as [`read_line`](Self::read_line), but awaits on the reader instead of blocking
+    #[cfg(feature = "async")]
+    pub async fn read_line_async(
+        &mut self,
+        reader: &mut (impl tokio::io::AsyncRead + Unpin),
+    ) -> Result<Option<&[u8]>, ReadLineError> {
+        self.read_line_async_with_limit(reader, |_| crate::MAX_LINE_SIZE)
+            .await
+    }
+
+    /// Reads a line from the async `reader`, size of the line is limited depending on
+    /// the command
+    ///
+    /// Same as [`read_line_with_limit`](Self::read_line_with_limit), but awaits on the reader
+    /// instead of blocking
+    #[cfg(feature = "async")]
+    pub async fn read_line_async_with_limit(
+        &mut self,
+        reader: &mut (impl tokio::io::AsyncRead + Unpin),
+        max_line_size: impl Fn(&[u8]) -> usize,
+    ) -> Result<Option<&[u8]>, ReadLineError> {
+        use tokio::io::AsyncReadExt;
+
+        let line_end = match self.buffered_line() {
+            Some(line_end) => line_end,
+            None => loop {
+                if self.bytes_read == self.storage().len() {
+                    self.grow(&max_line_size)?;
+                }
+                let chunk_start = self.bytes_read;
+                let chunk_size = reader
+                    .read(&mut self.storage_mut()[chunk_start..])
+                    .await
+                    .map_err(ReadLineError::Read)?;
+                match self.accept_chunk(chunk_size)? {
+                    Chunk::Line(line_end) => break line_end,
+                    Chunk::Eof => return Ok(None),
+                    Chunk::Incomplete => (),
+                }
+            },
+        };
+        self.checked_line(line_end, max_line_size).map(Some)
+    }
+
+    /// Returns the line ending at `line_end` if it doesn't exceed the limit of its command
+    ///
+    /// Limits smaller than [`MAX_LINE_SIZE`](crate::MAX_LINE_SIZE) can only be checked once
+    /// the line is complete. Trailing `\r` is stripped from the returned line.
+    fn checked_line(
+        &self,
+        line_end: usize,
+        max_line_size: impl Fn(&[u8]) -> usize,
+    ) -> Result<&[u8], ReadLineError> {
+        let line = &self.storage()[..line_end];
+        if line_end >= max_line_size(command(line)) {
+            return Err(ReadLineError::LineTooLong);
+        }
+        Ok(line.strip_suffix(b"\r").unwrap_or(line))
+    }
+
+    /// Buffer that currently holds the received bytes
+    fn storage(&self) -> &[u8] {
+        match &self.long_line {
+            Some(long_line) => long_line,
+            None => &self.buffer,
+        }
+    }
+
+    /// Mutable reference to the buffer that currently holds the received bytes
+    fn storage_mut(&mut self) -> &mut [u8] {
+        match &mut self.long_line {
+            Some(long_line) => long_line,
+            None => &mut self.buffer,
+        }
+    }
+
+    /// Moves the incomplete line into a larger buffer, if the command allows it
+    ///
+    /// Returns error if the line can't grow anymore
+    fn grow(&mut self, max_line_size: impl Fn(&[u8]) -> usize) -> Result<(), ReadLineError> {
+        if self.long_line.is_some() {
+            // Line has already grown up to its limit
+            return Err(ReadLineError::LineTooLong);
+        }
+        let max_line_size = max_line_size(command(&self.buffer));
+        if max_line_size <= self.buffer.len() {
+            return Err(ReadLineError::LineTooLong);
+        }
+
+        // Buffer is allocated once at its full size, so it's never reallocated leaving
+        // copies of the data behind
+        let mut long_line = Zeroizing::new(vec![0u8; max_line_size]);
+        long_line[..self.bytes_read].copy_from_slice(&self.buffer[..self.bytes_read]);
+        self.buffer.zeroize();
+        self.long_line = Some(long_line);
+        Ok(())
+    }
+
+    /// Frees the buffer allocated for the long line if the remaining bytes fit into
+    /// the regular buffer
+    fn shrink(&mut self) {
+        if self.bytes_read > self.buffer.len() {
+            return;
+        }
+        if let Some(long_line) = self.long_line.take() {
+            self.buffer[..self.bytes_read].copy_from_slice(&long_line[..self.bytes_read]);
+        }
+    }
+
+    /// Consumes the previously returned line and looks for the next one among unprocessed
+    /// bytes left from previous reads
+    ///
+    /// Returns position of the line end if the line is found
+    fn buffered_line(&mut self) -> Option<usize> {
+        self.consume_line();
+        let newline_pos = self.storage()[..self.bytes_read]
+            .iter()
+            .position(|c| *c == b'\n')?;
+        self.newline_found = Some(newline_pos);
+        Some(newline_pos)
+    }
+
+    /// Accounts `chunk_size` bytes that were just read into the end of the buffer
+    fn accept_chunk(&mut self, chunk_size: usize) -> Result<Chunk, ReadLineError> {
+        let chunk_start = self.bytes_read;
+        self.bytes_read += chunk_size;
+
+        match (chunk_start, chunk_size) {
+            (0, 0) => return Ok(Chunk::Eof),
+            (_, 0) => return Err(io::ErrorKind::UnexpectedEof.into()),
+            _ => (),
+        }
+        match self.storage()[chunk_start..self.bytes_read]
+            .iter()
+            .position(|c| *c == b'\n')
+        {
+            Some(newline_pos) => {
+                let newline_pos = newline_pos + chunk_start;
+                self.newline_found = Some(newline_pos);
+                Ok(Chunk::Line(newline_pos))
+            }
+            None => Ok(Chunk::Incomplete),
+        }
+    }
+
+    /// Removes the line returned by the last [`read_line`](Self::read_line) call from the buffer
+    fn consume_line(&mut self) {
         if let Some(newline_pos) = self.newline_found.take() {
             // We still store a line from previous `read_line` invocation. Gotta clear
             // that out
             self.bytes_read -= newline_pos + 1;
-            self.buffer.copy_within(newline_pos + 1.., 0);
+            let bytes_read = self.bytes_read;
+            let storage = self.storage_mut();
+            storage.copy_within(newline_pos + 1.., 0);
+            // Bytes left behind after the shift are stale copies of the data, we don't want
+            // them to linger in memory
+            storage[bytes_read..].zeroize();
+            self.shrink();
         }
+    }
 
-        // There's some unprocessed bytes from previous `read_line` invocation.
-        // Check if it has a newline.
-        if self.bytes_read != 0 {
-            if let Some(pos) = self.buffer[..self.bytes_read]
-                .iter()
-                .position(|c| *c == b'\n')
-            {
-                self.newline_found = Some(pos);
-                return Ok(Some(&self.buffer[..pos]));
-            }
-        }
+    /// Takes bytes that were read from the reader but haven't been returned as a line yet
+    ///
+    /// Copies up to `buf.len()` of such bytes into `buf` and returns their number. Taken
+    /// bytes are removed from the buffer, as well as the line returned by the last
+    /// [`read_line`](Self::read_line) call.
+    pub fn take_buffered(&mut self, buf: &mut [u8]) -> usize {
+        self.consume_line();
+        let taken = self.bytes_read.min(buf.len());
+        let bytes_read = self.bytes_read;
+        let storage = self.storage_mut();
+        buf[..taken].copy_from_slice(&storage[..taken]);
+        storage.copy_within(taken..bytes_read, 0);
+        storage[bytes_read - taken..].zeroize();
+        self.bytes_read -= taken;
+        self.shrink();
+        taken
+    }
 
-        // Read bytes until we find a newline character
-        while self.bytes_read < crate::MAX_LINE_SIZE {
-            let chunk_start = self.bytes_read;
-            let chunk_size = reader
-                .read(&mut self.buffer[chunk_start..])
-                .map_err(ReadLineError::Read)?;
-            self.bytes_read += chunk_size;
-
-            match (chunk_start, chunk_size) {
-                (0, 0) => return Ok(None),
-                (_, 0) => return Err(io::ErrorKind::UnexpectedEof.into()),
-                _ => (),
-            }
-            if let Some(newline_pos) = self.buffer[chunk_start..chunk_start + chunk_size]
-                .iter()
-                .position(|c| *c == b'\n')
-                .map(|p| p + chunk_start)
-            {
-                self.newline_found = Some(newline_pos);
-                return Ok(Some(&self.buffer[..newline_pos]));
-            }
+    /// Indicates whether a complete line following the last returned one is already buffered
+    ///
+    /// If it is, next [`read_line`](Self::read_line) call returns it without reading from
+    /// the reader.
+    pub fn has_pending_line(&self) -> bool {
+        let rest = match self.newline_found {
+            Some(newline_pos) => &self.storage()[newline_pos + 1..self.bytes_read],
+            None => &self.storage()[..self.bytes_read],
+        };
+        rest.contains(&b'\n')
+    }
+
+    /// Overwrites the line returned by the last [`read_line`](Self::read_line) call with zeroes
+    ///
+    /// Should be called once the line is processed if it contained sensitive data
+    pub fn zeroize_line(&mut self) {
+        if let Some(newline_pos) = self.newline_found {
+            self.storage_mut()[..newline_pos].zeroize();
         }
+    }
+}
+
+/// Returns the first word of the `line`
+fn command(line: &[u8]) -> &[u8] {
+    match line.iter().position(|x| *x == b' ') {
+        Some(pos) => &line[..pos],
+        None => line,
+    }
+}
 
-        Err(ReadLineError::LineTooLong)
+impl Zeroize for LineReader {
+    /// Discards all received bytes, overwriting them with zeroes
+    fn zeroize(&mut self) {
+        self.buffer.zeroize();
+        self.long_line = None;
+        self.bytes_read = 0;
+        self.newline_found = None;
     }
 }
 
+impl Drop for LineReader {
+    fn drop(&mut self) {
+        self.zeroize();
+    }
+}
+
+/// Outcome of reading a chunk of bytes
+enum Chunk {
+    /// Line is complete, it ends at given position
+    Line(usize),
+    /// Reader has no more data, no bytes were read since the last line
+    Eof,
+    /// Line isn't complete yet
+    Incomplete,
+}
+
 #[derive(Debug)]
 pub enum ReadLineError {
     Read(io::Error),
@@ -166,6 +407,58 @@ mod test {
         assert_eq!(line2, b"line2");
     }
 
+    #[test]
+    fn strips_carriage_return() {
+        let mut reader = LineReader::new();
+        let mut read = read_chunk_by_chunk(&[b"GETPIN\r", b"\nD a\rb\r\r\nEND\n\r\n"]);
+
+        let line = reader.read_line(&mut read).unwrap().unwrap();
+        assert_eq!(line, b"GETPIN");
+        // Only a single `\r` preceding `\n` is stripped
+        let line = reader.read_line(&mut read).unwrap().unwrap();
+        assert_eq!(line, b"D a\rb\r");
+        let line = reader.read_line(&mut read).unwrap().unwrap();
+        assert_eq!(line, b"END");
+        let line = reader.read_line(&mut read).unwrap().unwrap();
+        assert_eq!(line, b"");
+        assert_eq!(reader.read_line(&mut read).unwrap(), None);
+    }
+
+    #[test]
+    fn tells_whether_line_is_pending() {
+        let mut reader = LineReader::new();
+        let mut read = read_chunk_by_chunk(&[b"line1\nline2\nline", b"3\n"]);
+        assert!(!reader.has_pending_line());
+
+        reader.read_line(&mut read).unwrap().unwrap();
+        assert!(reader.has_pending_line());
+        reader.read_line(&mut read).unwrap().unwrap();
+        assert!(!reader.has_pending_line());
+        reader.read_line(&mut read).unwrap().unwrap();
+        assert!(!reader.has_pending_line());
+    }
+
+    #[test]
+    fn takes_buffered_bytes() {
+        let mut reader = LineReader::new();
+        let mut read = read_chunk_by_chunk(&[b"line1\nraw bytes", b"line2\n"]);
+
+        let line1 = reader.read_line(&mut read).unwrap().unwrap();
+        assert_eq!(line1, b"line1");
+
+        let mut buf = [0u8; 4];
+        assert_eq!(reader.take_buffered(&mut buf), 4);
+        assert_eq!(&buf, b"raw ");
+        assert_eq!(reader.take_buffered(&mut buf), 4);
+        assert_eq!(&buf, b"byte");
+        assert_eq!(reader.take_buffered(&mut buf), 1);
+        assert_eq!(&buf[..1], b"s");
+        assert_eq!(reader.take_buffered(&mut buf), 0);
+
+        let line2 = reader.read_line(&mut read).unwrap().unwrap();
+        assert_eq!(line2, b"line2");
+    }
+
     #[test]
     fn reads_one_line_in_pieces() {
         let mut reader = LineReader::new();
@@ -214,6 +507,74 @@ mod test {
         )
     }
 
+    #[test]
+    fn zeroizes_consumed_lines() {
+        let mut reader = LineReader::new();
+        let mut read = read_chunk_by_chunk(&[b"D secret\nD other secret\nEND\n"]);
+
+        let line = reader.read_line(&mut read).unwrap().unwrap();
+        assert_eq!(line, b"D secret");
+        reader.zeroize_line();
+
+        let line = reader.read_line(&mut read).unwrap().unwrap();
+        assert_eq!(line, b"D other secret");
+        reader.zeroize_line();
+
+        let line = reader.read_line(&mut read).unwrap().unwrap();
+        assert_eq!(line, b"END");
+
+        assert!(!reader.buffer.windows(6).any(|w| w == b"secret"));
+    }
+
+    #[test]
+    fn reads_long_line_of_allowed_command() {
+        let mut reader = LineReader::new();
+        let long_line = format!("LONG {}\nSHORT\n", "a".repeat(3994));
+        let chunks: Vec<_> = long_line.as_bytes().chunks(100).collect();
+        let mut read = read_chunk_by_chunk(&chunks);
+        let max_line_size = |cmd: &[u8]| if cmd == b"LONG" { 4000 } else { 1000 };
+
+        let line = reader
+            .read_line_with_limit(&mut read, max_line_size)
+            .unwrap()
+            .unwrap();
+        assert_eq!(line.len(), 3999);
+        assert!(line.starts_with(b"LONG aaa"));
+        assert!(reader.long_line.is_some());
+
+        let line = reader
+            .read_line_with_limit(&mut read, max_line_size)
+            .unwrap()
+            .unwrap();
+        assert_eq!(line, b"SHORT");
+        // Larger buffer is freed once the long line is consumed
+        assert!(reader.long_line.is_none());
+
+        let long_line = format!("LONG {}\n", "a".repeat(3995));
+        let chunks: Vec<_> = long_line.as_bytes().chunks(100).collect();
+        let mut read = read_chunk_by_chunk(&chunks);
+        let err = reader
+            .read_line_with_limit(&mut read, max_line_size)
+            .unwrap_err();
+        assert!(matches!(err, super::ReadLineError::LineTooLong), "{err:?}");
+    }
+
+    #[test]
+    fn zeroizes_buffer() {
+        use zeroize::Zeroize;
+
+        let mut reader = LineReader::new();
+        let mut read = read_chunk_by_chunk(&[b"D secret\nD other secret"]);
+
+        let line = reader.read_line(&mut read).unwrap().unwrap();
+        assert_eq!(line, b"D secret");
+
+        // That's what happens on drop
+        reader.zeroize();
+        assert!(reader.buffer.iter().all(|x| *x == 0));
+        assert!(!reader.has_pending_line());
+    }
+
     #[test]
     fn errors_on_very_large_line() {
         let mut reader = LineReader::new();
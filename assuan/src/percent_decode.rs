@@ -1,26 +1,97 @@
-pub fn percent_decode(x: &str) -> PercentDecoder {
-    PercentDecoder(x.chars())
+//! Decoding of percent-encoded strings
+//!
+//! Assuan escapes special characters as `%XX`, where `XX` are uppercase hex digits. Params of
+//! commands registered via [`AssuanServer::add_command`](crate::AssuanServer::add_command) are
+//! decoded automatically, the functions below are useful for handlers receiving raw params,
+//! e.g. ones registered via [`AssuanServer::add_command_raw`](crate::AssuanServer::add_command_raw).
+
+/// Decodes percent encoding, `+` is kept as is
+///
+/// ### Example
+/// ```rust
+/// use assuan::percent_decode::percent_decode;
+///
+/// let decoded = percent_decode("1+1%3D2").collect::<Result<String, _>>()?;
+/// assert_eq!(decoded, "1+1=2");
+/// # Ok::<_, assuan::percent_decode::MalformedEncoding>(())
+/// ```
+pub fn percent_decode(x: &str) -> PercentDecoder<'_> {
+    PercentDecoder {
+        chars: x.char_indices(),
+        plus_as_space: false,
+        lowercase_hex: false,
+    }
+}
+
+/// Decodes percent encoding, `+` is decoded as a space
+///
+/// Some clients encode spaces as `+`, e.g. in form-encoded option values. Literal `+` is
+/// expected to be escaped as `%2B` then.
+///
+/// ### Example
+/// ```rust
+/// use assuan::percent_decode::percent_decode_plus;
+///
+/// let decoded = percent_decode_plus("1%2B1+is+2").collect::<Result<String, _>>()?;
+/// assert_eq!(decoded, "1+1 is 2");
+/// # Ok::<_, assuan::percent_decode::MalformedEncoding>(())
+/// ```
+pub fn percent_decode_plus(x: &str) -> PercentDecoder<'_> {
+    PercentDecoder {
+        chars: x.char_indices(),
+        plus_as_space: true,
+        lowercase_hex: false,
+    }
+}
+
+/// Decodes percent encoding, lowercase hex digits are accepted
+///
+/// Assuan spec requires escapes to use uppercase hex digits, but some clients emit lowercase
+/// ones. Escapes that aren't hex at all or are truncated are still rejected.
+///
+/// ### Example
+/// ```rust
+/// use assuan::percent_decode::{percent_decode, percent_decode_lenient};
+///
+/// let decoded = percent_decode_lenient("one%0atwo").collect::<Result<String, _>>()?;
+/// assert_eq!(decoded, "one\ntwo");
+/// assert!(percent_decode("one%0atwo").collect::<Result<String, _>>().is_err());
+/// # Ok::<_, assuan::percent_decode::MalformedEncoding>(())
+/// ```
+pub fn percent_decode_lenient(x: &str) -> PercentDecoder<'_> {
+    PercentDecoder {
+        chars: x.char_indices(),
+        plus_as_space: false,
+        lowercase_hex: true,
+    }
 }
 
-pub struct PercentDecoder<'s>(std::str::Chars<'s>);
+/// Iterator over decoded characters, returned by [`percent_decode`], [`percent_decode_plus`]
+/// and [`percent_decode_lenient`]
+pub struct PercentDecoder<'s> {
+    chars: std::str::CharIndices<'s>,
+    plus_as_space: bool,
+    lowercase_hex: bool,
+}
 
 impl<'s> PercentDecoder<'s> {
     fn decode_next(&mut self) -> Result<Option<char>, MalformedEncoding> {
-        match self.0.next() {
-            Some('%') => {
-                let a = self.0.next().ok_or(MalformedEncoding)?;
-                let b = self.0.next().ok_or(MalformedEncoding)?;
-
-                if !a.is_ascii_digit() && !a.is_ascii_uppercase() {
-                    return Err(MalformedEncoding);
-                }
-                if !b.is_ascii_digit() && !b.is_ascii_uppercase() {
-                    return Err(MalformedEncoding);
-                }
-
-                decode_one_char(a, b).map(Some)
+        match self.chars.next() {
+            Some((_, '+')) if self.plus_as_space => Ok(Some(' ')),
+            Some((pos, '%')) => {
+                let lowercase_hex = self.lowercase_hex;
+                let mut hex_digit = || match self.chars.next() {
+                    Some((_, x @ ('0'..='9' | 'A'..='F'))) => Ok(x),
+                    Some((_, x @ 'a'..='f')) if lowercase_hex => Ok(x),
+                    Some((pos, _)) => Err(MalformedEncoding::new(pos, Malformation::InvalidHex)),
+                    None => Err(MalformedEncoding::new(pos, Malformation::Truncated)),
+                };
+                let a = hex_digit()?;
+                let b = hex_digit()?;
+
+                Ok(decode_one_char(a, b))
             }
-            Some(x) => Ok(Some(x)),
+            Some((_, x)) => Ok(Some(x)),
             None => Ok(None),
         }
     }
@@ -34,19 +105,100 @@ impl<'s> Iterator for PercentDecoder<'s> {
     }
 }
 
-pub fn decode_one_char(a: char, b: char) -> Result<char, MalformedEncoding> {
-    let a = a.to_digit(16).ok_or(MalformedEncoding)?;
-    let b = b.to_digit(16).ok_or(MalformedEncoding)?;
+/// Decodes a character escaped as `%ab`
+///
+/// Returns `None` if `a` or `b` is not a hex digit
+pub(crate) fn decode_one_char(a: char, b: char) -> Option<char> {
+    let a = a.to_digit(16)?;
+    let b = b.to_digit(16)?;
+
+    char::from_u32(a * 0x10 + b)
+}
+
+/// Decodes percent encoding into raw bytes
+///
+/// Unlike [`percent_decode`], escaped bytes aren't interpreted as characters, so it can
+/// be used to decode binary data
+pub fn percent_decode_bytes(x: &[u8]) -> PercentDecoderBytes<'_> {
+    PercentDecoderBytes(x.iter().enumerate())
+}
+
+/// Iterator over decoded bytes, returned by [`percent_decode_bytes`]
+pub struct PercentDecoderBytes<'s>(std::iter::Enumerate<std::slice::Iter<'s, u8>>);
+
+impl<'s> Iterator for PercentDecoderBytes<'s> {
+    type Item = Result<u8, MalformedEncoding>;
 
-    char::from_u32(a * 0x10 + b).ok_or(MalformedEncoding)
+    fn next(&mut self) -> Option<Self::Item> {
+        match self.0.next()? {
+            (pos, b'%') => {
+                let mut hex_digit = || match self.0.next() {
+                    Some((_, x @ b'0'..=b'9')) => Ok(x - b'0'),
+                    Some((_, x @ b'A'..=b'F')) => Ok(x - b'A' + 10),
+                    Some((pos, _)) => Err(MalformedEncoding::new(pos, Malformation::InvalidHex)),
+                    None => Err(MalformedEncoding::new(pos, Malformation::Truncated)),
+                };
+                Some(hex_digit().and_then(|a| Ok(a * 0x10 + hex_digit()?)))
+            }
+            (_, x) => Some(Ok(*x)),
+        }
+    }
 }
 
+/// Percent encoding is malformed
 #[derive(Debug)]
-pub struct MalformedEncoding;
+pub struct MalformedEncoding {
+    /// Byte offset in the encoded string where malformation was found
+    ///
+    /// For truncated escape, it's position of `%`, otherwise it's position of invalid digit
+    pub position: usize,
+    /// What's wrong with the encoding
+    pub reason: Malformation,
+}
+
+/// Explains why [percent encoding is malformed](MalformedEncoding)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Malformation {
+    /// String ends in the middle of `%XX` escape
+    Truncated,
+    /// Escape contains a character that's not an uppercase hex digit (or a lowercase one,
+    /// if [decoding is lenient](percent_decode_lenient))
+    InvalidHex,
+}
+
+impl MalformedEncoding {
+    fn new(position: usize, reason: Malformation) -> Self {
+        Self { position, reason }
+    }
+}
+
+impl std::fmt::Display for MalformedEncoding {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let position = self.position;
+        match self.reason {
+            Malformation::Truncated => write!(
+                f,
+                "malformed percent encoding: truncated escape at {position}"
+            ),
+            Malformation::InvalidHex => {
+                write!(f, "malformed percent encoding: non-hex digit at {position}")
+            }
+        }
+    }
+}
+
+impl crate::HasErrorCode for MalformedEncoding {
+    fn code(&self) -> crate::ErrorCode {
+        crate::ErrorCode::ASS_PARAMETER
+    }
+}
 
 #[cfg(test)]
 mod test {
-    use super::percent_decode;
+    use super::{
+        percent_decode, percent_decode_bytes, percent_decode_lenient, percent_decode_plus,
+        Malformation,
+    };
 
     #[test]
     fn test_cases() {
@@ -61,6 +213,44 @@ mod test {
         }
     }
 
+    #[test]
+    fn plus_as_space() {
+        let decode = |input| {
+            percent_decode(input)
+                .collect::<Result<String, _>>()
+                .unwrap()
+        };
+        let decode_plus = |input| {
+            percent_decode_plus(input)
+                .collect::<Result<String, _>>()
+                .unwrap()
+        };
+        assert_eq!(decode("a+b%2B"), "a+b+");
+        assert_eq!(decode_plus("a+b%2B"), "a b+");
+        assert_eq!(decode_plus("%20++"), "   ");
+    }
+
+    #[test]
+    fn lenient_decoding() {
+        let decoded = percent_decode_lenient("%0a%0A%c3%A9")
+            .collect::<Result<String, _>>()
+            .unwrap();
+        assert_eq!(decoded, "\n\n\u{c3}\u{a9}");
+
+        let cases: &[(&str, usize, Malformation)] = &[
+            ("%", 0, Malformation::Truncated),
+            ("ab%a", 2, Malformation::Truncated),
+            ("%FG", 2, Malformation::InvalidHex),
+            ("%gA", 1, Malformation::InvalidHex),
+        ];
+        for (input, position, reason) in cases {
+            let err = percent_decode_lenient(input)
+                .collect::<Result<String, _>>()
+                .unwrap_err();
+            assert_eq!((err.position, err.reason), (*position, *reason), "{input}");
+        }
+    }
+
     #[test]
     fn invalid_encodings() {
         let cases: &[&str] = &["%", "ab%A", "ab%0a", "%FG"];
@@ -72,4 +262,44 @@ mod test {
                 .unwrap_err();
         }
     }
+
+    #[test]
+    fn reports_malformation() {
+        let cases: &[(&str, usize, Malformation)] = &[
+            ("%", 0, Malformation::Truncated),
+            ("ab%A", 2, Malformation::Truncated),
+            ("ab%0a", 4, Malformation::InvalidHex),
+            ("%GG", 1, Malformation::InvalidHex),
+            ("é%1", 2, Malformation::Truncated),
+        ];
+
+        for (input, position, reason) in cases {
+            let err = percent_decode(input)
+                .collect::<Result<String, _>>()
+                .unwrap_err();
+            assert_eq!((err.position, err.reason), (*position, *reason), "{input}");
+            let err = percent_decode_plus(input)
+                .collect::<Result<String, _>>()
+                .unwrap_err();
+            assert_eq!((err.position, err.reason), (*position, *reason), "{input}");
+            let err = percent_decode_bytes(input.as_bytes())
+                .collect::<Result<Vec<u8>, _>>()
+                .unwrap_err();
+            assert_eq!((err.position, err.reason), (*position, *reason), "{input}");
+        }
+    }
+
+    #[test]
+    fn decodes_binary_bytes() {
+        let actual = percent_decode_bytes(b"a%00%FF%25\xC3\xA9")
+            .collect::<Result<Vec<u8>, _>>()
+            .unwrap();
+        assert_eq!(actual, b"a\x00\xFF%\xC3\xA9");
+
+        for input in [&b"%"[..], b"%A", b"%0a", b"%FG"] {
+            percent_decode_bytes(input)
+                .collect::<Result<Vec<u8>, _>>()
+                .unwrap_err();
+        }
+    }
 }
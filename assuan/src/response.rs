@@ -7,7 +7,13 @@ use std::fmt;
 /// Any response indicating success of requested operation. Responses
 /// indicating error should be constructed by returning `Err(_)` in
 /// request handler
+///
+/// Responses are equal if they're of the same kind and are sent the same way on the wire,
+/// e.g. [`SecretData`] is never equal to [`Data`], even if they contain the same data. Lines
+/// are compared in constant time, so comparing secret data doesn't leak its content via
+/// timing, although it leaks its size. [`Inquire`] responses are compared by the keyword only.
 #[allow(clippy::large_enum_variant)]
+#[derive(PartialEq)]
 pub enum Response {
     /// Secret data response
     SecretData(SecretData),
@@ -15,6 +21,14 @@ pub enum Response {
     Data(Data),
     /// OK response
     Ok(Ok),
+    /// Inquiry of data from the client
+    Inquire(Inquire),
+    /// OK response preceded by comment lines
+    Commented(Commented),
+    /// Data of arbitrary size split across several data lines
+    DataStream(DataStream),
+    /// Response preceded by status lines
+    WithStatus(WithStatus),
 }
 
 impl From<SecretData> for Response {
@@ -35,6 +49,30 @@ impl From<Ok> for Response {
     }
 }
 
+impl From<Inquire> for Response {
+    fn from(v: Inquire) -> Self {
+        Response::Inquire(v)
+    }
+}
+
+impl From<Commented> for Response {
+    fn from(v: Commented) -> Self {
+        Response::Commented(v)
+    }
+}
+
+impl From<DataStream> for Response {
+    fn from(v: DataStream) -> Self {
+        Response::DataStream(v)
+    }
+}
+
+impl From<WithStatus> for Response {
+    fn from(v: WithStatus) -> Self {
+        Response::WithStatus(v)
+    }
+}
+
 impl Response {
     /// Constructs a default OK response
     ///
@@ -48,6 +86,18 @@ impl Response {
         Self::Ok(Ok::new())
     }
 
+    /// Constructs an OK response without debug info
+    ///
+    /// Alias to:
+    /// ```rust
+    /// use assuan::response::{Response, Ok};
+    ///
+    /// let r: Response = Ok::bare().into();
+    /// ```
+    pub fn ok_bare() -> Self {
+        Self::Ok(Ok::bare())
+    }
+
     /// Constructs an OK response with custom debug info
     ///
     /// Alias to:
@@ -61,6 +111,58 @@ impl Response {
         Ok::with_debug_info(info).map(Self::Ok)
     }
 
+    /// Constructs a default OK response that closes the connection once it's sent
+    ///
+    /// Useful for implementing custom termination commands. Alias to:
+    /// ```rust
+    /// use assuan::response::{Response, Ok};
+    ///
+    /// let r: Response = Ok::new().close_connection(true).into();
+    /// ```
+    pub fn ok_closing() -> Self {
+        Self::Ok(Ok::new().close_connection(true))
+    }
+
+    /// Constructs an OK response with custom debug info that closes the connection once it's sent
+    ///
+    /// Alias to:
+    /// ```rust
+    /// use assuan::response::{Response, Ok};
+    ///
+    /// let r: Response = Ok::with_debug_info("closing connection")?
+    ///     .close_connection(true)
+    ///     .into();
+    /// # Ok::<_, assuan::response::TooLong>(())
+    /// ```
+    pub fn ok_closing_with_info(info: &str) -> Result<Self, TooLong> {
+        Ok::with_debug_info(info).map(|ok| Self::Ok(ok.close_connection(true)))
+    }
+
+    /// Constructs a default OK response that shuts down the server once it's sent
+    ///
+    /// Useful for implementing commands like `SHUTDOWN`. Alias to:
+    /// ```rust
+    /// use assuan::response::{Response, Ok};
+    ///
+    /// let r: Response = Ok::new().shutdown_server(true).into();
+    /// ```
+    pub fn ok_shutting_down() -> Self {
+        Self::Ok(Ok::new().shutdown_server(true))
+    }
+
+    /// Constructs an OK response preceded by a multi-line message sent as comments
+    ///
+    /// Alias to:
+    /// ```rust
+    /// use assuan::response::{Response, Commented};
+    ///
+    /// let r: Response = Commented::new("first line\nsecond line")?.into();
+    /// # Ok::<_, assuan::response::TooLong>(())
+    /// ```
+    pub fn ok_with_comments(message: &str) -> Result<Self, TooLong> {
+        Commented::new(message).map(Self::Commented)
+    }
+
     /// Constructs a data response
     ///
     /// Alias to:
@@ -74,6 +176,86 @@ impl Response {
         Data::new(data).map(Self::Data)
     }
 
+    /// Constructs a secret data response which is zeroized on drop
+    ///
+    /// Note that `data` itself isn't zeroized. Alias to:
+    /// ```rust
+    /// use assuan::response::{Response, SecretData};
+    ///
+    /// let mut data = SecretData::default();
+    /// data.append("my password")?;
+    /// let r: Response = data.into();
+    /// # Ok::<_, assuan::response::TooLong>(())
+    /// ```
+    pub fn data_secret(data: &str) -> Result<Self, TooLong> {
+        let mut secret = SecretData::default();
+        secret.append(data)?;
+        Ok(Self::SecretData(secret))
+    }
+
+    /// Constructs a data response of arbitrary size
+    ///
+    /// Alias to:
+    /// ```rust
+    /// use assuan::response::{Response, DataStream};
+    ///
+    /// let mut data = DataStream::new();
+    /// data.append("data to be sent");
+    /// let r: Response = data.into();
+    /// ```
+    pub fn data_stream(data: &str) -> Self {
+        let mut stream = DataStream::new();
+        stream.append(data);
+        Self::DataStream(stream)
+    }
+
+    /// Constructs an inquire response
+    ///
+    /// Alias to:
+    /// ```rust
+    /// use assuan::response::{Response, Inquire, TooLong};
+    ///
+    /// let r: Response = Inquire::new("NAME", |name| Ok::<_, TooLong>(name.into()))?.into();
+    /// # Ok::<_, assuan::response::TooLong>(())
+    /// ```
+    pub fn inquire<E>(
+        keyword: &str,
+        continuation: impl FnOnce(SecretData) -> Result<Response, E> + Send + 'static,
+    ) -> Result<Self, TooLong>
+    where
+        E: fmt::Display + crate::HasErrorCode,
+    {
+        Inquire::new(keyword, continuation).map(Self::Inquire)
+    }
+
+    /// Attaches `S keyword args` status line sent right before the response
+    ///
+    /// Handy for attaching a single informational status to an otherwise normal response.
+    /// Statuses attached several times are sent in the order they were attached. `args` are
+    /// percent-encoded, if needed. Returns error if the status line exceeds
+    /// [`MAX_LINE_SIZE`](crate::MAX_LINE_SIZE).
+    ///
+    /// ### Example
+    /// ```rust
+    /// use assuan::response::Response;
+    ///
+    /// let r = Response::ok().with_status("WARNING", "weak-key")?;
+    /// # Ok::<_, assuan::response::TooLong>(())
+    /// ```
+    pub fn with_status(self, keyword: &str, args: &str) -> Result<Self, TooLong> {
+        let status = status_line(keyword, args)?;
+        Ok(match self {
+            Self::WithStatus(mut resp) => {
+                resp.statuses.push(status);
+                Self::WithStatus(resp)
+            }
+            resp => Self::WithStatus(WithStatus {
+                statuses: vec![status],
+                resp: Box::new(resp),
+            }),
+        })
+    }
+
     pub(crate) fn write(&self, out: &mut impl std::io::Write) -> std::io::Result<()> {
         match self {
             Self::Ok(ok) => ok.resp.write(out),
@@ -85,15 +267,91 @@ impl Response {
                 data.data_resp.write(out)?;
                 data.ok.resp.write(out)
             }
+            Self::Inquire(inquire) => inquire.write(out),
+            Self::Commented(commented) => {
+                for comment in &commented.comments {
+                    comment.write(out)?;
+                }
+                commented.ok.resp.write(out)
+            }
+            Self::DataStream(stream) => {
+                for line in &stream.lines {
+                    line.write(out)?;
+                }
+                stream.ok.resp.write(out)
+            }
+            Self::WithStatus(resp) => {
+                for status in &resp.statuses {
+                    status.write(out)?;
+                }
+                resp.resp.write(out)
+            }
         }
     }
 
     /// Indicates whether a connection needs to be closed when response is sent
     pub fn connection_needs_be_closed(&self) -> bool {
+        self.final_ok()
+            .is_some_and(|ok| ok.close_conn || ok.shutdown)
+    }
+
+    /// Indicates whether the server needs to stop serving any clients when response is sent
+    pub fn server_needs_be_shut_down(&self) -> bool {
+        self.final_ok().is_some_and(|ok| ok.shutdown)
+    }
+
+    /// Indicates whether connection needs to be closed when response is sent
+    ///
+    /// Sets the flag on the `OK` line terminating the response, so that e.g. data is sent before the
+    /// connection is closed. Inquiries are not terminated by `OK` and are returned unchanged.
+    ///
+    /// ### Example
+    /// ```rust
+    /// use assuan::response::{Response, SecretData};
+    ///
+    /// let mut secret = SecretData::default();
+    /// secret.append("my password")?;
+    /// let r = Response::from(secret).close_connection(true);
+    /// assert!(r.connection_needs_be_closed());
+    /// # Ok::<_, assuan::response::TooLong>(())
+    /// ```
+    pub fn close_connection(mut self, v: bool) -> Self {
+        if let Some(ok) = self.final_ok_mut() {
+            ok.close_conn = v;
+        }
+        self
+    }
+
+    /// Number of status lines sent before the response
+    pub(crate) fn status_count(&self) -> usize {
+        match self {
+            Self::WithStatus(r) => r.statuses.len() + r.resp.status_count(),
+            _ => 0,
+        }
+    }
+
+    /// `OK` line terminating the response, `None` for inquiries
+    fn final_ok(&self) -> Option<&Ok> {
         match self {
-            Self::Ok(r) => r.close_conn,
-            Self::Data(r) => r.ok.close_conn,
-            Self::SecretData(r) => r.ok.close_conn,
+            Self::Ok(r) => Some(r),
+            Self::Data(r) => Some(&r.ok),
+            Self::SecretData(r) => Some(&r.ok),
+            Self::Inquire(_) => None,
+            Self::Commented(r) => Some(&r.ok),
+            Self::DataStream(r) => Some(&r.ok),
+            Self::WithStatus(r) => r.resp.final_ok(),
+        }
+    }
+
+    fn final_ok_mut(&mut self) -> Option<&mut Ok> {
+        match self {
+            Self::Ok(r) => Some(r),
+            Self::Data(r) => Some(&mut r.ok),
+            Self::SecretData(r) => Some(&mut r.ok),
+            Self::Inquire(_) => None,
+            Self::Commented(r) => Some(&mut r.ok),
+            Self::DataStream(r) => Some(&mut r.ok),
+            Self::WithStatus(r) => r.resp.final_ok_mut(),
         }
     }
 }
@@ -115,6 +373,37 @@ impl Response {
 /// ```
 pub type SecretData = Box<zeroize::Zeroizing<Data>>;
 
+/// Compares decoded data of two secrets in constant time
+///
+/// Unlike `==`, comparison doesn't stop at the first mismatch, so the timing doesn't reveal
+/// the length of a common prefix. It only depends on the length of the longer secret. Data is
+/// compared after decoding, so the same bytes appended via [`Data::append`] and
+/// [`Data::append_bytes`] are equal.
+///
+/// ### Example
+/// ```rust
+/// use assuan::response::{secret_eq, SecretData};
+///
+/// let (mut pin, mut repeated) = (SecretData::default(), SecretData::default());
+/// pin.append("1234")?;
+/// repeated.append("1234")?;
+/// assert!(secret_eq(&pin, &repeated));
+/// repeated.append("5")?;
+/// assert!(!secret_eq(&pin, &repeated));
+/// # Ok::<_, assuan::response::TooLong>(())
+/// ```
+pub fn secret_eq(a: &SecretData, b: &SecretData) -> bool {
+    let (mut a, mut b) = (a.decoded_bytes(), b.decoded_bytes());
+    let mut diff = 0;
+    loop {
+        match (a.next(), b.next()) {
+            (None, None) => return diff == 0,
+            // Bytes never reach `u16::MAX`, so secret being shorter always makes a difference
+            (x, y) => diff |= x.map_or(u16::MAX, u16::from) ^ y.map_or(u16::MAX, u16::from),
+        }
+    }
+}
+
 /// Data response
 ///
 /// On a wire, data response has format:
@@ -126,12 +415,12 @@ pub type SecretData = Box<zeroize::Zeroizing<Data>>;
 ///
 /// Data is UTF8 string. Certain characters in the string are percent-encoded (e.g. `\n` is transmitted as `%A0`).
 /// Percent encoding is done automatically when data is written. Data string is limited by [Data::MAX_BYTES] size
-/// in bytes after percent-encoding.
+/// in bytes after percent-encoding. Binary data can be sent via [Data::new_bytes] and [Data::append_bytes].
 ///
 /// Data response is always followed by [Ok] response. By default, `OK success` is sent, however, custom debug
 /// info may be specified via [Data::with_custom_ok] or [Data::with_debug_info]. Assuan protocol also allows
 /// data responses to be followed by `ERR` response, but the library doesn't support that.
-#[derive(Clone, Copy)]
+#[derive(Clone, Copy, PartialEq, Eq)]
 pub struct Data {
     data_resp: ResponseLine,
     ok: Ok,
@@ -156,6 +445,74 @@ impl Data {
         Ok(resp)
     }
 
+    /// Constructs data response containing binary `data`
+    ///
+    /// Bytes that aren't printable ASCII characters (`0x00`..`0x1F`, `0x7F` and non-ASCII
+    /// bytes) are percent-encoded as `%XX`. Returns error if response exceeds the limit set by
+    /// assuan protocol (see [Data::MAX_BYTES]).
+    ///
+    /// ### Example
+    /// ```rust
+    /// use assuan::response::Data;
+    ///
+    /// // DER-encoded signature
+    /// let data = Data::new_bytes(&[0x30, 0x06, 0x02, 0x01, 0x7F, 0x02, 0x01, 0x80])?;
+    /// assert_eq!(data.size(), 22);
+    /// # Ok::<_, assuan::response::TooLong>(())
+    /// ```
+    pub fn new_bytes(data: &[u8]) -> Result<Self, TooLong> {
+        let mut resp = Self::default();
+        resp.append_bytes(data)?;
+        Ok(resp)
+    }
+
+    /// Constructs data response containing uppercase hex representation of `bytes`
+    ///
+    /// Handy for sending key fingerprints and keygrips. Hex digits are written directly into
+    /// the response without allocating an intermediate string.
+    ///
+    /// Returns error if the hex representation exceeds the limit set by assuan protocol
+    /// (see [Data::MAX_BYTES]).
+    ///
+    /// ### Example
+    /// ```rust
+    /// use assuan::response::Data;
+    ///
+    /// let data = Data::from_hex(&[0x0A, 0xBC, 0xFF])?;
+    /// assert!(data.chars().eq("0ABCFF".chars()));
+    /// # Ok::<_, assuan::response::TooLong>(())
+    /// ```
+    pub fn from_hex(bytes: &[u8]) -> Result<Self, TooLong> {
+        let mut resp = Self::default();
+        resp.append_hex(bytes)?;
+        Ok(resp)
+    }
+
+    /// Constructs [secret data response](SecretData) containing uppercase hex representation
+    /// of `bytes`
+    ///
+    /// Same as [Data::from_hex], but the response is zeroized on drop.
+    pub fn secret_from_hex(bytes: &[u8]) -> Result<SecretData, TooLong> {
+        let mut resp = SecretData::default();
+        resp.append_hex(bytes)?;
+        Ok(resp)
+    }
+
+    /// Appends uppercase hex representation of `bytes` to the response
+    ///
+    /// Response is left unchanged if the hex representation doesn't fit
+    fn append_hex(&mut self, bytes: &[u8]) -> Result<(), TooLong> {
+        const DIGITS: &[u8; 16] = b"0123456789ABCDEF";
+        if bytes.len() > (Self::MAX_BYTES - self.size()) / 2 {
+            return Err(TooLong);
+        }
+        for byte in bytes {
+            self.push(DIGITS[usize::from(byte >> 4)].into())?;
+            self.push(DIGITS[usize::from(byte & 0x0F)].into())?;
+        }
+        Ok(())
+    }
+
     /// Sets `Ok` response to be sent after the data
     pub fn with_custom_ok(mut self, ok: Ok) -> Self {
         self.ok = ok;
@@ -176,6 +533,42 @@ impl Data {
         self.data_resp.append(data)
     }
 
+    /// Appends binary data to the response
+    ///
+    /// Bytes are encoded the same way as in [Data::new_bytes]. Returns error if response
+    /// exceeds the limit set by assuan protocol (see [Data::MAX_BYTES]), response is left
+    /// unchanged in this case.
+    pub fn append_bytes(&mut self, data: &[u8]) -> Result<(), TooLong> {
+        self.data_resp.append_bytes(data)
+    }
+
+    /// Appends binary data yielded by an iterator, e.g. output of a streaming cipher
+    ///
+    /// Bytes are encoded as they arrive, the same way as in [Data::new_bytes]. Returns error
+    /// once the response exceeds the limit set by assuan protocol (see [Data::MAX_BYTES]),
+    /// response is left unchanged in this case, and iterator is consumed up to the byte that
+    /// didn't fit. Use [DataStream::append_iter] for data that may not fit into a single line.
+    ///
+    /// ### Example
+    /// ```rust
+    /// use assuan::response::SecretData;
+    ///
+    /// let mut response = SecretData::default();
+    /// response.append_iter([0x01, 0x02].iter().map(|x| x ^ 0x40))?;
+    /// assert!(response.chars().eq("AB".chars()));
+    /// # Ok::<_, assuan::response::TooLong>(())
+    /// ```
+    pub fn append_iter(&mut self, data: impl IntoIterator<Item = u8>) -> Result<(), TooLong> {
+        let size = self.data_resp.size();
+        for x in data {
+            if let Err(err) = self.data_resp.push_byte(x) {
+                self.data_resp.truncate(size);
+                return Err(err);
+            }
+        }
+        Ok(())
+    }
+
     /// Appends single character to the response
     ///
     /// Returns error if response exceeds the limit set by assuan protocol (see [Data::MAX_BYTES])
@@ -214,6 +607,32 @@ impl Data {
         self
     }
 
+    /// Returns an iterator over characters of the data
+    ///
+    /// Data is decoded on the fly. Note that collecting the characters into a `String`
+    /// defeats zeroizing guarantees of [`SecretData`].
+    ///
+    /// ### Example
+    /// ```rust
+    /// use assuan::response::Data;
+    ///
+    /// let data = Data::new("one\ntwo")?;
+    /// assert!(data.chars().eq("one\ntwo".chars()));
+    /// # Ok::<_, assuan::response::TooLong>(())
+    /// ```
+    pub fn chars(&self) -> impl Iterator<Item = char> + '_ {
+        let escaped = &self.data_resp.as_str()[Self::PREFIX.len()..];
+        crate::percent_decode::percent_decode(escaped)
+            .map(|x| x.expect("response line is guaranteed to have a valid percent encoding"))
+    }
+
+    /// Returns an iterator over decoded bytes of the data
+    fn decoded_bytes(&self) -> impl Iterator<Item = u8> + '_ {
+        let escaped = &self.data_resp.as_str().as_bytes()[Self::PREFIX.len()..];
+        crate::percent_decode::percent_decode_bytes(escaped)
+            .map(|x| x.expect("response line is guaranteed to have a valid percent encoding"))
+    }
+
     /// Size of escaped data
     ///
     /// ### Example
@@ -242,8 +661,144 @@ impl Default for Data {
     }
 }
 
+/// Content isn't printed as data may be sensitive
+impl fmt::Debug for Data {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("Data(..)")
+    }
+}
+
 impl zeroize::DefaultIsZeroes for Data {}
 
+#[cfg(feature = "secrecy")]
+impl Data {
+    /// Constructs a [secret data response](SecretData) from a [`secrecy`] secret
+    ///
+    /// Accepts both `SecretString` and `SecretBox<String>`. Returns error if the secret
+    /// exceeds the limit set by assuan protocol (see [Data::MAX_BYTES]).
+    ///
+    /// ### Example
+    /// ```rust
+    /// use assuan::response::Data;
+    ///
+    /// let secret = secrecy::SecretString::from("my password");
+    /// let response = Data::new_secret(&secret)?;
+    /// assert!(response.chars().eq("my password".chars()));
+    /// # Ok::<_, assuan::response::TooLong>(())
+    /// ```
+    pub fn new_secret<S: AsRef<str> + ?Sized>(
+        secret: &impl secrecy::ExposeSecret<S>,
+    ) -> Result<SecretData, TooLong> {
+        let mut data = SecretData::default();
+        data.append(secret.expose_secret().as_ref())?;
+        Ok(data)
+    }
+}
+
+/// Decodes the data into a [`secrecy`] secret
+///
+/// The string is allocated once with the exact size, so no copies of the data are left
+/// in memory.
+#[cfg(feature = "secrecy")]
+impl From<&Data> for secrecy::SecretString {
+    fn from(data: &Data) -> Self {
+        let len = data.chars().map(char::len_utf8).sum();
+        let mut decoded = zeroize::Zeroizing::new(String::with_capacity(len));
+        decoded.extend(data.chars());
+        secrecy::SecretString::new(std::mem::take(&mut *decoded).into_boxed_str())
+    }
+}
+
+/// Data response of arbitrary size
+///
+/// On a wire, the response has format:
+///
+/// ```text
+/// D [escaped first chunk of data]\n
+/// D [escaped second chunk of data]\n
+/// OK success\n
+/// ```
+///
+/// Unlike [Data], the size of data isn't limited: it's split across as many data lines as
+/// needed, each line respecting [MAX_LINE_SIZE](crate::MAX_LINE_SIZE). Data is split only
+/// between encoded characters, so percent-escapes are never broken across lines. Data may be
+/// a UTF-8 string or binary: bytes appended via [`append_bytes`](DataStream::append_bytes)
+/// are encoded the same way as in [`Data::new_bytes`].
+///
+/// Data isn't zeroized, so it's not suitable for sensitive information.
+///
+/// ### Example
+/// ```rust
+/// use assuan::response::DataStream;
+///
+/// let mut data = DataStream::new();
+/// data.append("-----BEGIN PGP PUBLIC KEY BLOCK-----\n");
+/// data.append_bytes(&[0x99, 0x01, 0x0D]);
+/// ```
+#[derive(PartialEq, Eq)]
+pub struct DataStream {
+    lines: Vec<ResponseLine>,
+    ok: Ok,
+}
+
+impl DataStream {
+    /// Constructs an empty data response
+    pub fn new() -> Self {
+        Self {
+            lines: Vec::new(),
+            ok: Ok::new(),
+        }
+    }
+
+    /// Appends a string to the response
+    pub fn append(&mut self, data: &str) {
+        for x in data.chars() {
+            self.push_with(|line| line.push(x));
+        }
+    }
+
+    /// Appends binary data to the response
+    pub fn append_bytes(&mut self, data: &[u8]) {
+        for x in data {
+            self.push_with(|line| line.push_byte(*x));
+        }
+    }
+
+    /// Appends binary data yielded by an iterator, bytes are encoded as they arrive
+    pub fn append_iter(&mut self, data: impl IntoIterator<Item = u8>) {
+        for x in data {
+            self.push_with(|line| line.push_byte(x));
+        }
+    }
+
+    /// Sets `Ok` response to be sent after the data
+    pub fn with_custom_ok(mut self, ok: Ok) -> Self {
+        self.ok = ok;
+        self
+    }
+
+    /// Pushes a single encoded character to the last data line, starts a new line if
+    /// the last one is full
+    fn push_with(&mut self, push: impl Fn(&mut ResponseLine) -> Result<(), TooLong>) {
+        if let Some(line) = self.lines.last_mut() {
+            if push(line).is_ok() {
+                return;
+            }
+        }
+        let mut line = ResponseLine::new()
+            .chain(Data::PREFIX)
+            .expect("prefix is much smaller than the limit");
+        push(&mut line).expect("single character always fits into an empty line");
+        self.lines.push(line);
+    }
+}
+
+impl Default for DataStream {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 /// OK response
 ///
 /// On a wire, OK response has format:
@@ -255,10 +810,11 @@ impl zeroize::DefaultIsZeroes for Data {}
 /// Response is UTF8 string. Certain characters in the string are percent-encoded (e.g. `\n` is transmitted as `%A0`).
 /// Percent encoding is done automatically when response is written. Debug info is limited by [Ok::MAX_BYTES] size
 /// in bytes after percent-encoding.
-#[derive(Clone, Copy)]
+#[derive(Clone, Copy, PartialEq, Eq)]
 pub struct Ok {
     resp: ResponseLine,
     close_conn: bool,
+    shutdown: bool,
 }
 
 impl Ok {
@@ -285,13 +841,28 @@ impl Ok {
         Ok(Self {
             resp: ResponseLine::new().chain("OK ")?.chain(info)?,
             close_conn: false,
+            shutdown: false,
         })
     }
 
+    /// Constructs `OK` response without debug info
+    ///
+    /// On a wire, it's sent as `OK\n`. Debug info may still be [appended](Ok::append) later.
+    pub fn bare() -> Self {
+        Self {
+            resp: ResponseLine::new()
+                .chain(Self::PREFIX.trim_end())
+                .expect("prefix is much smaller than the limit"),
+            close_conn: false,
+            shutdown: false,
+        }
+    }
+
     /// Appends data to the response
     ///
     /// Returns error if response exceeds the limit set by assuan protocol (see [Ok::MAX_BYTES])
     pub fn append(&mut self, data: &str) -> Result<(), TooLong> {
+        self.separate_debug_info()?;
         self.resp.append(data)
     }
 
@@ -299,15 +870,36 @@ impl Ok {
     ///
     /// Returns error if response exceeds the limit set by assuan protocol (see [Ok::MAX_BYTES])
     pub fn push(&mut self, x: char) -> Result<(), TooLong> {
+        self.separate_debug_info()?;
         self.resp.push(x)
     }
 
+    /// Puts a space between `OK` and debug info of a [bare](Ok::bare) response
+    fn separate_debug_info(&mut self) -> Result<(), TooLong> {
+        if self.resp.size() < Self::PREFIX.len() {
+            self.resp.append(" ")?;
+        }
+        Ok(())
+    }
+
     /// Indicated whether connection needs to be closed when response is sent
     pub fn close_connection(mut self, v: bool) -> Self {
         self.close_conn = v;
         self
     }
 
+    /// Indicates whether the server needs to stop serving any clients when response is sent
+    ///
+    /// Shutting down implies closing the connection. Server loops like
+    /// [`run_stdio_loop`](crate::AssuanServer::run_stdio_loop) and
+    /// [`UnixSocketServer::serve`](crate::unix_socket::UnixSocketServer::serve) return once
+    /// the response is sent. Use [`AssuanServer::is_shutdown_requested`](crate::AssuanServer::is_shutdown_requested)
+    /// to find out whether the server was shut down after serving a client.
+    pub fn shutdown_server(mut self, v: bool) -> Self {
+        self.shutdown = v;
+        self
+    }
+
     /// Size of escaped debug info
     ///
     /// ### Example
@@ -321,7 +913,7 @@ impl Ok {
     /// # Ok::<_, assuan::response::TooLong>(())
     /// ```
     pub fn size(&self) -> usize {
-        self.resp.size() - Self::PREFIX.len()
+        self.resp.size().saturating_sub(Self::PREFIX.len())
     }
 }
 
@@ -333,6 +925,180 @@ impl Default for Ok {
 
 impl zeroize::DefaultIsZeroes for Ok {}
 
+/// OK response preceded by comment lines
+///
+/// On a wire, the response has format:
+///
+/// ```text
+/// # [escaped first line]\n
+/// # [escaped second line]\n
+/// OK success\n
+/// ```
+///
+/// `OK` debug info can't span several lines, so a multi-line message is sent as comment lines,
+/// one per line of the message. Each line is limited by [Commented::MAX_LINE_BYTES] size in
+/// bytes after percent-encoding.
+///
+/// ### Example
+/// ```rust
+/// use assuan::response::{Commented, Ok};
+///
+/// let resp = Commented::new("Key was unlocked.\nIt will be locked again in 10 minutes.")?
+///     .with_custom_ok(Ok::with_debug_info("unlocked")?);
+/// # Ok::<_, assuan::response::TooLong>(())
+/// ```
+#[derive(PartialEq, Eq)]
+pub struct Commented {
+    comments: Vec<ResponseLine>,
+    ok: Ok,
+}
+
+impl Commented {
+    /// Max size of a comment line
+    ///
+    /// Assuan spec sets the limit for max response size: 1000 bytes. 3 bytes of those are
+    /// used for comment prefix (`"# "` of 2 bytes) and final `\n` byte indicating end of the
+    /// line. So each line may be up to 997 bytes long.
+    pub const MAX_LINE_BYTES: usize = 997;
+
+    const PREFIX: &'static str = "# ";
+
+    /// Constructs a response that sends every line of the `message` as a comment, followed by
+    /// default `OK` response
+    ///
+    /// Returns error if any line exceeds the limit (see [Commented::MAX_LINE_BYTES])
+    pub fn new(message: &str) -> Result<Self, TooLong> {
+        let comments = message
+            .lines()
+            .map(|line| ResponseLine::new().chain(Self::PREFIX)?.chain(line))
+            .collect::<Result<_, _>>()?;
+        Ok(Self {
+            comments,
+            ok: Ok::new(),
+        })
+    }
+
+    /// Sets `Ok` response to be sent after the comments
+    pub fn with_custom_ok(mut self, ok: Ok) -> Self {
+        self.ok = ok;
+        self
+    }
+}
+
+/// Response preceded by status lines
+///
+/// On a wire, it has format:
+///
+/// ```text
+/// S [keyword] [escaped args]\n
+/// [response]
+/// ```
+///
+/// Constructed via [`Response::with_status`].
+#[derive(PartialEq)]
+pub struct WithStatus {
+    statuses: Vec<ResponseLine>,
+    resp: Box<Response>,
+}
+
+/// Inquire response
+///
+/// Asks the client to provide some data. On a wire, inquiry has format:
+///
+/// ```text
+/// INQUIRE [escaped keyword]\n
+/// ```
+///
+/// Client replies with zero or more data lines terminated by `END`, or cancels the inquiry by
+/// sending `CAN`:
+///
+/// ```text
+/// D [escaped data]\n
+/// END\n
+/// ```
+///
+/// Once the reply is received, the data is decoded and passed to the continuation which
+/// produces the actual response to the command. Data is considered to be sensitive, so it's
+/// passed as [`SecretData`] and zeroized on drop. It implies that the reply is limited by
+/// [`Data::MAX_BYTES`], if client sends more data, the command fails with
+/// [`ASS_TOO_MUCH_DATA`](crate::ErrorCode::ASS_TOO_MUCH_DATA) error. The continuation may return another inquiry.
+/// If client cancels the inquiry, the continuation is not called and the command fails with
+/// [`ASS_CANCELED`](crate::ErrorCode::ASS_CANCELED) error.
+///
+/// If the reply is malformed, server still reads it up to `END` (or `CAN`) before responding
+/// with an error, so that the rest of the reply isn't treated as new commands.
+///
+/// ### Example
+/// ```rust
+/// use assuan::response::{Inquire, Response};
+///
+/// let resp = Inquire::new("PASSPHRASE", |passphrase| {
+///     if passphrase.chars().eq("correct horse battery staple".chars()) {
+///         Ok(Response::ok())
+///     } else {
+///         Err(assuan::WithErrorCode {
+///             code: assuan::ErrorCode::BAD_PASSPHRASE,
+///             error: "bad passphrase",
+///         })
+///     }
+/// })?;
+/// # Ok::<_, assuan::response::TooLong>(())
+/// ```
+pub struct Inquire {
+    line: ResponseLine,
+    continuation: Continuation,
+}
+
+type Continuation =
+    Box<dyn FnOnce(SecretData) -> Result<Response, crate::WithErrorCode<String>> + Send>;
+
+impl Inquire {
+    const PREFIX: &'static str = "INQUIRE ";
+
+    /// Constructs an inquiry with given `keyword`
+    ///
+    /// `continuation` is called with data sent by the client. Returns error if `keyword` exceeds
+    /// the limit set by assuan protocol.
+    pub fn new<E>(
+        keyword: &str,
+        continuation: impl FnOnce(SecretData) -> Result<Response, E> + Send + 'static,
+    ) -> Result<Self, TooLong>
+    where
+        E: fmt::Display + crate::HasErrorCode,
+    {
+        Ok(Self {
+            line: Self::line(keyword)?,
+            continuation: Box::new(move |data| {
+                continuation(data).map_err(|err| crate::WithErrorCode {
+                    code: err.code(),
+                    error: err.to_string(),
+                })
+            }),
+        })
+    }
+
+    /// Builds `INQUIRE keyword` line
+    pub(crate) fn line(keyword: &str) -> Result<ResponseLine, TooLong> {
+        ResponseLine::new().chain(Self::PREFIX)?.chain(keyword)
+    }
+
+    pub(crate) fn write(&self, out: &mut impl std::io::Write) -> std::io::Result<()> {
+        self.line.write(out)
+    }
+
+    /// Resumes processing of the command with the data received from the client
+    pub(crate) fn resume(self, data: SecretData) -> Result<Response, crate::WithErrorCode<String>> {
+        (self.continuation)(data)
+    }
+}
+
+/// Inquiries are equal if they ask for the same keyword, continuations aren't compared
+impl PartialEq for Inquire {
+    fn eq(&self, other: &Self) -> bool {
+        self.line == other.line
+    }
+}
+
 /// Response exceeds limit of [MAX_LINE_SIZE](crate::MAX_LINE_SIZE)
 #[derive(Debug)]
 pub struct TooLong;
@@ -349,15 +1115,63 @@ impl crate::HasErrorCode for TooLong {
     }
 }
 
-pub(crate) use builder::ResponseLine;
+/// Builds `ERR code desc` line reporting an error
+///
+/// That's how the server responds when a command fails. `desc` is percent-encoded, if needed.
+/// Returns error if the line exceeds [`MAX_LINE_SIZE`](crate::MAX_LINE_SIZE).
+///
+/// ### Example
+/// ```rust
+/// use assuan::{response::error_line, ErrorCode};
+///
+/// let line = error_line(ErrorCode::ASS_PARAMETER, "100% wrong")?;
+/// assert_eq!(line.as_str(), "ERR 280 100%25 wrong");
+/// # Ok::<_, assuan::response::TooLong>(())
+/// ```
+pub fn error_line(code: crate::ErrorCode, desc: &str) -> Result<ResponseLine, TooLong> {
+    ResponseLine::new()
+        .chain("ERR ")?
+        .chain(&code.raw().to_string())?
+        .chain(" ")?
+        .chain(desc)
+}
+
+/// Builds `S keyword args` status line
+pub(crate) fn status_line(keyword: &str, args: &str) -> Result<ResponseLine, TooLong> {
+    let line = ResponseLine::new().chain("S ")?.chain(keyword)?;
+    if args.is_empty() {
+        Ok(line)
+    } else {
+        line.chain(" ")?.chain(args)
+    }
+}
+
+pub use builder::{optionally_escape_byte, ResponseLine};
+
+// We keep the constructor in a separate private module to make sure that its private methods
+// are not being used by rest of the parent module.
 mod builder {
     use super::TooLong;
 
     /// Response line constructor. Follows requirements enforced by assuan spec, including the percentage
     /// encoding, and size limit.
     ///
-    /// We keep it in a separate private module to make sure that its private methods are not being
-    /// used by rest of the parent module.
+    /// Can be used to build custom responses, e.g. status lines. Line can't exceed
+    /// [`MAX_LINE_SIZE`](crate::MAX_LINE_SIZE) bytes, including the final `\n` symbol
+    /// that's put automatically on [`write`](Self::write).
+    ///
+    /// ### Example
+    /// ```rust
+    /// use assuan::response::ResponseLine;
+    ///
+    /// let line = ResponseLine::new().chain("S PROGRESS ")?.chain("100%")?;
+    /// assert_eq!(line.as_str(), "S PROGRESS 100%25");
+    ///
+    /// let mut out = vec![];
+    /// line.write(&mut out).unwrap();
+    /// assert_eq!(out, b"S PROGRESS 100%25\n");
+    /// # Ok::<_, assuan::response::TooLong>(())
+    /// ```
     #[derive(Clone, Copy)]
     pub struct ResponseLine {
         resp: [u8; Self::MAX_SIZE],
@@ -393,32 +1207,40 @@ mod builder {
         /// Appends data to the response
         ///
         /// Data must be a valid UTF-8 string no longer than 1000 bytes (including the final `\n` symbol that's
-        /// put automatically). Returns error if the data exceeds the size limit.
-        pub fn append(&mut self, mut data: &str) -> Result<(), TooLong> {
-            if data.len() > self.resp.len() - self.size {
+        /// put automatically). Returns error if the data exceeds the size limit, response is left
+        /// unchanged in this case.
+        pub fn append(&mut self, data: &str) -> Result<(), TooLong> {
+            // Size is checked after escaping, so the data is never written partially
+            let escaped_size: usize = data
+                .bytes()
+                .map(|x| optionally_escape_byte(x).map_or(1, str::len))
+                .sum();
+            if escaped_size > self.resp.len() - self.size {
                 return Err(TooLong);
             }
 
-            loop {
-                let mut iter = data.char_indices();
-                let Some((pos, x)) = iter.find_map(|(i, x)| Some((i, optionally_escape(x)?)))
-                else {
-                    // There's nothing to be escaped, we can just copy the string
-                    self.add_data(data)?;
-                    return Ok(());
-                };
-
+            // All the symbols that need to be escaped are ASCII, so we can look for them
+            // byte by byte: bytes of multibyte UTF-8 characters are never in ASCII range
+            let mut data = data.as_bytes();
+            while let Some(pos) = data
+                .iter()
+                .position(|x| optionally_escape_byte(*x).is_some())
+            {
                 // A symbol that needs to be escaped is found at position `pos`.
                 // The whole string up to this symbol can be copied without
                 // modification
                 self.add_data(&data[..pos])?;
 
                 // Write escaped symbol
-                self.add_data(x)?;
+                let escaped = optionally_escape_byte(data[pos]).expect("we checked it above");
+                self.add_data(escaped)?;
 
                 // Continue parsing the string
-                data = iter.as_str();
+                data = &data[pos + 1..];
             }
+
+            // There's nothing left to be escaped, we can just copy the rest of the string
+            self.add_data(data)
         }
 
         /// Appends single character to the response
@@ -431,6 +1253,37 @@ mod builder {
             self.append(s)
         }
 
+        /// Appends binary data to the response
+        ///
+        /// Bytes are encoded the same way as in [`push_byte`](Self::push_byte). Returns error
+        /// if data exceeds the size limit, response is left unchanged in this case.
+        pub fn append_bytes(&mut self, data: &[u8]) -> Result<(), TooLong> {
+            let escaped_size: usize = data.iter().map(|x| escaped_byte_size(*x)).sum();
+            if escaped_size > self.resp.len() - self.size {
+                return Err(TooLong);
+            }
+            data.iter().try_for_each(|x| self.push_byte(*x))
+        }
+
+        /// Appends single byte of binary data to the response
+        ///
+        /// Bytes that aren't printable ASCII characters (control characters and bytes outside
+        /// of ASCII range) are percent-encoded, so response remains a valid UTF-8 string
+        /// that doesn't contain raw control characters. Returns error if data exceeds the size
+        /// limit.
+        pub fn push_byte(&mut self, x: u8) -> Result<(), TooLong> {
+            const DIGITS: &[u8; 16] = b"0123456789ABCDEF";
+            if is_printable(x) {
+                self.push(x.into())
+            } else {
+                self.add_data([
+                    b'%',
+                    DIGITS[usize::from(x >> 4)],
+                    DIGITS[usize::from(x & 0x0F)],
+                ])
+            }
+        }
+
         fn add_data(&mut self, data: impl AsRef<[u8]>) -> Result<(), TooLong> {
             let data_len = data.as_ref().len();
             if data_len == 0 {
@@ -446,15 +1299,21 @@ mod builder {
             Ok(())
         }
 
+        /// Shortens the response to `size` bytes, has no effect if response is shorter
+        ///
+        /// `size` must not split an encoded character.
+        pub(crate) fn truncate(&mut self, size: usize) {
+            debug_assert!(self.as_str().is_char_boundary(size.min(self.size)));
+            self.size = self.size.min(size);
+        }
+
         /// Deletes the last symbol from the response and returns it
         ///
         /// Be aware that this method doesn't have great performance as `ResponseLine` stores
         /// the string as bytes, and removing last character requires UTF8 decoding to find
         /// the range of the last symbol which is not efficient.
         pub fn pop(&mut self) -> Option<char> {
-            let s = std::str::from_utf8(&self.resp[..self.size])
-                .expect("response is guaranteed to be a valid utf8 string");
-            let mut chars = s.char_indices().rev();
+            let mut chars = self.as_str().char_indices().rev();
             let (last_pos, last_char) = chars.next()?;
             let mid = chars.next();
             let possibly_percent = chars.next();
@@ -472,7 +1331,13 @@ mod builder {
             }
         }
 
-        /// Writes response to the writer
+        /// Returns the escaped response as a string
+        pub fn as_str(&self) -> &str {
+            std::str::from_utf8(&self.resp[..self.size])
+                .expect("response is guaranteed to be a valid utf8 string")
+        }
+
+        /// Writes response followed by `\n` to the writer
         pub fn write(&self, out: &mut impl std::io::Write) -> std::io::Result<()> {
             out.write_all(&self.resp[..self.size])?;
             out.write_all(b"\n")
@@ -485,15 +1350,43 @@ mod builder {
         }
     }
 
+    /// Lines are compared in constant time: comparison doesn't stop at the first mismatched
+    /// byte, so it doesn't leak the content of the line. Size of the line isn't hidden.
+    impl PartialEq for ResponseLine {
+        fn eq(&self, other: &Self) -> bool {
+            let (a, b) = (&self.resp[..self.size], &other.resp[..other.size]);
+            a.len() == b.len() && a.iter().zip(b).fold(0, |diff, (x, y)| diff | (x ^ y)) == 0
+        }
+    }
+
+    impl Eq for ResponseLine {}
+
     impl zeroize::DefaultIsZeroes for ResponseLine {}
 
-    /// Escapes char if it needs to be escaped, returns `None` otherwise
-    pub fn optionally_escape(x: char) -> Option<&'static str> {
+    /// Indicates whether the byte is a printable ASCII character
+    fn is_printable(x: u8) -> bool {
+        x == b' ' || x.is_ascii_graphic()
+    }
+
+    /// Size of the byte appended via [`ResponseLine::push_byte`]
+    fn escaped_byte_size(x: u8) -> usize {
+        if is_printable(x) && optionally_escape_byte(x).is_none() {
+            1
+        } else {
+            3
+        }
+    }
+
+    /// Escapes byte if it needs to be escaped, returns `None` otherwise
+    ///
+    /// Escaped bytes are `%`, `\r`, `\n` and `\\`. Used by [`ResponseLine`] that escapes
+    /// appended data automatically.
+    pub fn optionally_escape_byte(x: u8) -> Option<&'static str> {
         match x {
-            '%' => Some("%25"),
-            '\r' => Some("%0D"),
-            '\n' => Some("%0A"),
-            '\\' => Some("%5C"),
+            b'%' => Some("%25"),
+            b'\r' => Some("%0D"),
+            b'\n' => Some("%0A"),
+            b'\\' => Some("%5C"),
             _ => None,
         }
     }
@@ -505,6 +1398,19 @@ mod tests {
 
     use super::*;
 
+    /// Escapes char if it needs to be escaped, returns `None` otherwise
+    ///
+    /// Char-by-char escaping that's used as a reference for the optimized implementation
+    fn optionally_escape(x: char) -> Option<&'static str> {
+        match x {
+            '%' => Some("%25"),
+            '\r' => Some("%0D"),
+            '\n' => Some("%0A"),
+            '\\' => Some("%5C"),
+            _ => None,
+        }
+    }
+
     const CHARS: &[&[char]] = &[
         // 1-byte characters
         &['1', '2', '3', 'd', 'o', 'g'],
@@ -522,7 +1428,7 @@ mod tests {
     fn chars_have_expected_size() {
         for (i, chars) in CHARS.iter().enumerate() {
             for x in *chars {
-                if let Some(encoding) = builder::optionally_escape(*x) {
+                if let Some(encoding) = optionally_escape(*x) {
                     assert_eq!(encoding.len(), i + 1)
                 } else {
                     assert_eq!(x.len_utf8(), i + 1);
@@ -549,6 +1455,42 @@ mod tests {
         })
     }
 
+    #[test]
+    fn append_escapes_same_as_char_by_char() {
+        let mut rng = rand_dev::DevRng::new();
+
+        for len in [0, 1, 10, 100, Data::MAX_BYTES] {
+            let data: String = gen_str_of_len(&mut rng, len).collect();
+
+            let expected: String = data
+                .chars()
+                .map(|x| match optionally_escape(x) {
+                    Some(escaped) => escaped.to_string(),
+                    None => x.to_string(),
+                })
+                .collect();
+            let actual = ResponseLine::new().chain(&data).unwrap();
+
+            assert_eq!(actual.as_str(), expected);
+        }
+    }
+
+    #[test]
+    fn escaped_data_exceeding_limit_is_not_written() {
+        // Line takes up to 999 bytes, each newline takes 3 bytes once escaped
+        let max_size = crate::MAX_LINE_SIZE - 1;
+        let newlines = "\n".repeat(max_size / 3);
+        let line = ResponseLine::new().chain(&newlines).unwrap();
+        assert_eq!(line.size(), max_size);
+
+        // Raw data fits, but escaped data exceeds the limit by a single byte
+        let mut line = ResponseLine::new().chain("a").unwrap();
+        line.append(&newlines).unwrap_err();
+        assert_eq!(line.as_str(), "a");
+        line.append("b\n").unwrap();
+        assert_eq!(line.as_str(), "ab%0A");
+    }
+
     #[test]
     fn ok_response_max_size() {
         let mut rng = rand_dev::DevRng::new();
@@ -573,4 +1515,297 @@ mod tests {
         }
         assert_eq!(resp.pop(), None);
     }
+
+    #[test]
+    fn fingerprint_as_hex() {
+        let fingerprint: [u8; 20] = [
+            0x3A, 0xA5, 0xC3, 0x43, 0x71, 0x56, 0x7B, 0xD2, 0x00, 0x01, 0x02, 0x0F, 0x10, 0x7F,
+            0x80, 0x9E, 0xAB, 0xCD, 0xEF, 0xFF,
+        ];
+        let expected = "D 3AA5C34371567BD20001020F107F809EABCDEFFF\nOK success\n";
+
+        let mut output = vec![];
+        Response::from(Data::from_hex(&fingerprint).unwrap())
+            .write(&mut output)
+            .unwrap();
+        assert_eq!(String::from_utf8(output).unwrap(), expected);
+
+        let secret = Data::secret_from_hex(&fingerprint).unwrap();
+        assert_eq!(secret.size(), 40);
+        assert!(secret.chars().eq(expected[2..42].chars()));
+
+        let too_long = [0u8; Data::MAX_BYTES / 2 + 1];
+        assert!(Data::from_hex(&too_long).is_err());
+        assert!(Data::from_hex(&too_long[1..]).is_ok());
+    }
+
+    #[test]
+    fn secret_data_response() {
+        use zeroize::Zeroize;
+
+        let Response::SecretData(mut data) = Response::data_secret("my password").unwrap() else {
+            panic!("expected secret data response");
+        };
+        assert!(data.chars().eq("my password".chars()));
+
+        // That's what happens on drop
+        data.zeroize();
+        assert_eq!(data.size(), 0);
+
+        assert!(Response::data_secret(&"a".repeat(Data::MAX_BYTES + 1)).is_err());
+    }
+
+    #[test]
+    fn responses_are_compared_by_content() {
+        let data = Response::data("some data").unwrap();
+        let mut built = Data::new("some").unwrap();
+        built.append(" data").unwrap();
+        assert!(data == built.into());
+        assert!(data != Response::data("other data").unwrap());
+        assert!(data != Response::ok());
+        assert!(data != Response::data_secret("some data").unwrap());
+
+        let custom_ok = Data::new("some data")
+            .unwrap()
+            .with_custom_ok(Ok::with_debug_info("done").unwrap());
+        assert!(data != custom_ok.into());
+
+        assert!(Response::ok() == Ok::new().into());
+        assert!(Response::ok() != Response::ok_closing());
+        assert!(Response::data_stream("some data") == Response::data_stream("some data"));
+    }
+
+    #[test]
+    fn binary_data() {
+        let data = Data::new_bytes(b"0E\x02!\x00\xFF %\\\x7F\t").unwrap();
+        let mut output = vec![];
+        Response::from(data).write(&mut output).unwrap();
+        assert_eq!(output, b"D 0E%02!%00%FF %25%5C%7F%09\nOK success\n");
+
+        let mut data = Data::new_bytes(&[0; Data::MAX_BYTES / 3]).unwrap();
+        assert!(Data::new_bytes(&[0; Data::MAX_BYTES / 3 + 1]).is_err());
+        data.append_bytes(b"a").unwrap();
+        // Response is left unchanged if data doesn't fit
+        assert!(data.append_bytes(b"bc\x00").is_err());
+        assert_eq!(data.size(), Data::MAX_BYTES);
+        assert_eq!(data.pop(), Some('a'));
+    }
+
+    #[test]
+    fn data_from_byte_iterator() {
+        // Exactly fills a line: all bytes but the last one are percent-encoded into 3 bytes
+        let bytes = || std::iter::repeat_n(0xFF, Data::MAX_BYTES / 3).chain([b'a']);
+        let mut data = SecretData::default();
+        data.append_iter(bytes()).unwrap();
+        assert_eq!(data.size(), Data::MAX_BYTES);
+
+        // Line is full, response is left unchanged
+        let mut data = SecretData::default();
+        data.append("a").unwrap();
+        let mut iter = bytes();
+        assert!(data.append_iter(&mut iter).is_err());
+        assert_eq!(data.size(), 1);
+        assert_eq!(iter.count(), 0);
+
+        let mut stream = DataStream::new();
+        stream.append_iter(bytes().chain(bytes()));
+        let mut output = vec![];
+        Response::from(stream).write(&mut output).unwrap();
+        let line = format!("D {}a\n", "%FF".repeat(Data::MAX_BYTES / 3));
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            format!("{line}{line}OK success\n")
+        );
+    }
+
+    #[test]
+    fn debug_output_hides_data() {
+        let data = Data::new("my password").unwrap();
+        assert_eq!(format!("{data:?}"), "Data(..)");
+    }
+
+    #[test]
+    fn secrets_are_compared_by_decoded_bytes() {
+        let secret = |parts: &[&[u8]]| {
+            let mut data = SecretData::default();
+            for part in parts {
+                data.append_bytes(part).unwrap();
+            }
+            data
+        };
+        let pin = secret(&[b"12%4\n"]);
+        assert!(secret_eq(&pin, &secret(&[b"12", b"%4\n"])));
+        assert!(!secret_eq(&pin, &secret(&[b"12%4"])));
+        assert!(!secret_eq(&pin, &secret(&[b"12%4\n5"])));
+        assert!(!secret_eq(&pin, &secret(&[b"12%5\n"])));
+        assert!(!secret_eq(&pin, &secret(&[])));
+        assert!(secret_eq(&secret(&[]), &secret(&[])));
+
+        // Non-ASCII char is encoded differently when appended as bytes
+        let mut text = SecretData::default();
+        text.append("\u{e9}").unwrap();
+        let bytes = secret(&["\u{e9}".as_bytes()]);
+        assert_ne!(text.size(), bytes.size());
+        assert!(secret_eq(&text, &bytes));
+    }
+
+    #[test]
+    fn data_stream_splits_lines() {
+        // Percent-escape lands on a line boundary
+        let data = format!(
+            "{}%{}\n\u{e9}",
+            "a".repeat(Data::MAX_BYTES - 1),
+            "b".repeat(10)
+        );
+        let mut stream = DataStream::new();
+        stream.append(&data);
+        stream.append_bytes(b"\x00\xFFz");
+
+        let mut output = vec![];
+        Response::from(stream).write(&mut output).unwrap();
+        let output = String::from_utf8(output).unwrap();
+        let lines: Vec<_> = output.lines().collect();
+        assert_eq!(lines.len(), 3);
+        assert_eq!(lines[0], format!("D {}", "a".repeat(Data::MAX_BYTES - 1)));
+        assert_eq!(lines[1], format!("D %25{}%0A\u{e9}%00%FFz", "b".repeat(10)));
+        assert_eq!(lines[2], "OK success");
+        assert!(lines.iter().all(|l| l.len() < crate::MAX_LINE_SIZE));
+
+        let large = "x".repeat(10 * Data::MAX_BYTES);
+        let mut output = vec![];
+        Response::data_stream(&large).write(&mut output).unwrap();
+        let output = String::from_utf8(output).unwrap();
+        assert_eq!(output.lines().filter(|l| l.starts_with("D ")).count(), 10);
+        assert_eq!(output.lines().filter(|l| l.starts_with("OK")).count(), 1);
+        let received: String = output
+            .lines()
+            .filter_map(|l| l.strip_prefix("D "))
+            .collect();
+        assert_eq!(received, large);
+    }
+
+    #[test]
+    fn bare_ok() {
+        let write = |resp: Response| {
+            let mut out = vec![];
+            resp.write(&mut out).unwrap();
+            String::from_utf8(out).unwrap()
+        };
+
+        assert_eq!(write(Response::ok_bare()), "OK\n");
+        assert_eq!(write(Response::ok()), "OK success\n");
+        assert_eq!(Ok::bare().size(), 0);
+
+        let mut ok = Ok::bare();
+        ok.push('1').unwrap();
+        ok.append("00%").unwrap();
+        assert_eq!(ok.size(), 6);
+        assert_eq!(write(ok.into()), "OK 100%25\n");
+
+        let data = Data::new("1234").unwrap().with_custom_ok(Ok::bare());
+        assert_eq!(write(data.into()), "D 1234\nOK\n");
+    }
+
+    #[test]
+    fn error_line_escapes_desc() {
+        let line = super::error_line(crate::ErrorCode::ASS_PARAMETER, "bad\nvalue 100%").unwrap();
+        assert_eq!(line.as_str(), "ERR 280 bad%0Avalue 100%25");
+
+        let code = crate::ErrorCode::NO_PIN.with_source(crate::error_code::Source::PINENTRY);
+        let line = super::error_line(code, "").unwrap();
+        assert_eq!(line.as_str(), "ERR 83886258 ");
+
+        let long = "%".repeat(crate::MAX_LINE_SIZE / 3);
+        assert!(super::error_line(crate::ErrorCode::INTERNAL, &long).is_err());
+    }
+
+    #[test]
+    fn status_is_sent_before_response() {
+        let write = |resp: Response| {
+            let mut out = vec![];
+            resp.write(&mut out).unwrap();
+            String::from_utf8(out).unwrap()
+        };
+
+        let resp = Response::ok().with_status("WARNING", "weak-key").unwrap();
+        assert_eq!(write(resp), "S WARNING weak-key\nOK success\n");
+
+        let resp = Response::data("1234")
+            .unwrap()
+            .with_status("PROGRESS", "50%")
+            .unwrap()
+            .with_status("DONE", "")
+            .unwrap();
+        assert_eq!(
+            write(resp),
+            "S PROGRESS 50%25\nS DONE\nD 1234\nOK success\n"
+        );
+
+        let resp = Response::ok_closing().with_status("BYE", "").unwrap();
+        assert!(resp.connection_needs_be_closed());
+
+        assert!(Response::ok()
+            .with_status("WARNING", &"a".repeat(crate::MAX_LINE_SIZE))
+            .is_err());
+    }
+
+    #[test]
+    fn ok_closing() {
+        let resp = Response::ok_closing();
+        assert!(resp.connection_needs_be_closed());
+        let mut out = vec![];
+        resp.write(&mut out).unwrap();
+        assert_eq!(out, b"OK success\n");
+
+        let resp = Response::ok_closing_with_info("see you\nlater").unwrap();
+        assert!(resp.connection_needs_be_closed());
+        let mut out = vec![];
+        resp.write(&mut out).unwrap();
+        assert_eq!(out, b"OK see you%0Alater\n");
+
+        assert!(!Response::ok().connection_needs_be_closed());
+    }
+
+    #[test]
+    fn response_is_send() {
+        fn assert_send<T: Send>() {}
+        assert_send::<Response>();
+    }
+
+    #[test]
+    fn multiline_message_as_comments() {
+        let resp = Response::ok_with_comments("first line\nsecond 100%\r\nthird line\n").unwrap();
+        assert!(!resp.connection_needs_be_closed());
+        let mut out = vec![];
+        resp.write(&mut out).unwrap();
+        assert_eq!(
+            out,
+            b"# first line\n# second 100%25\n# third line\nOK success\n"
+        );
+
+        let resp: Response = Commented::new(&"a".repeat(Commented::MAX_LINE_BYTES))
+            .unwrap()
+            .with_custom_ok(Ok::new().close_connection(true))
+            .into();
+        assert!(resp.connection_needs_be_closed());
+
+        let too_long = format!("ok\n{}", "a".repeat(Commented::MAX_LINE_BYTES + 1));
+        assert!(Commented::new(&too_long).is_err());
+    }
+
+    #[cfg(feature = "secrecy")]
+    #[test]
+    fn secrecy_round_trip() {
+        use secrecy::{ExposeSecret, SecretString};
+
+        let secret = SecretString::from("pass%word\n");
+        let data = Data::new_secret(&secret).unwrap();
+        assert!(data.chars().eq("pass%word\n".chars()));
+
+        let secret = SecretString::from(&**data);
+        assert_eq!(secret.expose_secret(), "pass%word\n");
+
+        let too_long = SecretString::from("a".repeat(Data::MAX_BYTES + 1));
+        assert!(matches!(Data::new_secret(&too_long), Err(TooLong)));
+    }
 }
@@ -4,40 +4,62 @@ use std::fmt;
 
 pub use either::Either;
 
-use crate::{ErrorCode, HasErrorCode, Response};
+use crate::{
+    conn::ReadWrite, percent_decode::MalformedEncoding, CmdCtx, ErrorCode, HasErrorCode, Response,
+};
 
 /// List of registered commands
 pub trait CmdList<S> {
-    /// Error type returned by [handle](Self::handle)
+    /// Error type returned by [handle_encoded](Self::handle_encoded)
     type Error: fmt::Display + HasErrorCode;
 
     /// Routes the command execution
     ///
     /// Calling this function attempts to find a command `cmd` in the list. If it's present,
-    /// the command handler function is called with `state` and `params` being the arguments,
+    /// the command handler function is called with `state`, `params` and `ctx` being the arguments,
     /// `Some(response)` is returned. If command is not found in the list, `None` is returned.
-    fn handle(
+    ///
+    /// `params` are passed as they were received from the client, i.e. percent-encoded, and it's
+    /// up to the command handler to decode them (e.g. via
+    /// [`percent_decode`](crate::percent_decode::percent_decode)). Malformed encoding is
+    /// reported by the handler as any other error, the connection is kept open.
+    ///
+    /// Handlers registered via [`AssuanServer`](crate::AssuanServer) methods decode the params
+    /// before calling the user function. If params have malformed percent encoding, the command
+    /// fails with [`ASS_PARAMETER`](ErrorCode::ASS_PARAMETER) error.
+    ///
+    /// `cmd` is passed as it was received from the client too. Command names are matched
+    /// ignoring ASCII case, e.g. `bye` is the same command as `BYE`.
+    fn handle_encoded(
         &mut self,
         cmd: &str,
         state: &mut S,
         params: Option<&str>,
+        ctx: &mut CmdCtx<'_>,
     ) -> Option<Result<Response, Self::Error>>;
+
+    /// Returns names of the commands in the list in order of registration
+    ///
+    /// Used to answer `HELP` request. Default implementation returns no names.
+    fn command_names(&self) -> impl Iterator<Item = &'static str> {
+        std::iter::empty()
+    }
 }
 
 /// Prepends a new command to the [list of commands](CmdList)
 ///
 /// Not part of public API as it's a bit complex. [`AssuanServer::add_command`](crate::AssuanServer::add_command)
 /// returns `impl CmdList<S>` in order to hide this type.
-pub(crate) struct Cons<F, L> {
+pub(crate) struct Cons<H, L> {
     cmd_name: &'static str,
-    handler: F,
+    handler: H,
     tail: L,
 }
 
-impl<F, L> Cons<F, L> {
+impl<H, L> Cons<H, L> {
     /// Constructs a new [list of commands](CmdList) that has command with `name` and `handler`
     /// as the first element in the list, followed by a list `tail`
-    pub fn new(name: &'static str, handler: F, tail: L) -> Self {
+    pub fn new(name: &'static str, handler: H, tail: L) -> Self {
         Self {
             cmd_name: name,
             handler,
@@ -46,28 +68,351 @@ impl<F, L> Cons<F, L> {
     }
 }
 
-impl<F, S, E, L> CmdList<S> for Cons<F, L>
+impl<H, S, L> CmdList<S> for Cons<H, L>
 where
-    F: FnMut(&mut S, Option<&str>) -> Result<Response, E>,
+    H: Handler<S>,
     L: CmdList<S>,
+{
+    type Error = Either<H::Error, L::Error>;
+
+    fn handle_encoded(
+        &mut self,
+        cmd: &str,
+        state: &mut S,
+        params: Option<&str>,
+        ctx: &mut CmdCtx<'_>,
+    ) -> Option<Result<Response, Self::Error>> {
+        if cmd.eq_ignore_ascii_case(self.cmd_name) {
+            Some(self.handler.call(state, params, ctx).map_err(Either::Left))
+        } else {
+            self.tail
+                .handle_encoded(cmd, state, params, ctx)
+                .map(|result| result.map_err(Either::Right))
+        }
+    }
+
+    fn command_names(&self) -> impl Iterator<Item = &'static str> {
+        // Tail was registered earlier
+        self.tail
+            .command_names()
+            .chain(std::iter::once(self.cmd_name))
+    }
+}
+
+/// Prepends a `GETINFO` provider to the [list of commands](CmdList)
+///
+/// Answers `GETINFO key` request, passes other requests to the tail. See
+/// [`AssuanServer::add_getinfo`](crate::AssuanServer::add_getinfo).
+pub(crate) struct GetInfo<P, L> {
+    key: &'static str,
+    provider: P,
+    tail: L,
+}
+
+impl<P, L> GetInfo<P, L> {
+    pub fn new(key: &'static str, provider: P, tail: L) -> Self {
+        Self {
+            key,
+            provider,
+            tail,
+        }
+    }
+}
+
+impl<P, S, E, L> CmdList<S> for GetInfo<P, L>
+where
+    P: Fn(&S) -> Result<String, E>,
     E: fmt::Display + HasErrorCode,
+    L: CmdList<S>,
 {
     type Error = Either<E, L::Error>;
 
-    fn handle(
+    fn handle_encoded(
         &mut self,
         cmd: &str,
         state: &mut S,
         params: Option<&str>,
+        ctx: &mut CmdCtx<'_>,
     ) -> Option<Result<Response, Self::Error>> {
-        if cmd == self.cmd_name {
-            Some((self.handler)(state, params).map_err(Either::Left))
+        // Malformed key is never ours, tail reports the error
+        if cmd.eq_ignore_ascii_case("GETINFO")
+            && decode_str(params).ok().flatten().as_deref() == Some(self.key)
+        {
+            Some(
+                (self.provider)(state)
+                    .map(|value| Response::data_stream(&value))
+                    .map_err(Either::Left),
+            )
         } else {
             self.tail
-                .handle(cmd, state, params)
+                .handle_encoded(cmd, state, params, ctx)
                 .map(|result| result.map_err(Either::Right))
         }
     }
+
+    fn command_names(&self) -> impl Iterator<Item = &'static str> {
+        self.tail.command_names()
+    }
+}
+
+/// Wraps the [list of commands](CmdList) with a fallback handling unknown commands
+///
+/// Fallback isn't called for `GETINFO` and `HELP` as they're answered by the server. See
+/// [`AssuanServer::with_fallback`](crate::AssuanServer::with_fallback).
+pub(crate) struct Fallback<F, L> {
+    fallback: F,
+    list: L,
+}
+
+impl<F, L> Fallback<F, L> {
+    pub fn new(fallback: F, list: L) -> Self {
+        Self { fallback, list }
+    }
+}
+
+impl<F, S, E, L> CmdList<S> for Fallback<F, L>
+where
+    F: FnMut(&mut S, &str, Option<&str>) -> Result<Response, E>,
+    E: fmt::Display + HasErrorCode,
+    L: CmdList<S>,
+{
+    type Error = Either<E, L::Error>;
+
+    fn handle_encoded(
+        &mut self,
+        cmd: &str,
+        state: &mut S,
+        params: Option<&str>,
+        ctx: &mut CmdCtx<'_>,
+    ) -> Option<Result<Response, Self::Error>> {
+        match self.list.handle_encoded(cmd, state, params, ctx) {
+            Some(result) => Some(result.map_err(Either::Right)),
+            None if cmd.eq_ignore_ascii_case("GETINFO") || cmd.eq_ignore_ascii_case("HELP") => None,
+            None => Some((self.fallback)(state, cmd, params).map_err(Either::Left)),
+        }
+    }
+
+    fn command_names(&self) -> impl Iterator<Item = &'static str> {
+        self.list.command_names()
+    }
+}
+
+/// Command handler
+///
+/// Receives `params` percent-encoded, the way they were sent by the client. Implementations
+/// decode the params and pass them to the handler function registered by the user.
+pub(crate) trait Handler<S> {
+    type Error: fmt::Display + HasErrorCode;
+
+    fn call(
+        &mut self,
+        state: &mut S,
+        params: Option<&str>,
+        ctx: &mut CmdCtx<'_>,
+    ) -> Result<Response, Self::Error>;
+}
+
+/// Decodes percent-encoded params into a string
+fn decode_str(params: Option<&str>) -> Result<Option<String>, MalformedEncoding> {
+    params
+        .map(|params| crate::percent_decode::percent_decode(params).collect())
+        .transpose()
+}
+
+/// Handler registered via [`AssuanServer::add_command`](crate::AssuanServer::add_command)
+pub(crate) struct StrHandler<F>(pub F);
+
+impl<F, S, E> Handler<S> for StrHandler<F>
+where
+    F: FnMut(&mut S, Option<&str>) -> Result<Response, E>,
+    E: fmt::Display + HasErrorCode,
+{
+    type Error = Either<MalformedEncoding, E>;
+
+    fn call(
+        &mut self,
+        state: &mut S,
+        params: Option<&str>,
+        _ctx: &mut CmdCtx<'_>,
+    ) -> Result<Response, Self::Error> {
+        let params = decode_str(params).map_err(Either::Left)?;
+        (self.0)(state, params.as_deref()).map_err(Either::Right)
+    }
+}
+
+/// Handler registered via [`AssuanServer::add_command_with_ctx`](crate::AssuanServer::add_command_with_ctx)
+pub(crate) struct CtxHandler<F>(pub F);
+
+impl<F, S, E> Handler<S> for CtxHandler<F>
+where
+    F: FnMut(&mut S, Option<&str>, &mut CmdCtx<'_>) -> Result<Response, E>,
+    E: fmt::Display + HasErrorCode,
+{
+    type Error = Either<MalformedEncoding, E>;
+
+    fn call(
+        &mut self,
+        state: &mut S,
+        params: Option<&str>,
+        ctx: &mut CmdCtx<'_>,
+    ) -> Result<Response, Self::Error> {
+        let params = decode_str(params).map_err(Either::Left)?;
+        (self.0)(state, params.as_deref(), ctx).map_err(Either::Right)
+    }
+}
+
+/// Handler registered via [`AssuanServer::add_command_bytes`](crate::AssuanServer::add_command_bytes)
+pub(crate) struct BytesHandler<F>(pub F);
+
+impl<F, S, E> Handler<S> for BytesHandler<F>
+where
+    F: FnMut(&mut S, Option<&[u8]>) -> Result<Response, E>,
+    E: fmt::Display + HasErrorCode,
+{
+    type Error = Either<MalformedEncoding, E>;
+
+    fn call(
+        &mut self,
+        state: &mut S,
+        params: Option<&str>,
+        _ctx: &mut CmdCtx<'_>,
+    ) -> Result<Response, Self::Error> {
+        let params = params
+            .map(|params| {
+                crate::percent_decode::percent_decode_bytes(params.as_bytes())
+                    .collect::<Result<Vec<u8>, _>>()
+                    .map(zeroize::Zeroizing::new)
+            })
+            .transpose()
+            .map_err(Either::Left)?;
+        (self.0)(state, params.as_ref().map(|params| params.as_slice())).map_err(Either::Right)
+    }
+}
+
+/// Handler registered via [`AssuanServer::add_command_raw`](crate::AssuanServer::add_command_raw)
+pub(crate) struct RawHandler<F>(pub F);
+
+impl<F, S, E> Handler<S> for RawHandler<F>
+where
+    F: FnMut(&mut S, Option<&str>) -> Result<Response, E>,
+    E: fmt::Display + HasErrorCode,
+{
+    type Error = E;
+
+    fn call(
+        &mut self,
+        state: &mut S,
+        params: Option<&str>,
+        _ctx: &mut CmdCtx<'_>,
+    ) -> Result<Response, Self::Error> {
+        (self.0)(state, params)
+    }
+}
+
+/// Handler registered via [`AssuanServer::add_command_interactive`](crate::AssuanServer::add_command_interactive)
+pub(crate) struct InteractiveHandler<F>(pub F);
+
+impl<F, S, E> Handler<S> for InteractiveHandler<F>
+where
+    F: FnMut(&mut S, Option<&str>, &mut dyn ReadWrite) -> Result<Option<Response>, E>,
+    E: fmt::Display + HasErrorCode,
+{
+    type Error = Either<MalformedEncoding, E>;
+
+    fn call(
+        &mut self,
+        state: &mut S,
+        params: Option<&str>,
+        ctx: &mut CmdCtx<'_>,
+    ) -> Result<Response, Self::Error> {
+        let params = decode_str(params).map_err(Either::Left)?;
+        let response = (self.0)(state, params.as_deref(), &mut ctx.raw_conn());
+        match response.map_err(Either::Right)? {
+            Some(response) => Ok(response),
+            None => {
+                ctx.suppress_response();
+                Ok(Response::ok())
+            }
+        }
+    }
+}
+
+/// Handler that is only called when the precondition holds
+///
+/// See [`AssuanServer::add_command_with_precondition`](crate::AssuanServer::add_command_with_precondition)
+pub(crate) struct Guarded<P, H> {
+    pub precondition: P,
+    pub handler: H,
+}
+
+impl<P, H, S, E> Handler<S> for Guarded<P, H>
+where
+    P: Fn(&S) -> Result<(), E>,
+    E: fmt::Display + HasErrorCode,
+    H: Handler<S>,
+{
+    type Error = Either<E, H::Error>;
+
+    fn call(
+        &mut self,
+        state: &mut S,
+        params: Option<&str>,
+        ctx: &mut CmdCtx<'_>,
+    ) -> Result<Response, Self::Error> {
+        (self.precondition)(state).map_err(Either::Left)?;
+        self.handler.call(state, params, ctx).map_err(Either::Right)
+    }
+}
+
+/// Handler that is only called when decoded params fit into the limit
+///
+/// See [`AssuanServer::add_command_with_max_args_len`](crate::AssuanServer::add_command_with_max_args_len)
+pub(crate) struct Limited<H> {
+    pub max_args_len: usize,
+    pub handler: H,
+}
+
+impl<H, S> Handler<S> for Limited<H>
+where
+    H: Handler<S>,
+{
+    type Error = Either<ArgsTooLarge, H::Error>;
+
+    fn call(
+        &mut self,
+        state: &mut S,
+        params: Option<&str>,
+        ctx: &mut CmdCtx<'_>,
+    ) -> Result<Response, Self::Error> {
+        // Decoded params are counted without being allocated. Malformed encoding is reported
+        // by the handler
+        let len = params
+            .map(|params| crate::percent_decode::percent_decode_bytes(params.as_bytes()).count())
+            .unwrap_or(0);
+        if len > self.max_args_len {
+            return Err(Either::Left(ArgsTooLarge {
+                max: self.max_args_len,
+            }));
+        }
+        self.handler.call(state, params, ctx).map_err(Either::Right)
+    }
+}
+
+/// Decoded params exceed the limit set for the command
+pub(crate) struct ArgsTooLarge {
+    max: usize,
+}
+
+impl fmt::Display for ArgsTooLarge {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "arguments exceed {} bytes", self.max)
+    }
+}
+
+impl HasErrorCode for ArgsTooLarge {
+    fn code(&self) -> ErrorCode {
+        ErrorCode::TOO_LARGE
+    }
 }
 
 /// Empty [list of commands](CmdList)
@@ -77,11 +422,12 @@ impl<S> CmdList<S> for Nil {
     type Error = std::convert::Infallible;
 
     /// Always returns `None`
-    fn handle(
+    fn handle_encoded(
         &mut self,
         _cmd: &str,
         _state: &mut S,
         _params: Option<&str>,
+        _ctx: &mut CmdCtx<'_>,
     ) -> Option<Result<Response, Self::Error>> {
         None
     }
@@ -92,6 +438,11 @@ impl<S> CmdList<S> for Nil {
 /// Contains commands:
 /// * `BYE` that always responds with `OK` and terminates the connection
 /// * `NOP` that always responds with `OK` and doesn't do anything else
+/// * `RESET` that always responds with `OK`, the service may observe it by registering
+///   [a reset handler](crate::AssuanServer::add_reset_handler)
+///
+/// `HELP` isn't listed here: it's answered by the server itself, as listing the commands
+/// requires seeing the whole [`CmdList`].
 pub struct PredefinedCmds<L = Nil> {
     tail: L,
 }
@@ -119,28 +470,43 @@ impl<L> PredefinedCmds<L> {
 impl<S, L: CmdList<S>> CmdList<S> for PredefinedCmds<L> {
     type Error = L::Error;
 
-    fn handle(
+    fn handle_encoded(
         &mut self,
         cmd: &str,
         state: &mut S,
         params: Option<&str>,
+        ctx: &mut CmdCtx<'_>,
     ) -> Option<Result<Response, Self::Error>> {
         use crate::response;
-        match cmd {
-            "NOP" => {
+        let predefined = ["NOP", "BYE", "RESET"]
+            .into_iter()
+            .find(|name| cmd.eq_ignore_ascii_case(name));
+        match predefined {
+            Some("NOP") => {
                 // No operation. Returns OK without any action.
                 Some(Ok(response::Ok::new().into()))
             }
-            "BYE" => {
+            Some("RESET") => {
+                // Reset the state. Nothing to reset unless service registered its own handler
+                // which takes precedence over this one.
+                Some(Ok(Response::ok()))
+            }
+            Some("BYE") => {
                 // Close the connection. The server will respond with OK.
-                Some(Ok(response::Ok::new().close_connection(true).into()))
+                Some(Ok(Response::ok_closing()))
             }
             _ => {
                 // It is not a system command
-                self.tail.handle(cmd, state, params)
+                self.tail.handle_encoded(cmd, state, params, ctx)
             }
         }
     }
+
+    fn command_names(&self) -> impl Iterator<Item = &'static str> {
+        ["NOP", "BYE", "RESET"]
+            .into_iter()
+            .chain(self.tail.command_names())
+    }
 }
 
 impl<L, R> HasErrorCode for Either<L, R>
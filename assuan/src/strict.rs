@@ -0,0 +1,77 @@
+//! Spec rules enforced in [strict mode](crate::AssuanServer::strict)
+
+use std::fmt;
+
+use crate::{ErrorCode, HasErrorCode};
+
+/// Checks that the received line conforms to the assuan spec
+///
+/// Applies to every line, including comments and empty lines
+pub fn check_line(line: &str) -> Result<(), SpecViolation> {
+    if line.contains('\0') {
+        return Err(SpecViolation::NulCharacter);
+    }
+    Ok(())
+}
+
+/// Checks that the request conforms to the assuan spec
+///
+/// `cmd` and `args` are parts of the request line, args are not decoded yet
+pub fn check_request(cmd: &str, args: Option<&str>) -> Result<(), SpecViolation> {
+    if cmd.chars().any(|x| x.is_control()) {
+        return Err(SpecViolation::ControlCharInCmd);
+    }
+    if cmd.chars().any(|x| x.is_lowercase()) {
+        return Err(SpecViolation::LowercaseCmd);
+    }
+    if cmd == "OPTION" {
+        check_option(args.unwrap_or_default())?;
+    }
+    Ok(())
+}
+
+/// Checks that `OPTION` args have form `name [[=] value]`
+///
+/// Name may be surrounded by spaces and prefixed with two dashes
+fn check_option(args: &str) -> Result<(), SpecViolation> {
+    let args = args.trim_start();
+    let args = args.strip_prefix("--").unwrap_or(args);
+    let name_len = args
+        .find(|x: char| !(x.is_ascii_alphanumeric() || x == '-' || x == '_'))
+        .unwrap_or(args.len());
+    let (name, rest) = args.split_at(name_len);
+    if name.is_empty() || !(rest.is_empty() || rest.starts_with([' ', '='])) {
+        return Err(SpecViolation::MalformedOption);
+    }
+    Ok(())
+}
+
+/// Request that doesn't conform to the spec
+#[derive(Debug)]
+pub enum SpecViolation {
+    NulCharacter,
+    ControlCharInCmd,
+    LowercaseCmd,
+    MalformedOption,
+}
+
+impl fmt::Display for SpecViolation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::NulCharacter => f.write_str("line contains NUL character"),
+            Self::ControlCharInCmd => f.write_str("command contains control characters"),
+            Self::LowercaseCmd => f.write_str("command must be in uppercase"),
+            Self::MalformedOption => f.write_str("malformed option"),
+        }
+    }
+}
+
+impl HasErrorCode for SpecViolation {
+    fn code(&self) -> ErrorCode {
+        match self {
+            Self::NulCharacter => ErrorCode::ASS_INV_VALUE,
+            Self::ControlCharInCmd | Self::LowercaseCmd => ErrorCode::ASS_SYNTAX,
+            Self::MalformedOption => ErrorCode::ASS_PARAMETER,
+        }
+    }
+}
@@ -0,0 +1,431 @@
+//! Serves clients connecting over a unix domain socket
+//!
+//! That's how gpg-agent talks to its helpers like scdaemon. [`UnixSocketServer`] takes care of
+//! creating the socket, accepting connections and removing the socket once it's not needed.
+//! Connecting processes may be authenticated via their [credentials](PeerCreds).
+//!
+//! ### Example
+//! ```rust,no_run
+//! use assuan::{unix_socket::UnixSocketServer, AssuanServer};
+//!
+//! let socket = UnixSocketServer::bind("/run/user/1000/my-agent.sock")?;
+//! socket.serve(|| AssuanServer::new(()))?;
+//! # Ok::<_, std::io::Error>(())
+//! ```
+
+use std::{
+    io,
+    os::unix::{
+        fs::PermissionsExt,
+        net::{UnixListener, UnixStream},
+    },
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+};
+
+use crate::{router::CmdList, AssuanServer};
+
+/// Binds the socket at `path` and serves clients connecting to it
+///
+/// Shortcut for [`UnixSocketServer::bind`] followed by [`UnixSocketServer::serve`]. Serves the
+/// clients forever, unless accepting a connection fails.
+pub fn serve_unix_socket<S, L>(
+    path: impl AsRef<Path>,
+    make_server: impl FnMut() -> AssuanServer<S, L>,
+) -> io::Result<()>
+where
+    L: CmdList<S>,
+{
+    UnixSocketServer::bind(path)?.serve(make_server)
+}
+
+/// Unix domain socket accepting assuan clients
+///
+/// Socket file is removed when the server is dropped.
+pub struct UnixSocketServer {
+    listener: UnixListener,
+    path: PathBuf,
+    shutdown: Arc<AtomicBool>,
+}
+
+impl UnixSocketServer {
+    /// Creates a socket at `path`
+    ///
+    /// Socket is only accessible by the owner (it has `0600` permissions): it's created under
+    /// a temporary name and moved to `path` once the permissions are set, so no one can
+    /// connect to it in between. Returns error if `path` already exists.
+    pub fn bind(path: impl AsRef<Path>) -> io::Result<Self> {
+        let path = path.as_ref();
+        if path.symlink_metadata().is_ok() {
+            return Err(io::ErrorKind::AlreadyExists.into());
+        }
+
+        let mut tmp_name = path.as_os_str().to_owned();
+        tmp_name.push(format!(".{}.tmp", std::process::id()));
+        let tmp_path = PathBuf::from(tmp_name);
+
+        let listener = UnixListener::bind(&tmp_path)?;
+        let moved = std::fs::set_permissions(&tmp_path, std::fs::Permissions::from_mode(0o600))
+            .and_then(|()| std::fs::rename(&tmp_path, path));
+        if let Err(err) = moved {
+            let _ = std::fs::remove_file(&tmp_path);
+            return Err(err);
+        }
+
+        Ok(Self {
+            listener,
+            path: path.to_owned(),
+            shutdown: Arc::new(AtomicBool::new(false)),
+        })
+    }
+
+    /// Accepts the clients and serves them one after another
+    ///
+    /// Every client is served by a fresh server constructed by `make_server`, so state of
+    /// one client never leaks to another. Errors that occur while serving a client only end
+    /// that client's connection. Returns `Ok(())` once [shut down](ShutdownHandle::shutdown)
+    /// or once a command handler [shut down the server](crate::response::Ok::shutdown_server),
+    /// or error if accepting a connection failed.
+    pub fn serve<S, L>(&self, mut make_server: impl FnMut() -> AssuanServer<S, L>) -> io::Result<()>
+    where
+        L: CmdList<S>,
+    {
+        self.accept_loop(|stream| {
+            let mut server = make_server();
+            let _ = server.serve_unix_stream(stream);
+            server.is_shutdown_requested()
+        })
+    }
+
+    /// Accepts the clients and serves them one after another, telling `make_server` who's
+    /// connected
+    ///
+    /// Same as [`serve`](Self::serve), but `make_server` receives [credentials](PeerCreds) of
+    /// the connected process, so the service can reject requests from unexpected users, e.g.
+    /// with [`ASS_PARAMETER`](crate::ErrorCode::ASS_PARAMETER) error. If credentials can't be
+    /// obtained, the connection is closed without serving it.
+    ///
+    /// ### Example
+    /// ```rust,no_run
+    /// use assuan::{unix_socket::UnixSocketServer, AssuanServer, ErrorCode, Response, WithErrorCode};
+    ///
+    /// let socket = UnixSocketServer::bind("/run/user/1000/my-agent.sock")?;
+    /// socket.serve_with_creds(|peer| {
+    ///     AssuanServer::new(peer).add_command_with_precondition(
+    ///         "GETKEY",
+    ///         |peer: &_| {
+    ///             if peer.uid == 1000 {
+    ///                 Ok(())
+    ///             } else {
+    ///                 Err(WithErrorCode {
+    ///                     code: ErrorCode::ASS_PARAMETER,
+    ///                     error: "access denied",
+    ///                 })
+    ///             }
+    ///         },
+    ///         |_: &mut _, _args: Option<&str>| Response::data("key"),
+    ///     )
+    /// })?;
+    /// # Ok::<_, std::io::Error>(())
+    /// ```
+    pub fn serve_with_creds<S, L>(
+        &self,
+        mut make_server: impl FnMut(PeerCreds) -> AssuanServer<S, L>,
+    ) -> io::Result<()>
+    where
+        L: CmdList<S>,
+    {
+        self.accept_loop(|stream| {
+            let Ok(creds) = PeerCreds::of(stream) else {
+                return false;
+            };
+            let mut server = make_server(creds);
+            let _ = server.serve_unix_stream(stream);
+            server.is_shutdown_requested()
+        })
+    }
+
+    /// Accepts connections and passes them to `serve` until shut down
+    ///
+    /// `serve` returns `true` if the server was shut down by the client
+    fn accept_loop(&self, mut serve: impl FnMut(&UnixStream) -> bool) -> io::Result<()> {
+        loop {
+            let stream = match self.listener.accept() {
+                Ok((stream, _addr)) => stream,
+                Err(err)
+                    if matches!(
+                        err.kind(),
+                        io::ErrorKind::Interrupted | io::ErrorKind::ConnectionAborted
+                    ) =>
+                {
+                    continue
+                }
+                Err(err) => return Err(err),
+            };
+            if self.shutdown.load(Ordering::SeqCst) {
+                return Ok(());
+            }
+            if serve(&stream) {
+                return Ok(());
+            }
+        }
+    }
+
+    /// Returns a handle that can be used to stop [serving](Self::serve) the clients from
+    /// another thread
+    pub fn shutdown_handle(&self) -> ShutdownHandle {
+        ShutdownHandle {
+            path: self.path.clone(),
+            shutdown: self.shutdown.clone(),
+        }
+    }
+
+    /// Path of the socket
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl Drop for UnixSocketServer {
+    fn drop(&mut self) {
+        // Errors are ignored: there's nothing we can do about them on drop
+        let _ = std::fs::remove_file(&self.path);
+    }
+}
+
+/// Stops [`UnixSocketServer`] from serving the clients
+#[derive(Clone)]
+pub struct ShutdownHandle {
+    path: PathBuf,
+    shutdown: Arc<AtomicBool>,
+}
+
+impl ShutdownHandle {
+    /// Makes [`UnixSocketServer::serve`] return once the client being served, if any, is
+    /// served
+    pub fn shutdown(&self) {
+        self.shutdown.store(true, Ordering::SeqCst);
+        // Wake up the server waiting for a connection. If it fails, the server has already
+        // stopped accepting connections.
+        let _ = UnixStream::connect(&self.path);
+    }
+}
+
+/// Credentials of the process connected to the unix socket
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PeerCreds {
+    /// User ID of the process
+    pub uid: u32,
+    /// Group ID of the process
+    pub gid: u32,
+    /// Process ID
+    ///
+    /// Only available on Linux and Android
+    pub pid: Option<i32>,
+}
+
+impl PeerCreds {
+    /// Obtains credentials of the process on the other end of the `stream`
+    ///
+    /// Credentials are the ones the process had when it connected to the socket.
+    pub fn of(stream: &UnixStream) -> io::Result<Self> {
+        use std::os::fd::AsRawFd;
+        let fd = stream.as_raw_fd();
+
+        #[cfg(any(target_os = "linux", target_os = "android"))]
+        {
+            let mut creds = libc::ucred {
+                pid: 0,
+                uid: 0,
+                gid: 0,
+            };
+            let mut len = std::mem::size_of::<libc::ucred>() as libc::socklen_t;
+            // SAFETY: `creds` and `len` are valid for writes, `len` is the size of `creds`
+            let result = unsafe {
+                libc::getsockopt(
+                    fd,
+                    libc::SOL_SOCKET,
+                    libc::SO_PEERCRED,
+                    (&mut creds as *mut libc::ucred).cast(),
+                    &mut len,
+                )
+            };
+            if result != 0 {
+                return Err(io::Error::last_os_error());
+            }
+            Ok(Self {
+                uid: creds.uid,
+                gid: creds.gid,
+                pid: Some(creds.pid),
+            })
+        }
+
+        #[cfg(not(any(target_os = "linux", target_os = "android")))]
+        {
+            let (mut uid, mut gid) = (0, 0);
+            // SAFETY: `uid` and `gid` are valid for writes
+            if unsafe { libc::getpeereid(fd, &mut uid, &mut gid) } != 0 {
+                return Err(io::Error::last_os_error());
+            }
+            Ok(Self {
+                uid,
+                gid,
+                pid: None,
+            })
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{Read, Write};
+    use std::os::unix::{fs::PermissionsExt, net::UnixStream};
+
+    use super::{PeerCreds, UnixSocketServer};
+    use crate::{response::Response, AssuanServer, ErrorCode, WithErrorCode};
+
+    #[test]
+    fn peer_creds() {
+        let (server_end, _client_end) = UnixStream::pair().unwrap();
+        let creds = PeerCreds::of(&server_end).unwrap();
+        // SAFETY: getuid and getgid are always successful
+        let (uid, gid) = unsafe { (libc::getuid(), libc::getgid()) };
+        assert_eq!(creds.uid, uid);
+        assert_eq!(creds.gid, gid);
+        #[cfg(target_os = "linux")]
+        assert_eq!(creds.pid, Some(std::process::id() as i32));
+    }
+
+    #[test]
+    fn peer_is_authenticated() {
+        let dir = std::env::temp_dir().join(format!("assuan-test-creds-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("S.test");
+
+        let socket = UnixSocketServer::bind(&path).unwrap();
+        let shutdown = socket.shutdown_handle();
+        let client_path = path.clone();
+        let client = std::thread::spawn(move || {
+            let mut conn = UnixStream::connect(&client_path).unwrap();
+            conn.write_all(b"GETKEY\nBYE\n").unwrap();
+            let mut output = String::new();
+            conn.read_to_string(&mut output).unwrap();
+            shutdown.shutdown();
+            output
+        });
+
+        // Only root may get the key
+        socket
+            .serve_with_creds(|peer| {
+                AssuanServer::new(peer).add_command_with_precondition(
+                    "GETKEY",
+                    |peer: &PeerCreds| {
+                        if peer.uid == 0 {
+                            Ok(())
+                        } else {
+                            Err(WithErrorCode {
+                                code: ErrorCode::ASS_PARAMETER,
+                                error: "access denied",
+                            })
+                        }
+                    },
+                    |_: &mut PeerCreds, _args: Option<&str>| Response::data("key"),
+                )
+            })
+            .unwrap();
+
+        let expected = if PeerCreds::of(&UnixStream::pair().unwrap().0).unwrap().uid == 0 {
+            "OK how can I serve you?\nD key\nOK success\nOK success\n".to_owned()
+        } else {
+            format!(
+                "OK how can I serve you?\nERR {} access denied\nOK success\n",
+                ErrorCode::ASS_PARAMETER.0
+            )
+        };
+        assert_eq!(client.join().unwrap(), expected);
+
+        drop(socket);
+        std::fs::remove_dir(&dir).unwrap();
+    }
+
+    #[test]
+    fn serves_clients_until_shut_down() {
+        let dir = std::env::temp_dir().join(format!("assuan-test-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("S.test");
+
+        let socket = UnixSocketServer::bind(&path).unwrap();
+        let mode = std::fs::metadata(&path).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o600);
+        assert!(UnixSocketServer::bind(&path).is_err());
+
+        let shutdown = socket.shutdown_handle();
+        let client_path = path.clone();
+        let client = std::thread::spawn(move || {
+            let mut outputs = vec![];
+            for _ in 0..2 {
+                let mut conn = UnixStream::connect(&client_path).unwrap();
+                conn.write_all(b"COUNT\nCOUNT\nBYE\n").unwrap();
+                let mut output = String::new();
+                conn.read_to_string(&mut output).unwrap();
+                outputs.push(output);
+            }
+            shutdown.shutdown();
+            outputs
+        });
+
+        // Every client gets a fresh counter
+        socket
+            .serve(|| {
+                AssuanServer::new(0).add_command("COUNT", |count: &mut u32, _args: Option<&str>| {
+                    *count += 1;
+                    Response::ok_with_debug_info(&count.to_string())
+                })
+            })
+            .unwrap();
+        let outputs = client.join().unwrap();
+        for output in outputs {
+            assert_eq!(output, "OK how can I serve you?\nOK 1\nOK 2\nOK success\n");
+        }
+
+        drop(socket);
+        assert!(!path.exists());
+        std::fs::remove_dir(&dir).unwrap();
+    }
+
+    #[test]
+    fn client_shuts_down_server() {
+        let dir = std::env::temp_dir().join(format!("assuan-test-stop-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("S.test");
+
+        let socket = UnixSocketServer::bind(&path).unwrap();
+        let client_path = path.clone();
+        let client = std::thread::spawn(move || {
+            let mut conn = UnixStream::connect(&client_path).unwrap();
+            conn.write_all(b"SHUTDOWN\nNOP\n").unwrap();
+            let mut output = String::new();
+            conn.read_to_string(&mut output).unwrap();
+            output
+        });
+
+        // Returns without a shutdown handle being used
+        socket
+            .serve(|| {
+                AssuanServer::new(()).add_command("SHUTDOWN", |_: &mut (), _args: Option<&str>| {
+                    Ok::<_, std::convert::Infallible>(Response::ok_shutting_down())
+                })
+            })
+            .unwrap();
+        assert_eq!(
+            client.join().unwrap(),
+            "OK how can I serve you?\nOK success\n"
+        );
+
+        drop(socket);
+        std::fs::remove_dir(&dir).unwrap();
+    }
+}
@@ -17,10 +17,18 @@
 
 use std::{fmt, io};
 
+#[cfg(feature = "crossterm")]
+pub use terminal::Crossterm;
+#[cfg(feature = "test-util")]
+pub use terminal::ScriptedTerminal;
 #[cfg(feature = "termion")]
 pub use terminal::Termion;
-pub use terminal::{Terminal, Tui};
+pub use terminal::{
+    AbortKind, Charset, DialogChoice, DialogLayout, InvalidChoice, PastePolicy, PinEcho,
+    QualityBar, StickyDialog, Terminal, Tui,
+};
 
+pub use assuan::clock;
 pub use zeroize;
 
 #[cfg(feature = "server")]
@@ -29,8 +37,7 @@ pub mod terminal;
 
 /// Builds Assuan server that implements a pinentry-tty tool
 ///
-/// Alias for wrapping [server::PinentryTty] into [pinentry::PinentryServer] and
-/// converting into [assuan::AssuanServer].
+/// Alias for [`server_with`] called with default [server::PinentryTty].
 ///
 /// ### Example
 /// Launch a pinentry-tty server that accepts commands from stdin and writes responses
@@ -43,7 +50,24 @@ pub fn server() -> assuan::AssuanServer<
     pinentry::PinentryServer<server::PinentryTty>,
     impl assuan::router::CmdList<pinentry::PinentryServer<server::PinentryTty>>,
 > {
-    pinentry::PinentryServer::new(server::PinentryTty::default()).build_assuan_server()
+    server_with(server::PinentryTty::default())
+}
+
+/// Builds Assuan server that implements a pinentry-tty tool configured by `pinentry`
+///
+/// Alias for wrapping `pinentry` into [pinentry::PinentryServer] and converting into
+/// [assuan::AssuanServer]. Server reports `tty` flavor and version of this crate via `GETINFO`.
+#[cfg(feature = "server")]
+pub fn server_with(
+    pinentry: server::PinentryTty,
+) -> assuan::AssuanServer<
+    pinentry::PinentryServer<server::PinentryTty>,
+    impl assuan::router::CmdList<pinentry::PinentryServer<server::PinentryTty>>,
+> {
+    pinentry::PinentryServer::new(pinentry)
+        .with_flavor("tty")
+        .build_assuan_server()
+        .set_info("version", env!("CARGO_PKG_VERSION"))
 }
 
 /// Asks user to provide a PIN
@@ -75,6 +99,7 @@ pub fn server() -> assuan::AssuanServer<
 /// ```
 /// and then user will be able to type a PIN. Characters of the PIN will not be visible.
 /// PIN can be submitted by typing Enter, or aborted by typing `Ctrl-C`, `Ctrl-D` or `Escape`.
+/// Besides Backspace, `Ctrl-U` erases the whole input and `Ctrl-W` erases the last word.
 ///
 /// ## Generic terminals
 /// This function is tied to [`termion` backend](Termion) and stdin/stdout. [`Tui::ask_pin`] can be used
@@ -104,6 +129,9 @@ pub fn ask_pin(prompt: impl fmt::Display, out: &mut impl PushPop<char>) -> io::R
 ///     or lowercase) to <u>c</u>ontinue, and type `A` to <u>a</u>bort.
 ///   * If given options `Continue` and `Cancel`, user can type `C` to <u>c</u>ontinue or
 ///     `A` to c<u>a</u>ncel.
+/// * Digits always select the option by its number, they're never used as option's character.
+///   If option text starts with digits, its first alphabetical character is used instead,
+///   e.g. option `2FA code` listed first is chosen by typing `1` or `F`, not `2`.
 ///
 /// ## Number of options
 /// At least one option must be provided. There cannot be more than 9 options.
@@ -153,7 +181,8 @@ pub fn dialog<'a, T>(
 /// The trait is used to store PIN typed by the user in [`ask_pin`], therefore the trait implementation
 /// must treat its content as highly sensitive.
 ///
-/// Out of box, we provide an implementation of the trait for the `Zeroizing<String>`:
+/// Out of box, we provide an implementation of the trait for the `Zeroizing<String>` (and
+/// `Zeroizing<Vec<u8>>` for binary PINs):
 /// 1. [`Zeroizing`](zeroize::Zeroizing) ensures that the PIN is erased from the memory when dropped
 /// 2. Implementation does not allow the string to grow: `push` operation is only possible
 ///    if the string has some capacity left \
@@ -182,7 +211,6 @@ pub trait PushPop<T> {
     fn pop(&mut self) -> Option<T>;
 }
 
-#[cfg(feature = "server")]
 impl PushPop<char> for assuan::response::SecretData {
     fn push(&mut self, x: char) -> Result<(), char> {
         (**self).push(x).map_err(|_| x)
@@ -224,3 +252,74 @@ impl PushPop<char> for zeroize::Zeroizing<String> {
         (**self).pop()
     }
 }
+
+/// Push/pop access to the byte buffer without reallocation
+///
+/// Same guarantees as for `Zeroizing<String>`: `push` never causes the internal buffer of
+/// `Vec` to grow. Useful for PINs that aren't text, e.g. raw bytes sent to a smartcard.
+impl PushPop<u8> for zeroize::Zeroizing<Vec<u8>> {
+    /// Appends a byte to the buffer if it has free capacity
+    ///
+    /// ```rust
+    /// use pinentry_tty::PushPop;
+    /// use zeroize::Zeroizing;
+    ///
+    /// let mut buf = Zeroizing::new(Vec::with_capacity(2));
+    /// buf.push(0x01).unwrap();
+    /// buf.push(0xff).unwrap();
+    ///
+    /// // Buffer has no internal capacity left. Pushing new byte
+    /// // will not succeed
+    /// assert_eq!(buf.push(0x02), Err(0x02));
+    /// assert_eq!(PushPop::pop(&mut buf), Some(0xff));
+    /// ```
+    fn push(&mut self, x: u8) -> Result<(), u8> {
+        if self.len() < self.capacity() {
+            (**self).push(x);
+            Ok(())
+        } else {
+            Err(x)
+        }
+    }
+
+    fn pop(&mut self) -> Option<u8> {
+        (**self).pop()
+    }
+}
+
+/// Push/pop access to the [`secrecy`] string without reallocation
+///
+/// Same guarantees as for `Zeroizing<String>`: `push` never causes the internal buffer to grow.
+///
+/// Note that `SecretString` is a boxed `str` which can't be appended to in place, so
+/// `SecretBox<String>` needs to be used for reading the PIN. Once it's read, it can be
+/// converted into a [`SecretData`](assuan::response::SecretData) response via
+/// [`Data::new_secret`](assuan::response::Data::new_secret).
+///
+/// ```rust
+/// use pinentry_tty::PushPop;
+/// use secrecy::SecretBox;
+///
+/// let mut buf = SecretBox::new(Box::new(String::with_capacity(2)));
+/// buf.push('a').unwrap();
+/// buf.push('b').unwrap();
+/// buf.push('c').unwrap_err();
+/// ```
+#[cfg(feature = "secrecy")]
+impl PushPop<char> for secrecy::SecretBox<String> {
+    fn push(&mut self, x: char) -> Result<(), char> {
+        use secrecy::ExposeSecretMut;
+        let s = self.expose_secret_mut();
+        if s.len() + x.len_utf8() <= s.capacity() {
+            s.push(x);
+            Ok(())
+        } else {
+            Err(x)
+        }
+    }
+
+    fn pop(&mut self) -> Option<char> {
+        use secrecy::ExposeSecretMut;
+        self.expose_secret_mut().pop()
+    }
+}
@@ -1,8 +1,12 @@
 fn main() -> std::io::Result<()> {
-    let mut server = pinentry_tty::server();
-
-    let stdin = std::io::stdin();
-    let stdout = std::io::stdout();
-
-    server.serve_client(stdin, stdout)
+    // Optional features are enabled by setting `PINENTRY_TTY_COLORS`, `PINENTRY_TTY_ALT_SCREEN`,
+    // `PINENTRY_TTY_CANONICAL_FALLBACK` and `PINENTRY_TTY_CONFIRM_PASTE` environment variables
+    // to `1`
+    let enabled = |var| std::env::var_os(var).is_some_and(|v| v == "1");
+    let pinentry = pinentry_tty::server::PinentryTty::default()
+        .with_colors(enabled("PINENTRY_TTY_COLORS"))
+        .with_alternate_screen(enabled("PINENTRY_TTY_ALT_SCREEN"))
+        .with_canonical_fallback(enabled("PINENTRY_TTY_CANONICAL_FALLBACK"))
+        .with_paste_confirmation(enabled("PINENTRY_TTY_CONFIRM_PASTE"));
+    pinentry_tty::server_with(pinentry).run_stdio()
 }
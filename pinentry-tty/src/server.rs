@@ -2,30 +2,164 @@
 //!
 //! Note: it's easier to use [`pinentry_tty::server()`](crate::server()) function.
 
-use std::fmt;
+use std::{fmt, sync::Arc};
 
 use assuan::response::SecretData;
 use either::Either;
 
-use crate::terminal::Tui;
+use crate::{clock::Clock, terminal::Tui};
+
+/// How long the `SETREPEATOK` message is shown unless user presses a key
+const REPEAT_OK_DURATION: std::time::Duration = std::time::Duration::from_secs(1);
 
 /// [PinentryCmds](pinentry::PinentryCmds) implementation based on [`ask_pin`](crate::ask_pin)
 /// and [`dialog`](crate::dialog) functions provided by this library
 ///
 /// Can be converted into assuan server using [`pinentry::PinentryServer`]
-#[derive(Default)]
 pub struct PinentryTty {
     tty: Option<std::path::PathBuf>,
+    colors: bool,
+    alternate_screen: bool,
+    canonical_fallback: bool,
+    paste_confirmation: bool,
+    charset: crate::Charset,
+    timeout: Option<std::time::Duration>,
+    quality_bar: Option<String>,
+    pin_quality: Option<PinQuality>,
+    clock: Arc<dyn Clock + Send + Sync>,
+}
+
+/// Estimates quality of the PIN, see [`PinentryTty::with_pin_quality`]
+type PinQuality = Arc<dyn Fn(&str) -> u8 + Send + Sync>;
+
+impl Default for PinentryTty {
+    fn default() -> Self {
+        Self {
+            tty: None,
+            colors: false,
+            alternate_screen: false,
+            canonical_fallback: false,
+            paste_confirmation: false,
+            charset: crate::Charset::Utf8,
+            timeout: None,
+            quality_bar: None,
+            pin_quality: None,
+            clock: Arc::new(crate::clock::SystemClock),
+        }
+    }
+}
+
+impl PinentryTty {
+    /// Enables or disables colored output
+    ///
+    /// When enabled, the error line is printed in red, the title and the prompt are printed
+    /// in bold. Colors are never used if the terminal [doesn't support styling](crate::Terminal::supports_styling).
+    ///
+    /// Disabled by default. The `pinentry-tty` binary enables colors when `PINENTRY_TTY_COLORS`
+    /// environment variable is set to `1`.
+    pub fn with_colors(mut self, enabled: bool) -> Self {
+        self.colors = enabled;
+        self
+    }
+
+    /// Enables or disables the alternate screen buffer
+    ///
+    /// When enabled, the prompts are shown in the [alternate screen](crate::terminal::AlternateScreen),
+    /// so they don't remain in the terminal scrollback once the pinentry is done.
+    ///
+    /// Disabled by default. The `pinentry-tty` binary enables it when `PINENTRY_TTY_ALT_SCREEN`
+    /// environment variable is set to `1`.
+    pub fn with_alternate_screen(mut self, enabled: bool) -> Self {
+        self.alternate_screen = enabled;
+        self
+    }
+
+    /// Allows falling back to canonical mode if the terminal can't be switched into raw mode
+    ///
+    /// See [`Terminal::canonical_fallback`](crate::Terminal::canonical_fallback) for the
+    /// limitations of this mode. Disabled by default, i.e. the prompt fails if raw mode is
+    /// unavailable. The `pinentry-tty` binary enables it when `PINENTRY_TTY_CANONICAL_FALLBACK`
+    /// environment variable is set to `1`.
+    pub fn with_canonical_fallback(mut self, enabled: bool) -> Self {
+        self.canonical_fallback = enabled;
+        self
+    }
+
+    /// Enables or disables confirmation of pasted PIN
+    ///
+    /// When enabled, user who pasted the PIN is asked to confirm it before it's sent to the
+    /// client. See [`PastePolicy::Confirm`](crate::PastePolicy::Confirm) for how paste is
+    /// detected. Disabled by default, i.e. pasted PIN is accepted silently. The `pinentry-tty`
+    /// binary enables it when `PINENTRY_TTY_CONFIRM_PASTE` environment variable is set to `1`.
+    pub fn with_paste_confirmation(mut self, enabled: bool) -> Self {
+        self.paste_confirmation = enabled;
+        self
+    }
+
+    /// Sets the function estimating quality of the PIN, from 0 (weakest) to 100 (strongest)
+    ///
+    /// When the client enables the quality bar, it's rendered next to the PIN input and
+    /// updated as user types. Without the estimate, the quality bar is never shown.
+    pub fn with_pin_quality(
+        mut self,
+        estimate: impl Fn(&str) -> u8 + Send + Sync + 'static,
+    ) -> Self {
+        self.pin_quality = Some(Arc::new(estimate));
+        self
+    }
+
+    /// Sets the clock used by time-dependent TUI features
+    ///
+    /// The clock is passed to every [terminal](crate::Termion::with_clock) opened by the pinentry.
+    /// By default, [system clock](crate::clock::SystemClock) is used.
+    pub fn with_clock(mut self, clock: Arc<dyn Clock + Send + Sync>) -> Self {
+        self.clock = clock;
+        self
+    }
 }
 
 impl pinentry::PinentryCmds for PinentryTty {
     type Error = Error;
 
+    /// Switches to the terminal at `path`
+    ///
+    /// Terminal is opened anew for every prompt and isn't cached, so the next prompt is
+    /// shown on the new terminal, even if the client switches terminals mid-session.
     fn set_tty(&mut self, path: std::path::PathBuf) -> Result<(), Self::Error> {
         self.tty = Some(path);
         Ok(())
     }
 
+    /// Determines [encoding](crate::Charset::from_lc_ctype) of the terminal input
+    fn set_lc_ctype(&mut self, lc_ctype: &str) -> Result<(), Self::Error> {
+        self.charset = crate::Charset::from_lc_ctype(lc_ctype);
+        Ok(())
+    }
+
+    /// Prompts fail with [`TIMEOUT`](assuan::ErrorCode::TIMEOUT) error once `timeout` elapses
+    ///
+    /// Timeout is measured from the moment the prompt is shown.
+    fn set_timeout(&mut self, timeout: Option<std::time::Duration>) -> Result<(), Self::Error> {
+        self.timeout = timeout;
+        Ok(())
+    }
+
+    /// Quality bar is shown only if [estimate](PinentryTty::with_pin_quality) is set
+    fn set_quality_bar(
+        &mut self,
+        label: Option<&str>,
+        _tooltip: Option<&str>,
+    ) -> Result<(), Self::Error> {
+        self.quality_bar = label.map(String::from);
+        Ok(())
+    }
+
+    fn pin_quality(&self, pin: &str) -> Option<u8> {
+        self.pin_quality
+            .as_ref()
+            .map(|estimate| estimate(pin).min(100))
+    }
+
     fn get_pin(
         &mut self,
         error: Option<&str>,
@@ -34,19 +168,12 @@ impl pinentry::PinentryCmds for PinentryTty {
         prompt: &str,
     ) -> Result<Option<SecretData>, Self::Error> {
         let mut tty = self.open_tty()?;
+        self.get_pin_on(&mut tty, error, window_title, desc, prompt)
+    }
 
-        let mut pin = SecretData::default();
-        let pin_submitted = tty.ask_pin(
-            &messages::PinPrompt {
-                error,
-                title: window_title,
-                desc,
-                prompt,
-            },
-            &mut pin,
-        )?;
-
-        Ok(Some(pin).filter(|_| pin_submitted))
+    fn show_repeat_ok(&mut self, message: &str) -> Result<(), Self::Error> {
+        let mut tty = self.open_tty()?;
+        self.show_repeat_ok_on(&mut tty, message)
     }
 
     fn confirm(
@@ -57,7 +184,75 @@ impl pinentry::PinentryCmds for PinentryTty {
         buttons: pinentry::Buttons,
     ) -> Result<pinentry::ConfirmChoice, Self::Error> {
         let mut tty = self.open_tty()?;
+        self.confirm_on(&mut tty, error, window_title, desc, buttons)
+    }
+}
+
+impl PinentryTty {
+    /// Implements [`get_pin`](pinentry::PinentryCmds::get_pin) on given terminal
+    fn get_pin_on(
+        &self,
+        tty: &mut impl crate::Terminal,
+        error: Option<&str>,
+        window_title: &str,
+        desc: Option<&str>,
+        prompt: &str,
+    ) -> Result<Option<SecretData>, Error> {
+        self.set_deadline(tty);
+        let mut tty = self.screen(tty)?;
+        let mut pin = SecretData::default();
+        let paste_policy = if self.paste_confirmation {
+            crate::PastePolicy::Confirm
+        } else {
+            crate::PastePolicy::Accept
+        };
+        let prompt = messages::PinPrompt {
+            error,
+            title: window_title,
+            desc,
+            prompt,
+            styled: self.styled(&tty),
+        };
+        let pin_submitted = match (&self.quality_bar, &self.pin_quality) {
+            (Some(label), Some(estimate)) => tty.ask_pin_with_quality(
+                &prompt,
+                &mut pin,
+                paste_policy,
+                crate::PinEcho::Hidden,
+                crate::terminal::QualityBar {
+                    label,
+                    quality: &**estimate,
+                },
+            )?,
+            _ => tty.ask_pin_with_paste_policy(&prompt, &mut pin, paste_policy)?,
+        };
 
+        Ok(Some(pin).filter(|_| pin_submitted))
+    }
+
+    /// Implements [`show_repeat_ok`](pinentry::PinentryCmds::show_repeat_ok) on given terminal
+    fn show_repeat_ok_on(
+        &self,
+        tty: &mut impl crate::Terminal,
+        message: &str,
+    ) -> Result<(), Error> {
+        let mut tty = self.screen(tty)?;
+        tty.notify(message, REPEAT_OK_DURATION)
+            .map_err(Reason::WriteTty)?;
+        Ok(())
+    }
+
+    /// Implements [`confirm`](pinentry::PinentryCmds::confirm) on given terminal
+    fn confirm_on(
+        &self,
+        tty: &mut impl crate::Terminal,
+        error: Option<&str>,
+        window_title: &str,
+        desc: Option<&str>,
+        buttons: pinentry::Buttons,
+    ) -> Result<pinentry::ConfirmChoice, Error> {
+        self.set_deadline(tty);
+        let mut tty = self.screen(tty)?;
         let mut options = Vec::with_capacity(3);
         options.push((buttons.ok, pinentry::ConfirmChoice::Ok));
 
@@ -68,35 +263,77 @@ impl pinentry::PinentryCmds for PinentryTty {
             options.push((cancel, pinentry::ConfirmChoice::Canceled));
         }
 
-        let choice = tty.dialog(
+        // Enter chooses the OK button, unless Cancel is preselected and present
+        let cancel = options
+            .iter()
+            .position(|(_, choice)| matches!(choice, pinentry::ConfirmChoice::Canceled));
+        let default = cancel.filter(|_| buttons.default_cancel).or(Some(0));
+        let choice = tty.dialog_with_default(
             &messages::Confirm {
                 error,
                 title: window_title,
                 desc,
+                styled: self.styled(&tty),
             },
             &options,
+            crate::DialogLayout::Lines,
+            crate::InvalidChoice::Ignore,
+            default,
         )?;
         Ok(*choice.unwrap_or(&pinentry::ConfirmChoice::Canceled))
     }
-}
 
-impl PinentryTty {
+    /// Switches `tty` into the alternate screen if it's enabled
+    fn screen<T: crate::Terminal>(
+        &self,
+        tty: T,
+    ) -> Result<Either<T, crate::terminal::AlternateScreen<T>>, Error> {
+        if self.alternate_screen {
+            Ok(Either::Right(
+                crate::terminal::AlternateScreen::enter(tty).map_err(Reason::WriteTty)?,
+            ))
+        } else {
+            Ok(Either::Left(tty))
+        }
+    }
+
+    /// Makes `tty` stop waiting for the user once the [timeout](Self::set_timeout) elapses
+    fn set_deadline(&self, tty: &mut impl crate::Terminal) {
+        let deadline = self
+            .timeout
+            .map(|timeout| crate::clock::Deadline::after(&tty.clock(), timeout));
+        tty.set_deadline(deadline);
+    }
+
+    /// Indicates whether the output to `tty` should be colored
+    fn styled(&self, tty: &impl crate::Terminal) -> bool {
+        self.colors && tty.supports_styling()
+    }
+
     fn open_tty(&self) -> Result<impl crate::Terminal, Error> {
         if let Some(path) = &self.tty {
             let tty_in = std::fs::OpenOptions::new()
                 .read(true)
                 .open(path)
-                .map_err(Reason::OpenTty)?;
+                .map_err(|err| Reason::OpenTty(path.clone(), err))?;
             let tty_out = std::fs::OpenOptions::new()
                 .write(true)
                 .open(path)
-                .map_err(Reason::OpenTty)?;
+                .map_err(|err| Reason::OpenTty(path.clone(), err))?;
             Ok(Either::Left(
-                crate::Termion::new(tty_in, tty_out).map_err(|_| Reason::OutputNotTty)?,
+                crate::Termion::new(tty_in, tty_out)
+                    .map_err(|_| Reason::OutputNotTty)?
+                    .with_clock(self.clock.clone())
+                    .with_canonical_fallback(self.canonical_fallback)
+                    .with_charset(self.charset),
             ))
         } else {
             Ok(Either::Right(
-                crate::Termion::new_stdio().map_err(|_| Reason::OutputNotTty)?,
+                crate::Termion::new_stdio()
+                    .map_err(|_| Reason::OutputNotTty)?
+                    .with_clock(self.clock.clone())
+                    .with_canonical_fallback(self.canonical_fallback)
+                    .with_charset(self.charset),
             ))
         }
     }
@@ -108,13 +345,14 @@ pub struct Error(Reason);
 
 #[derive(Debug)]
 enum Reason {
-    OpenTty(std::io::Error),
+    OpenTty(std::path::PathBuf, std::io::Error),
     WriteTty(std::io::Error),
     ReadTty(std::io::Error),
     RawMode(std::io::Error),
     Dialog(crate::terminal::DialogError),
     OutputNotTty,
     PinTooLong,
+    TimedOut,
     Internal(InternalError),
 }
 
@@ -126,13 +364,14 @@ enum InternalError {
 impl fmt::Display for Error {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
-            Self(Reason::OpenTty(err)) => write!(f, "open tty: {err}"),
+            Self(Reason::OpenTty(path, err)) => write!(f, "open tty {}: {err}", path.display()),
             Self(Reason::WriteTty(err)) => write!(f, "write to tty: {err}"),
             Self(Reason::ReadTty(err)) => write!(f, "read from tty: {err}"),
             Self(Reason::RawMode(err)) => write!(f, "enable raw mode: {err}"),
             Self(Reason::Dialog(err)) => write!(f, "dialog error: {err}"),
             Self(Reason::OutputNotTty) => write!(f, "output is not a tty"),
             Self(Reason::PinTooLong) => write!(f, "pin is too long"),
+            Self(Reason::TimedOut) => write!(f, "timed out"),
             Self(Reason::Internal(err)) => write!(f, "internal error: {err}"),
         }
     }
@@ -149,13 +388,14 @@ impl fmt::Display for InternalError {
 impl assuan::HasErrorCode for Error {
     fn code(&self) -> assuan::ErrorCode {
         match self {
-            Error(Reason::OpenTty(_)) => assuan::ErrorCode::ASS_GENERAL,
+            Error(Reason::OpenTty(..)) => assuan::ErrorCode::ASS_GENERAL,
             Error(Reason::WriteTty(_)) => assuan::ErrorCode::ASS_GENERAL,
             Error(Reason::ReadTty(_)) => assuan::ErrorCode::ASS_GENERAL,
             Error(Reason::RawMode(_)) => assuan::ErrorCode::ASS_GENERAL,
-            Error(Reason::Dialog(_)) => assuan::ErrorCode::ASS_GENERAL,
+            Error(Reason::Dialog(err)) => assuan::HasErrorCode::code(err),
             Error(Reason::OutputNotTty) => assuan::ErrorCode::ASS_GENERAL,
             Error(Reason::PinTooLong) => assuan::ErrorCode::TOO_LARGE,
+            Error(Reason::TimedOut) => assuan::ErrorCode::TIMEOUT,
             Error(Reason::Internal(_)) => assuan::ErrorCode::INTERNAL,
         }
     }
@@ -186,6 +426,7 @@ impl From<crate::terminal::AskPinError> for Error {
             crate::terminal::AskPinError::Write(err) => Error(Reason::WriteTty(err)),
             crate::terminal::AskPinError::RawMode(err) => Error(Reason::RawMode(err)),
             crate::terminal::AskPinError::PinTooLong => Error(Reason::PinTooLong),
+            crate::terminal::AskPinError::TimedOut => Error(Reason::TimedOut),
         }
     }
 }
@@ -204,25 +445,31 @@ impl From<crate::terminal::DialogError> for Error {
 mod messages {
     use std::fmt;
 
+    use crate::terminal::ctrl_seq::{Bold, DefaultColor, NoBold, Red};
+
     pub struct PinPrompt<'a> {
         pub error: Option<&'a str>,
         pub title: &'a str,
         pub desc: Option<&'a str>,
         pub prompt: &'a str,
+        pub styled: bool,
     }
 
+    /// Title that pinentry sets when client didn't set any
+    const DEFAULT_TITLE: &str = "Enter PIN";
+
     impl<'a> fmt::Display for PinPrompt<'a> {
         fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-            if let Some(error) = self.error {
-                writeln!(f, "Error: {error}")?;
-            }
-            writeln!(f, "{}", self.title)?;
-            if let Some(desc) = self.desc {
-                writeln!(f, "{desc}")?;
+            // Default title tells nothing the prompt doesn't, so it's omitted unless there's
+            // a description following it
+            if self.desc.is_some() || self.title != DEFAULT_TITLE {
+                write_header(f, self.styled, self.error, self.title, self.desc)?;
+                writeln!(f)?;
+            } else {
+                write_error(f, self.styled, self.error)?;
             }
-            writeln!(f)?;
 
-            write!(f, "{}", self.prompt)
+            write_styled(f, self.styled, Bold, self.prompt, NoBold)
         }
     }
 
@@ -230,18 +477,810 @@ mod messages {
         pub error: Option<&'a str>,
         pub title: &'a str,
         pub desc: Option<&'a str>,
+        pub styled: bool,
     }
 
     impl<'a> fmt::Display for Confirm<'a> {
         fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-            if let Some(error) = self.error {
-                writeln!(f, "Error: {error}")?;
-            }
-            writeln!(f, "{}", self.title)?;
-            if let Some(desc) = self.desc {
-                writeln!(f, "{desc}")?;
+            write_header(f, self.styled, self.error, self.title, self.desc)
+        }
+    }
+
+    fn write_header(
+        f: &mut fmt::Formatter<'_>,
+        styled: bool,
+        error: Option<&str>,
+        title: &str,
+        desc: Option<&str>,
+    ) -> fmt::Result {
+        write_error(f, styled, error)?;
+        write_styled(f, styled, Bold, title, NoBold)?;
+        writeln!(f)?;
+        if let Some(desc) = desc {
+            writeln!(f, "{desc}")?;
+        }
+        Ok(())
+    }
+
+    fn write_error(f: &mut fmt::Formatter<'_>, styled: bool, error: Option<&str>) -> fmt::Result {
+        if let Some(error) = error {
+            write_styled(f, styled, Red, format_args!("Error: {error}"), DefaultColor)?;
+            writeln!(f)?;
+        }
+        Ok(())
+    }
+
+    /// Writes `text` surrounded by `start` and `end` control sequences if `styled` is `true`,
+    /// otherwise writes `text` only
+    fn write_styled(
+        f: &mut fmt::Formatter<'_>,
+        styled: bool,
+        start: impl fmt::Display,
+        text: impl fmt::Display,
+        end: impl fmt::Display,
+    ) -> fmt::Result {
+        if styled {
+            write!(f, "{start}{text}{end}")
+        } else {
+            write!(f, "{text}")
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io;
+
+    use super::PinentryTty;
+    use crate::terminal::{Key, Terminal};
+
+    /// Terminal that types given keys and records the output
+    struct MockTerminal {
+        keys: Vec<Key>,
+        output: Vec<u8>,
+        supports_styling: bool,
+        refuses_raw_mode: bool,
+        canonical_fallback: bool,
+        typed_bytes: Vec<u8>,
+        size: Option<(u16, u16)>,
+        clock: crate::clock::MockClock,
+        deadline: Option<crate::clock::Deadline>,
+    }
+
+    /// Without raw mode, typed characters are read as bytes
+    impl io::Read for MockTerminal {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            if self.typed_bytes.is_empty() {
+                if let Some(Key::Char(x)) = self.keys.first() {
+                    self.typed_bytes = x.to_string().into_bytes();
+                    self.keys.remove(0);
+                }
             }
+            let len = self.typed_bytes.len().min(buf.len());
+            buf[..len].copy_from_slice(&self.typed_bytes[..len]);
+            self.typed_bytes.drain(..len);
+            Ok(len)
+        }
+    }
+
+    impl io::Write for MockTerminal {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.output.write(buf)
+        }
+        fn flush(&mut self) -> io::Result<()> {
             Ok(())
         }
     }
+
+    impl Terminal for MockTerminal {
+        fn keys(
+            &mut self,
+        ) -> io::Result<(
+            impl Iterator<Item = io::Result<Key>> + '_,
+            impl io::Write + '_,
+        )> {
+            if self.refuses_raw_mode {
+                return Err(io::Error::other("raw mode is unavailable"));
+            }
+            // Keys that weren't consumed remain for the next call. Once they run out, user
+            // is idle until the deadline, if any
+            let (keys, clock, deadline) = (&mut self.keys, &self.clock, self.deadline);
+            let keys = std::iter::from_fn(move || {
+                if !keys.is_empty() {
+                    return Some(Ok(keys.remove(0)));
+                }
+                let deadline = deadline?;
+                clock.advance(deadline.remaining(clock));
+                Some(Err(io::ErrorKind::TimedOut.into()))
+            });
+            Ok((keys, &mut self.output))
+        }
+
+        fn supports_styling(&self) -> bool {
+            self.supports_styling
+        }
+
+        fn canonical_fallback(&self) -> bool {
+            self.canonical_fallback
+        }
+
+        fn size(&self) -> Option<(u16, u16)> {
+            self.size
+        }
+
+        fn clock(&self) -> &dyn crate::clock::Clock {
+            &self.clock
+        }
+
+        fn set_deadline(&mut self, deadline: Option<crate::clock::Deadline>) -> bool {
+            self.deadline = deadline;
+            true
+        }
+
+        fn wait_key(&mut self, timeout: std::time::Duration) -> io::Result<bool> {
+            if self.keys.is_empty() {
+                self.clock.advance(timeout);
+                Ok(false)
+            } else {
+                self.keys.remove(0);
+                Ok(true)
+            }
+        }
+    }
+
+    fn mock_tty(keys: Vec<Key>, supports_styling: bool) -> MockTerminal {
+        MockTerminal {
+            keys,
+            output: vec![],
+            supports_styling,
+            refuses_raw_mode: false,
+            canonical_fallback: false,
+            typed_bytes: vec![],
+            size: None,
+            clock: crate::clock::MockClock::new(),
+            deadline: None,
+        }
+    }
+
+    fn get_pin(pinentry: &PinentryTty, supports_styling: bool) -> String {
+        let mut tty = mock_tty(vec![Key::Char('1'), Key::Char('\n')], supports_styling);
+        let pin = pinentry
+            .get_pin_on(&mut tty, Some("Bad PIN"), "Title", None, "PIN:")
+            .unwrap()
+            .unwrap();
+        assert!(pin.chars().eq("1".chars()));
+        String::from_utf8(tty.output).unwrap()
+    }
+
+    fn confirm(pinentry: &PinentryTty, supports_styling: bool) -> String {
+        let mut tty = mock_tty(vec![Key::Char('1')], supports_styling);
+        let buttons = pinentry::Buttons {
+            ok: "Ok",
+            not_ok: None,
+            cancel: None,
+            default_cancel: false,
+        };
+        let choice = pinentry
+            .confirm_on(&mut tty, Some("Bad PIN"), "Title", None, buttons)
+            .unwrap();
+        assert!(matches!(choice, pinentry::ConfirmChoice::Ok));
+        String::from_utf8(tty.output).unwrap()
+    }
+
+    #[test]
+    fn colors_only_emitted_when_enabled_and_supported() {
+        let colored = PinentryTty::default().with_colors(true);
+        assert_eq!(
+            get_pin(&colored, true),
+            "\x1B[31mError: Bad PIN\x1B[39m\n\x1B[1mTitle\x1B[22m\n\n\x1B[1mPIN:\x1B[22m\n"
+        );
+        assert!(confirm(&colored, true)
+            .starts_with("\x1B[31mError: Bad PIN\x1B[39m\n\x1B[1mTitle\x1B[22m\n"));
+
+        let no_styling = "Error: Bad PIN\nTitle\n\nPIN:\n";
+        assert_eq!(get_pin(&colored, false), no_styling);
+        assert_eq!(
+            confirm(&colored, false),
+            "Error: Bad PIN\nTitle\n\n  1 Ok\nType [1o] (Enter for 1) : 1\n"
+        );
+
+        let plain = PinentryTty::default();
+        assert_eq!(get_pin(&plain, true), no_styling);
+        assert!(confirm(&plain, true).starts_with("Error: Bad PIN\nTitle\n"));
+    }
+
+    #[test]
+    fn notification_is_cleared() {
+        use std::time::Duration;
+
+        use crate::{clock::Clock, Tui};
+
+        let mut tty = mock_tty(vec![], true);
+        let started = tty.clock.now();
+        tty.notify("PIN accepted", Duration::from_secs(2)).unwrap();
+        assert_eq!(tty.clock.now() - started, Duration::from_secs(2));
+        assert_eq!(
+            String::from_utf8(tty.output).unwrap(),
+            "PIN accepted\r\x1B[2K"
+        );
+
+        // Key press dismisses the notification early
+        let mut tty = mock_tty(vec![Key::Char('x')], true);
+        let started = tty.clock.now();
+        tty.notify("Operation\ncancelled", Duration::from_secs(2))
+            .unwrap();
+        assert_eq!(tty.clock.now(), started);
+        assert!(tty.keys.is_empty());
+        assert_eq!(
+            String::from_utf8(tty.output).unwrap(),
+            "Operation\ncancelled\r\x1B[2K\x1B[1A\x1B[2K"
+        );
+
+        // Message can't be cleared without control sequences
+        let mut tty = mock_tty(vec![], false);
+        tty.notify("PIN accepted", Duration::from_secs(2)).unwrap();
+        assert_eq!(String::from_utf8(tty.output).unwrap(), "PIN accepted\n");
+    }
+
+    #[test]
+    fn minimal_prompt() {
+        let pinentry = PinentryTty::default();
+        let prompt = |error, title, desc| {
+            let mut tty = mock_tty(vec![Key::Char('\n')], false);
+            pinentry
+                .get_pin_on(&mut tty, error, title, desc, "PIN: ")
+                .unwrap()
+                .unwrap();
+            String::from_utf8(tty.output).unwrap()
+        };
+
+        assert_eq!(prompt(None, "Enter PIN", None), "PIN: \n");
+        assert_eq!(
+            prompt(Some("Bad PIN"), "Enter PIN", None),
+            "Error: Bad PIN\nPIN: \n"
+        );
+
+        // Title is shown if it's set by client or introduces the description
+        assert_eq!(prompt(None, "Unlock", None), "Unlock\n\nPIN: \n");
+        assert_eq!(
+            prompt(None, "Enter PIN", Some("Key 0xAB")),
+            "Enter PIN\nKey 0xAB\n\nPIN: \n"
+        );
+    }
+
+    #[test]
+    fn pasted_pin_requires_confirmation() {
+        const PASTE_ON: &str = "\x1B[?2004h";
+        const PASTE_OFF: &str = "\x1B[?2004l";
+        let get_pin = |pinentry: &PinentryTty, confirmation| {
+            let mut keys = vec![Key::Char('1'), Key::PasteStart, Key::Char('2')];
+            keys.extend([Key::PasteEnd, Key::Char('\n'), Key::Char(confirmation)]);
+            let mut tty = mock_tty(keys, true);
+            let pin = pinentry
+                .get_pin_on(&mut tty, None, "Title", None, "PIN:")
+                .unwrap()
+                .map(|pin| pin.chars().collect::<String>());
+            (pin, String::from_utf8(tty.output).unwrap())
+        };
+
+        // Paste is accepted silently by default
+        let (pin, output) = get_pin(&PinentryTty::default(), 'n');
+        assert_eq!(pin.as_deref(), Some("12"));
+        assert_eq!(output, "Title\n\nPIN:\n");
+
+        let pinentry = PinentryTty::default().with_paste_confirmation(true);
+        let (pin, output) = get_pin(&pinentry, 'y');
+        assert_eq!(pin.as_deref(), Some("12"));
+        assert_eq!(
+            output,
+            format!(
+                "Title\n\nPIN:{PASTE_ON}{PASTE_OFF}\n\
+                Pasted input detected. Accept it? [y/N] \n"
+            )
+        );
+
+        let (pin, output) = get_pin(&pinentry, 'n');
+        assert_eq!(pin, None);
+        assert!(
+            output.ends_with("Accept it? [y/N] Aborted.\n"),
+            "{output:?}"
+        );
+
+        // Typed PIN doesn't need to be confirmed
+        let mut tty = mock_tty(vec![Key::Char('1'), Key::Char('\n')], true);
+        let pin = pinentry
+            .get_pin_on(&mut tty, None, "Title", None, "PIN:")
+            .unwrap()
+            .unwrap();
+        assert!(pin.chars().eq("1".chars()));
+        let output = String::from_utf8(tty.output).unwrap();
+        assert_eq!(output, format!("Title\n\nPIN:{PASTE_ON}{PASTE_OFF}\n"));
+    }
+
+    #[test]
+    fn dialog_aborts_after_invalid_choices() {
+        use crate::{DialogLayout, InvalidChoice, Tui};
+
+        let options = [("Ok", 1), ("Cancel", 2)];
+        let warn = |max_attempts| InvalidChoice::Warn {
+            beep: true,
+            max_attempts,
+        };
+        let keys = || "x\n3o".chars().map(Key::Char).collect();
+
+        let mut tty = mock_tty(keys(), false);
+        let choice = tty
+            .dialog_with_invalid_choice("Sure?", &options, DialogLayout::Lines, warn(None))
+            .unwrap();
+        assert_eq!(choice, Some(&1));
+        assert_eq!(
+            String::from_utf8(tty.output).unwrap(),
+            "Sure?\n  1 Ok\n  2 Cancel\nType [12oc] : \
+            \x07\r\nInvalid choice. Type [12oc] : \
+            \x07\r\nInvalid choice. Type [12oc] : o\n"
+        );
+
+        let mut tty = mock_tty(keys(), false);
+        let choice = tty
+            .dialog_with_invalid_choice("Sure?", &options, DialogLayout::Lines, warn(Some(2)))
+            .unwrap();
+        assert_eq!(choice, None);
+        let output = String::from_utf8(tty.output).unwrap();
+        assert!(
+            output.ends_with(
+                "Invalid choice. Type [12oc] : \x07\r\nToo many invalid choices. Aborted.\n"
+            ),
+            "{output:?}"
+        );
+
+        // Invalid choices are ignored by default
+        let mut tty = mock_tty(keys(), false);
+        let choice = tty.dialog("Sure?", &options).unwrap();
+        assert_eq!(choice, Some(&1));
+        let output = String::from_utf8(tty.output).unwrap();
+        assert!(output.ends_with("Type [12oc] : o\n"), "{output:?}");
+    }
+
+    #[test]
+    fn sticky_dialog_defaults_to_last_choice() {
+        use crate::StickyDialog;
+
+        let options = [("Yes", 1), ("No", 2), ("Skip", 3)];
+        let mut dialog = StickyDialog::new();
+
+        // Enter doesn't choose anything until the first choice is made
+        let mut tty = mock_tty("\n2".chars().map(Key::Char).collect(), false);
+        assert_eq!(
+            dialog.dialog(&mut tty, "Sure?", &options).unwrap(),
+            Some(&2)
+        );
+        assert_eq!(dialog.last_choice(), Some(1));
+        let output = String::from_utf8(tty.output).unwrap();
+        assert!(output.ends_with("Type [123yns] : 2\n"), "{output:?}");
+
+        let mut tty = mock_tty(vec![Key::Char('\r')], false);
+        assert_eq!(
+            dialog.dialog(&mut tty, "Sure?", &options).unwrap(),
+            Some(&2)
+        );
+        let output = String::from_utf8(tty.output).unwrap();
+        assert!(
+            output.ends_with("Type [123yns] (Enter for 2) : 2\n"),
+            "{output:?}"
+        );
+
+        // Aborting keeps the last choice
+        let mut tty = mock_tty(vec![Key::Esc], false);
+        assert_eq!(dialog.dialog(&mut tty, "Sure?", &options).unwrap(), None);
+        assert_eq!(dialog.last_choice(), Some(1));
+
+        let mut tty = mock_tty("s".chars().map(Key::Char).collect(), false);
+        assert_eq!(
+            dialog.dialog(&mut tty, "Sure?", &options).unwrap(),
+            Some(&3)
+        );
+        let mut tty = mock_tty(vec![Key::Char('\n')], false);
+        assert_eq!(
+            dialog.dialog(&mut tty, "Sure?", &options).unwrap(),
+            Some(&3)
+        );
+
+        // In canonical mode, the line after Enter is left for the next prompt
+        let mut tty = mock_tty("\ny\n".chars().map(Key::Char).collect(), false);
+        tty.refuses_raw_mode = true;
+        tty.canonical_fallback = true;
+        assert_eq!(
+            dialog.dialog(&mut tty, "Sure?", &options).unwrap(),
+            Some(&3)
+        );
+        assert_eq!(
+            dialog.dialog(&mut tty, "Sure?", &options).unwrap(),
+            Some(&1)
+        );
+        assert!(tty.keys.is_empty());
+    }
+
+    #[test]
+    fn quality_bar_is_shown_when_enabled() {
+        use pinentry::PinentryCmds;
+
+        let mut pinentry =
+            PinentryTty::default().with_pin_quality(|pin| (pin.len() * 30).min(255) as u8);
+        assert_eq!(pinentry.pin_quality("1234"), Some(100));
+
+        let get_pin = |pinentry: &PinentryTty| {
+            let mut tty = mock_tty(vec![Key::Char('1'), Key::Char('\n')], true);
+            pinentry
+                .get_pin_on(&mut tty, None, "Title", None, "PIN:")
+                .unwrap()
+                .unwrap();
+            String::from_utf8(tty.output).unwrap()
+        };
+        assert!(!get_pin(&pinentry).contains("Strength"));
+
+        pinentry.set_quality_bar(Some("Strength:"), None).unwrap();
+        let output = get_pin(&pinentry);
+        assert!(output.contains(" Strength: [###-------]"), "{output:?}");
+    }
+
+    #[test]
+    fn prompts_time_out() {
+        use std::time::Duration;
+
+        use assuan::HasErrorCode;
+        use pinentry::PinentryCmds;
+
+        use crate::clock::Clock;
+
+        let mut pinentry = PinentryTty::default();
+        pinentry.set_timeout(Some(Duration::from_secs(30))).unwrap();
+
+        let mut tty = mock_tty(vec![Key::Char('1')], false);
+        let started = tty.clock.now();
+        let Err(err) = pinentry.get_pin_on(&mut tty, None, "Title", None, "PIN:") else {
+            panic!("prompt must time out");
+        };
+        assert_eq!(err.code(), assuan::ErrorCode::TIMEOUT);
+        assert_eq!(tty.clock.now() - started, Duration::from_secs(30));
+        let output = String::from_utf8(tty.output).unwrap();
+        assert!(output.ends_with("PIN:Timed out.\n"), "{output:?}");
+
+        let mut tty = mock_tty(vec![Key::Char('x')], false);
+        let buttons = pinentry::Buttons {
+            ok: "Ok",
+            not_ok: None,
+            cancel: None,
+            default_cancel: false,
+        };
+        let err = pinentry
+            .confirm_on(&mut tty, None, "Title", None, buttons)
+            .unwrap_err();
+        assert_eq!(err.code(), assuan::ErrorCode::TIMEOUT);
+        let output = String::from_utf8(tty.output).unwrap();
+        assert!(output.ends_with(" : Timed out.\n"), "{output:?}");
+
+        // User who responds in time isn't affected
+        let mut tty = mock_tty(vec![Key::Char('1'), Key::Char('\n')], false);
+        let pin = pinentry
+            .get_pin_on(&mut tty, None, "Title", None, "PIN:")
+            .unwrap()
+            .unwrap();
+        assert!(pin.chars().eq("1".chars()));
+
+        pinentry.set_timeout(None).unwrap();
+        let mut tty = mock_tty(vec![Key::Char('1')], false);
+        let result = pinentry.get_pin_on(&mut tty, None, "Title", None, "PIN:");
+        assert!(result.is_err(), "keys run out before the PIN is submitted");
+        assert!(tty.deadline.is_none());
+    }
+
+    #[test]
+    fn confirm_defaults_to_ok() {
+        let pinentry = PinentryTty::default();
+        let buttons = || pinentry::Buttons {
+            ok: "Yes",
+            not_ok: Some("No"),
+            cancel: Some("Cancel"),
+            default_cancel: false,
+        };
+
+        let mut tty = mock_tty(vec![Key::Char('\r')], true);
+        let choice = pinentry
+            .confirm_on(&mut tty, None, "Title", None, buttons())
+            .unwrap();
+        assert!(matches!(choice, pinentry::ConfirmChoice::Ok));
+        let output = String::from_utf8(tty.output).unwrap();
+        assert!(
+            output.ends_with("Type [\x1B[4m1\x1B[24m23ync] (Enter for 1) : 1\n"),
+            "{output:?}"
+        );
+
+        let mut tty = mock_tty(vec![Key::Esc, Key::Char('\r')], true);
+        let choice = pinentry
+            .confirm_on(&mut tty, None, "Title", None, buttons())
+            .unwrap();
+        assert!(matches!(choice, pinentry::ConfirmChoice::Canceled));
+    }
+
+    #[test]
+    fn confirm_defaults_to_cancel_when_asked() {
+        let pinentry = PinentryTty::default();
+        let buttons = pinentry::Buttons {
+            ok: "Yes",
+            not_ok: Some("No"),
+            cancel: Some("Cancel"),
+            default_cancel: true,
+        };
+
+        let mut tty = mock_tty(vec![Key::Char('\r')], true);
+        let choice = pinentry
+            .confirm_on(&mut tty, None, "Title", None, buttons)
+            .unwrap();
+        assert!(matches!(choice, pinentry::ConfirmChoice::Canceled));
+        let output = String::from_utf8(tty.output).unwrap();
+        assert!(output.contains("(Enter for 3)"), "{output:?}");
+
+        // Without a Cancel button, OK stays the default
+        let buttons = pinentry::Buttons {
+            ok: "OK",
+            not_ok: None,
+            cancel: None,
+            default_cancel: true,
+        };
+        let mut tty = mock_tty(vec![Key::Char('\r')], true);
+        let choice = pinentry
+            .confirm_on(&mut tty, None, "Title", None, buttons)
+            .unwrap();
+        assert!(matches!(choice, pinentry::ConfirmChoice::Ok));
+    }
+
+    #[test]
+    fn repeat_ok_is_shown_briefly() {
+        use crate::clock::Clock;
+
+        let pinentry = PinentryTty::default();
+        let mut tty = mock_tty(vec![], false);
+        let shown_at = tty.clock.now();
+        pinentry.show_repeat_ok_on(&mut tty, "PINs match").unwrap();
+        assert_eq!(String::from_utf8(tty.output).unwrap(), "PINs match\n");
+        assert_eq!(tty.clock.now() - shown_at, super::REPEAT_OK_DURATION);
+    }
+
+    #[test]
+    fn alternate_screen_brackets_the_prompt() {
+        const ENTER: &str = "\x1B[?1049h";
+        const LEAVE: &str = "\x1B[?1049l";
+        let pinentry = PinentryTty::default().with_alternate_screen(true);
+
+        let output = get_pin(&pinentry, false);
+        assert_eq!(
+            output,
+            format!("{ENTER}Error: Bad PIN\nTitle\n\nPIN:\n{LEAVE}")
+        );
+        let output = confirm(&pinentry, false);
+        assert!(output.starts_with(&format!("{ENTER}Error: Bad PIN\n")));
+        assert!(output.ends_with(&format!(" : 1\n{LEAVE}")));
+
+        // Main screen is restored even if the prompt fails
+        let mut tty = mock_tty(vec![Key::Char('1')], false);
+        let result = pinentry.get_pin_on(&mut tty, None, "Title", None, "PIN:");
+        assert!(result.is_err(), "keys run out before the PIN is submitted");
+        let output = String::from_utf8(tty.output).unwrap();
+        assert!(
+            output.starts_with(ENTER) && output.ends_with(LEAVE),
+            "{output:?}"
+        );
+
+        let output = get_pin(&PinentryTty::default(), false);
+        assert!(!output.contains(ENTER) && !output.contains(LEAVE));
+    }
+
+    #[test]
+    fn changing_tty_mid_session_routes_next_prompt_to_new_tty() {
+        let mut server = crate::server();
+        let input = "OPTION ttyname=/nonexistent/tty1\n\
+            GETPIN\n\
+            OPTION ttyname=/nonexistent/tty2\n\
+            GETPIN\n\
+            CONFIRM\n\
+            BYE\n";
+        let mut output = vec![];
+        server.serve_client(input.as_bytes(), &mut output).unwrap();
+        let output = String::from_utf8(output).unwrap();
+        let errors: Vec<_> = output
+            .lines()
+            .filter(|line| line.starts_with("ERR"))
+            .collect();
+        assert_eq!(errors.len(), 3, "{output}");
+        assert!(errors[0].starts_with("ERR 83886337 open tty /nonexistent/tty1:"));
+        assert!(errors[1].starts_with("ERR 83886337 open tty /nonexistent/tty2:"));
+        assert!(errors[2].starts_with("ERR 83886337 open tty /nonexistent/tty2:"));
+    }
+
+    #[test]
+    fn dialog_options_in_columns() {
+        use crate::{DialogLayout, Tui};
+
+        let options = [
+            ("Yes", 1),
+            ("No", 2),
+            ("Maybe", 3),
+            ("Skip", 4),
+            ("Retry", 5),
+            ("Abort", 6),
+        ];
+        let dialog = |size, layout| {
+            let mut tty = mock_tty(vec![Key::Char('r')], false);
+            tty.size = size;
+            let choice = tty
+                .dialog_with_layout("Proceed?", &options, layout)
+                .unwrap();
+            assert_eq!(choice, Some(&5));
+            String::from_utf8(tty.output).unwrap()
+        };
+
+        assert_eq!(
+            dialog(Some((30, 24)), DialogLayout::Columns),
+            "Proceed?\n\
+            \x20 1 Yes    2 No     3 Maybe\n\
+            \x20 4 Skip   5 Retry  6 Abort\n\
+            Type [123456ynmsra] : r\n"
+        );
+
+        let lines = "Proceed?\n  1 Yes\n  2 No\n  3 Maybe\n  4 Skip\n  5 Retry\n  6 Abort\n\
+            Type [123456ynmsra] : r\n";
+        assert_eq!(dialog(Some((30, 24)), DialogLayout::Lines), lines);
+        assert_eq!(dialog(None, DialogLayout::Columns), lines);
+        // Options don't fit into a single line
+        assert_eq!(dialog(Some((5, 24)), DialogLayout::Columns), lines);
+    }
+
+    #[test]
+    fn options_starting_with_digits() {
+        use crate::Tui;
+
+        let options = [
+            ("3 more tries", 1),
+            ("2FA code", 2),
+            ("\u{2167} chapter", 3),
+        ];
+        let dialog = |key| {
+            let mut tty = mock_tty(vec![Key::Char(key)], false);
+            let choice = tty.dialog("Proceed?", &options).unwrap().copied();
+            (choice, String::from_utf8(tty.output).unwrap())
+        };
+
+        let (choice, output) = dialog('2');
+        assert_eq!(choice, Some(2));
+        assert_eq!(
+            output,
+            "Proceed?\n  1 3 more tries\n  2 2FA code\n  3 \u{2167} chapter\n\
+            Type [123mfc] : 2\n"
+        );
+        // Digits map to positions even if option text starts with the same digit
+        assert_eq!(dialog('3').0, Some(3));
+        assert_eq!(dialog('m').0, Some(1));
+        assert_eq!(dialog('F').0, Some(2));
+        assert_eq!(dialog('c').0, Some(3));
+    }
+
+    #[test]
+    fn canonical_fallback_without_raw_mode() {
+        let pinentry = PinentryTty::default();
+        let typed = |text: &str| text.chars().map(Key::Char).collect();
+
+        let mut tty = mock_tty(typed("pä55\n"), false);
+        tty.refuses_raw_mode = true;
+        let Err(err) = pinentry.get_pin_on(&mut tty, None, "Title", None, "PIN:") else {
+            panic!("raw mode is required without canonical fallback");
+        };
+        assert!(err.to_string().contains("raw mode"), "{err}");
+
+        tty.canonical_fallback = true;
+        let pin = pinentry
+            .get_pin_on(&mut tty, None, "Title", None, "PIN:")
+            .unwrap()
+            .unwrap();
+        assert!(pin.chars().eq("pä55".chars()));
+        let output = String::from_utf8(tty.output).unwrap();
+        assert!(output.ends_with("PIN:(input is visible) \n"), "{output:?}");
+
+        // Invalid choice is ignored
+        let mut tty = mock_tty(typed("xno\n"), false);
+        tty.refuses_raw_mode = true;
+        tty.canonical_fallback = true;
+        let buttons = pinentry::Buttons {
+            ok: "Yes",
+            not_ok: Some("No"),
+            cancel: None,
+            default_cancel: false,
+        };
+        let choice = pinentry
+            .confirm_on(&mut tty, None, "Title", None, buttons)
+            .unwrap();
+        assert!(matches!(choice, pinentry::ConfirmChoice::NotOk));
+        assert!(tty.keys.is_empty());
+    }
+
+    #[test]
+    fn pin_line_editing() {
+        use crate::Tui;
+
+        // Line editing keys are written as `^U` and `^W`
+        let ask_pin = |typed: &str| {
+            let keys = typed
+                .replace("^U", "\u{15}")
+                .replace("^W", "\u{17}")
+                .chars()
+                .map(|x| match x {
+                    '\u{15}' => Key::Ctrl('u'),
+                    '\u{17}' => Key::Ctrl('w'),
+                    x => Key::Char(x),
+                })
+                .collect();
+            let mut tty = mock_tty(keys, false);
+            let mut pin = zeroize::Zeroizing::new(String::with_capacity(10));
+            assert!(tty.ask_pin("PIN:", &mut pin).unwrap());
+            assert!(tty.keys.is_empty());
+            pin
+        };
+
+        assert_eq!(ask_pin("12 34 ^W5\n").as_str(), "12 5");
+        assert_eq!(ask_pin("12^W5\n").as_str(), "5");
+        assert_eq!(ask_pin("1 2^U3\n").as_str(), "3");
+        assert_eq!(ask_pin("^U^W4\n").as_str(), "4");
+    }
+
+    #[test]
+    fn masked_pin_echoes_asterisks() {
+        use crate::{PastePolicy, PinEcho, Tui};
+
+        let keys = vec![
+            Key::Backspace,
+            Key::Char('a'),
+            Key::Char('b'),
+            Key::Backspace,
+            Key::Char('c'),
+            // Cursor movement isn't supported
+            Key::Left,
+            Key::Home,
+            Key::Char('\n'),
+        ];
+        let mut tty = mock_tty(keys, false);
+        let mut pin = zeroize::Zeroizing::new(String::with_capacity(10));
+        assert!(tty
+            .ask_pin_with_echo("PIN:", &mut pin, PastePolicy::Accept, PinEcho::Masked)
+            .unwrap());
+        assert_eq!(pin.as_str(), "ac");
+        assert_eq!(String::from_utf8(tty.output).unwrap(), "PIN:**\x08 \x08*\n");
+
+        let keys = vec![Key::Char('a'), Key::Char('b'), Key::Esc];
+        let mut tty = mock_tty(keys, false);
+        let mut pin = zeroize::Zeroizing::new(String::with_capacity(10));
+        assert!(!tty
+            .ask_pin_with_echo("PIN:", &mut pin, PastePolicy::Accept, PinEcho::Masked)
+            .unwrap());
+        assert_eq!(
+            String::from_utf8(tty.output).unwrap(),
+            "PIN:**\x08 \x08\x08 \x08Aborted.\n"
+        );
+    }
+
+    #[cfg(feature = "secrecy")]
+    #[test]
+    fn secrecy_pin_converts_to_response() {
+        use crate::Tui;
+        use assuan::response::{Data, Response};
+        use secrecy::{ExposeSecret, SecretBox};
+
+        let keys = "secret\n".chars().map(Key::Char).collect();
+        let mut tty = mock_tty(keys, false);
+        let mut pin = SecretBox::new(Box::new(String::with_capacity(10)));
+        assert!(tty.ask_pin("PIN:", &mut pin).unwrap());
+        assert_eq!(pin.expose_secret(), "secret");
+
+        let data = Data::new_secret(&pin).unwrap();
+        assert!(data.chars().eq("secret".chars()));
+        assert!(matches!(Response::from(data), Response::SecretData(_)));
+    }
 }
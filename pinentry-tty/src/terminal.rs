@@ -5,11 +5,15 @@
 //! terminal.
 //!
 //! [`Termion`] is a out-of-box terminal implementation provided when `termion` feature
-//! is enabled (it is enabled by default).
+//! is enabled (it is enabled by default). [`Crossterm`] is an alternative one that supports
+//! Windows, it's provided when `crossterm` feature is enabled.
 
 use std::{fmt, io};
 
-use crate::PushPop;
+use crate::{
+    clock::{Clock, Deadline},
+    PushPop,
+};
 
 /// TTY terminal
 ///
@@ -30,6 +34,87 @@ pub trait Terminal: io::Read + io::Write {
         impl Iterator<Item = io::Result<Key>> + '_,
         impl io::Write + '_,
     )>;
+
+    /// Indicates whether the terminal supports styling the text (colors, bold text, etc.)
+    ///
+    /// When it returns `false`, no styling control sequences should be written to the terminal.
+    /// Default implementation returns `true`.
+    fn supports_styling(&self) -> bool {
+        true
+    }
+
+    /// Returns the clock used by time-dependent TUI features
+    ///
+    /// Default implementation returns [system clock](crate::clock::SystemClock). Terminals
+    /// used in tests may return [`MockClock`](crate::clock::MockClock) to make time-dependent
+    /// behavior deterministic.
+    fn clock(&self) -> &dyn Clock {
+        &crate::clock::SystemClock
+    }
+
+    /// Waits until user presses a key or `timeout` elapses
+    ///
+    /// Returns `true` if a key was pressed, the key is consumed. Default implementation
+    /// can't detect key presses: it [sleeps](Clock::sleep) for `timeout` and returns `false`.
+    fn wait_key(&mut self, timeout: std::time::Duration) -> io::Result<bool> {
+        self.clock().sleep(timeout);
+        Ok(false)
+    }
+
+    /// Returns size of the terminal as `(columns, rows)`, if it's known
+    ///
+    /// Default implementation returns `None`.
+    fn size(&self) -> Option<(u16, u16)> {
+        None
+    }
+
+    /// Sets the point in time after which reading the input stops waiting for the user
+    ///
+    /// Once `deadline` is reached, [keys](Self::keys) and [reading](io::Read) fail with
+    /// [`io::ErrorKind::TimedOut`] error, which [`Tui`] commands report as timeout. `None`
+    /// removes the deadline.
+    ///
+    /// Returns `false` if the terminal doesn't support deadlines, in which case the input
+    /// is awaited indefinitely. Default implementation doesn't support them.
+    fn set_deadline(&mut self, deadline: Option<Deadline>) -> bool {
+        let _ = deadline;
+        false
+    }
+
+    /// Reads a single key, waiting for it until `deadline`
+    ///
+    /// Returns `None` if no key was pressed before the deadline or the input has ended.
+    /// Default implementation [sets the deadline](Self::set_deadline), reads the next
+    /// [key](Self::keys), and removes the deadline afterwards. Fails with
+    /// [`io::ErrorKind::Unsupported`] if the terminal doesn't support deadlines.
+    fn read_key_timeout(&mut self, deadline: Deadline) -> io::Result<Option<Key>> {
+        if !self.set_deadline(Some(deadline)) {
+            return Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                "terminal doesn't support deadlines",
+            ));
+        }
+        let key = self
+            .keys()
+            .and_then(|(mut keys, _tty_out)| keys.next().transpose());
+        self.set_deadline(None);
+        match key {
+            Err(err) if err.kind() == io::ErrorKind::TimedOut => Ok(None),
+            key => key,
+        }
+    }
+
+    /// Indicates whether the TUI may fall back to canonical mode if [`keys`](Self::keys) fails
+    ///
+    /// In canonical mode, the input is read from the terminal line by line. It's a mode with
+    /// reduced functionality: characters typed by user are visible (the PIN isn't masked), and
+    /// the input is edited by the terminal itself, so special keys like Esc don't work. User
+    /// is warned about that before the input is read.
+    ///
+    /// Default implementation returns `false`, i.e. the TUI fails if raw mode is unavailable.
+    fn canonical_fallback(&self) -> bool {
+        false
+    }
 }
 
 /// Pinentry TUI commands implemented for any [`Terminal`]
@@ -42,6 +127,40 @@ pub trait Tui: Terminal {
         prompt: impl fmt::Display,
         out: &mut impl PushPop<char>,
     ) -> Result<bool, AskPinError>;
+    /// Asks user to provide a PIN, treating pasted input according to `policy`
+    ///
+    /// Same as [`ask_pin`](Tui::ask_pin) which uses [`PastePolicy::Accept`]
+    fn ask_pin_with_paste_policy(
+        &mut self,
+        prompt: impl fmt::Display,
+        out: &mut impl PushPop<char>,
+        policy: PastePolicy,
+    ) -> Result<bool, AskPinError>;
+    /// Asks user to provide a PIN, giving feedback on typed characters according to `echo`
+    ///
+    /// Same as [`ask_pin_with_paste_policy`](Tui::ask_pin_with_paste_policy) which uses
+    /// [`PinEcho::Hidden`]
+    fn ask_pin_with_echo(
+        &mut self,
+        prompt: impl fmt::Display,
+        out: &mut impl PushPop<char>,
+        policy: PastePolicy,
+        echo: PinEcho,
+    ) -> Result<bool, AskPinError>;
+    /// Asks user to provide a PIN, showing how strong the typed PIN is
+    ///
+    /// Same as [`ask_pin_with_echo`](Tui::ask_pin_with_echo), but the [quality bar](QualityBar)
+    /// is rendered after the input and updated as user types. The bar is shown only if
+    /// the terminal [supports styling](Terminal::supports_styling), and it's erased once
+    /// the input is done.
+    fn ask_pin_with_quality(
+        &mut self,
+        prompt: impl fmt::Display,
+        out: &mut impl PushPop<char>,
+        policy: PastePolicy,
+        echo: PinEcho,
+        quality: QualityBar<'_>,
+    ) -> Result<bool, AskPinError>;
     /// Asks user to choose among one or several options
     ///
     /// Similar to [`crate::dialog`] but defined for generic [`Terminal`] and returns more verbose [`DialogError`]
@@ -50,6 +169,173 @@ pub trait Tui: Terminal {
         message: impl fmt::Display,
         options: &'a [(&str, T)],
     ) -> Result<Option<&'a T>, DialogError>;
+    /// Asks user to choose among one or several options, rendering them with given `layout`
+    ///
+    /// Same as [`dialog`](Tui::dialog) which renders options with [`DialogLayout::Lines`]
+    fn dialog_with_layout<'a, T>(
+        &mut self,
+        message: impl fmt::Display,
+        options: &'a [(&str, T)],
+        layout: DialogLayout,
+    ) -> Result<Option<&'a T>, DialogError>;
+    /// Asks user to choose among one or several options, reacting to invalid choices according
+    /// to `invalid_choice`
+    ///
+    /// Same as [`dialog_with_layout`](Tui::dialog_with_layout) which uses
+    /// [`InvalidChoice::Ignore`]
+    fn dialog_with_invalid_choice<'a, T>(
+        &mut self,
+        message: impl fmt::Display,
+        options: &'a [(&str, T)],
+        layout: DialogLayout,
+        invalid_choice: InvalidChoice,
+    ) -> Result<Option<&'a T>, DialogError>;
+    /// Asks user to choose among one or several options, pressing Enter chooses the option
+    /// at `default` index
+    ///
+    /// Same as [`dialog_with_invalid_choice`](Tui::dialog_with_invalid_choice) which has no
+    /// default option. Default option is mentioned in the prompt. `default` is ignored if
+    /// it's out of bounds of `options`. See also [`StickyDialog`] that uses the previous
+    /// choice as default.
+    fn dialog_with_default<'a, T>(
+        &mut self,
+        message: impl fmt::Display,
+        options: &'a [(&str, T)],
+        layout: DialogLayout,
+        invalid_choice: InvalidChoice,
+        default: Option<usize>,
+    ) -> Result<Option<&'a T>, DialogError>;
+    /// Asks user to choose among one or several options, telling how the dialog was aborted
+    ///
+    /// Same as [`dialog_with_default`](Tui::dialog_with_default), but instead of `None`
+    /// returns [`DialogChoice::Aborted`] with the [reason](AbortKind), so e.g. Esc can be
+    /// treated differently from Ctrl-C.
+    fn dialog_ex<'a, T>(
+        &mut self,
+        message: impl fmt::Display,
+        options: &'a [(&str, T)],
+        layout: DialogLayout,
+        invalid_choice: InvalidChoice,
+        default: Option<usize>,
+    ) -> Result<DialogChoice<'a, T>, DialogError>;
+    /// Shows a transient notification, e.g. "PIN accepted"
+    ///
+    /// Message is shown until `duration` elapses or user presses a key (if the terminal
+    /// [can detect it](Terminal::wait_key)), then it's cleared. If terminal doesn't
+    /// [support styling](Terminal::supports_styling), message can't be cleared, so it's
+    /// left on its own line.
+    fn notify(
+        &mut self,
+        message: impl fmt::Display,
+        duration: std::time::Duration,
+    ) -> io::Result<()>;
+}
+
+/// Strength meter shown while user types the PIN, see [`Tui::ask_pin_with_quality`]
+#[derive(Clone, Copy)]
+pub struct QualityBar<'a> {
+    /// Text shown before the meter, e.g. `Quality:`
+    pub label: &'a str,
+    /// Estimates quality of the PIN typed so far, from 0 (weakest) to 100 (strongest)
+    pub quality: &'a dyn Fn(&str) -> u8,
+}
+
+/// Defines how [dialog](Tui::dialog_with_invalid_choice) reacts to a key that doesn't choose
+/// any option
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum InvalidChoice {
+    /// Key is silently ignored
+    #[default]
+    Ignore,
+    /// User is told that the choice is invalid and asked to choose again
+    ///
+    /// The message isn't visible in [canonical mode](Terminal::canonical_fallback). Enter
+    /// isn't considered a choice.
+    Warn {
+        /// Ring the terminal bell along with the message
+        beep: bool,
+        /// Abort the dialog after that many invalid choices, if set
+        max_attempts: Option<usize>,
+    },
+}
+
+/// Defines how [PIN prompt](Tui::ask_pin_with_paste_policy) treats pasted input
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum PastePolicy {
+    /// Pasted input is accepted as if it was typed
+    #[default]
+    Accept,
+    /// If user pasted anything, they're asked to confirm the input once it's submitted
+    ///
+    /// Paste is detected via bracketed paste mode, so it's only detected when terminal
+    /// [supports styling](Terminal::supports_styling) and
+    /// [reports pasting](Key::PasteStart). Confirmation is never asked if the terminal
+    /// is in [canonical mode](Terminal::canonical_fallback).
+    Confirm,
+}
+
+/// Defines what [PIN prompt](Tui::ask_pin_with_echo) shows while PIN is typed
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum PinEcho {
+    /// Nothing is shown
+    #[default]
+    Hidden,
+    /// `*` is shown per typed character and erased on backspace
+    ///
+    /// Reveals the PIN length to anyone looking at the terminal. Aborted prompt erases the
+    /// asterisks. Has no effect in [canonical mode](Terminal::canonical_fallback), where
+    /// the terminal echoes the input on its own.
+    Masked,
+}
+
+/// Outcome of a [dialog](Tui::dialog_ex)
+#[derive(Debug, PartialEq, Eq)]
+pub enum DialogChoice<'a, T> {
+    /// User chose the option with given value
+    Selected(&'a T),
+    /// User aborted the dialog
+    Aborted(AbortKind),
+}
+
+impl<'a, T> DialogChoice<'a, T> {
+    /// Returns the chosen value, `None` if dialog was aborted
+    pub fn selected(self) -> Option<&'a T> {
+        match self {
+            Self::Selected(value) => Some(value),
+            Self::Aborted(_) => None,
+        }
+    }
+}
+
+/// Tells how user aborted a [dialog](Tui::dialog_ex)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum AbortKind {
+    /// User pressed Esc
+    Esc,
+    /// User pressed Ctrl-C
+    CtrlC,
+    /// User pressed Ctrl-D
+    CtrlD,
+    /// User sent null signal
+    Null,
+    /// Input ended before user made a choice
+    Eof,
+    /// User made too many [invalid choices](InvalidChoice::Warn)
+    TooManyInvalidChoices,
+}
+
+/// Defines how [dialog](Tui::dialog_with_layout) options are rendered
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum DialogLayout {
+    /// Every option is rendered on its own line
+    #[default]
+    Lines,
+    /// Options are rendered in aligned columns, as many as fit into the terminal
+    /// [width](Terminal::size)
+    ///
+    /// Falls back to [`Lines`](Self::Lines) if terminal size is unknown
+    Columns,
 }
 
 impl<L, R> Terminal for either::Either<L, R>
@@ -75,9 +361,151 @@ where
             }
         }
     }
+
+    fn supports_styling(&self) -> bool {
+        either::for_both!(self, tty => tty.supports_styling())
+    }
+
+    fn clock(&self) -> &dyn Clock {
+        either::for_both!(self, tty => tty.clock())
+    }
+
+    fn wait_key(&mut self, timeout: std::time::Duration) -> io::Result<bool> {
+        either::for_both!(self, tty => tty.wait_key(timeout))
+    }
+
+    fn canonical_fallback(&self) -> bool {
+        either::for_both!(self, tty => tty.canonical_fallback())
+    }
+    fn size(&self) -> Option<(u16, u16)> {
+        either::for_both!(self, tty => tty.size())
+    }
+    fn set_deadline(&mut self, deadline: Option<Deadline>) -> bool {
+        either::for_both!(self, tty => tty.set_deadline(deadline))
+    }
+    fn read_key_timeout(&mut self, deadline: Deadline) -> io::Result<Option<Key>> {
+        either::for_both!(self, tty => tty.read_key_timeout(deadline))
+    }
+}
+
+impl<T: Terminal + ?Sized> Terminal for &mut T {
+    fn keys(
+        &mut self,
+    ) -> io::Result<(
+        impl Iterator<Item = io::Result<Key>> + '_,
+        impl io::Write + '_,
+    )> {
+        (**self).keys()
+    }
+
+    fn supports_styling(&self) -> bool {
+        (**self).supports_styling()
+    }
+
+    fn clock(&self) -> &dyn Clock {
+        (**self).clock()
+    }
+
+    fn wait_key(&mut self, timeout: std::time::Duration) -> io::Result<bool> {
+        (**self).wait_key(timeout)
+    }
+
+    fn canonical_fallback(&self) -> bool {
+        (**self).canonical_fallback()
+    }
+    fn size(&self) -> Option<(u16, u16)> {
+        (**self).size()
+    }
+    fn set_deadline(&mut self, deadline: Option<Deadline>) -> bool {
+        (**self).set_deadline(deadline)
+    }
+    fn read_key_timeout(&mut self, deadline: Deadline) -> io::Result<Option<Key>> {
+        (**self).read_key_timeout(deadline)
+    }
+}
+
+/// Terminal switched into the alternate screen buffer
+///
+/// Anything written to the terminal while it's in the alternate screen doesn't end up in the
+/// scrollback of the main screen. The main screen is restored when the terminal is dropped,
+/// including when the prompt fails or panics. Similar to the raw mode guard returned by
+/// [`Terminal::keys`], it must outlive any guards obtained from the terminal.
+pub struct AlternateScreen<T: Terminal> {
+    tty: T,
+}
+
+impl<T: Terminal> AlternateScreen<T> {
+    /// Switches `tty` into the alternate screen buffer
+    pub fn enter(mut tty: T) -> io::Result<Self> {
+        write!(tty, "{}", ctrl_seq::EnterAlternateScreen)?;
+        tty.flush()?;
+        Ok(Self { tty })
+    }
+}
+
+impl<T: Terminal> Drop for AlternateScreen<T> {
+    fn drop(&mut self) {
+        // Errors are ignored: there's nothing we can do about them on drop
+        let _ = write!(self.tty, "{}", ctrl_seq::LeaveAlternateScreen);
+        let _ = self.tty.flush();
+    }
+}
+
+impl<T: Terminal> io::Read for AlternateScreen<T> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.tty.read(buf)
+    }
+}
+
+impl<T: Terminal> io::Write for AlternateScreen<T> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.tty.write(buf)
+    }
+    fn flush(&mut self) -> io::Result<()> {
+        self.tty.flush()
+    }
+}
+
+impl<T: Terminal> Terminal for AlternateScreen<T> {
+    fn keys(
+        &mut self,
+    ) -> io::Result<(
+        impl Iterator<Item = io::Result<Key>> + '_,
+        impl io::Write + '_,
+    )> {
+        self.tty.keys()
+    }
+
+    fn supports_styling(&self) -> bool {
+        self.tty.supports_styling()
+    }
+
+    fn clock(&self) -> &dyn Clock {
+        self.tty.clock()
+    }
+
+    fn wait_key(&mut self, timeout: std::time::Duration) -> io::Result<bool> {
+        self.tty.wait_key(timeout)
+    }
+
+    fn canonical_fallback(&self) -> bool {
+        self.tty.canonical_fallback()
+    }
+    fn size(&self) -> Option<(u16, u16)> {
+        self.tty.size()
+    }
+    fn set_deadline(&mut self, deadline: Option<Deadline>) -> bool {
+        self.tty.set_deadline(deadline)
+    }
+    fn read_key_timeout(&mut self, deadline: Deadline) -> io::Result<Option<Key>> {
+        self.tty.read_key_timeout(deadline)
+    }
 }
 
 /// Key pressed by terminal user
+///
+/// New keys may be added in the future, so matches should ignore keys they don't handle.
+#[non_exhaustive]
 pub enum Key {
     /// User pressed a regular key represented by the char
     Char(char),
@@ -89,13 +517,69 @@ pub enum Key {
     Esc,
     /// User pressed backspace button
     Backspace,
+    /// User pressed delete button
+    Delete,
+    /// User pressed left arrow
+    Left,
+    /// User pressed right arrow
+    Right,
+    /// User pressed up arrow
+    Up,
+    /// User pressed down arrow
+    Down,
+    /// User pressed home button
+    Home,
+    /// User pressed end button
+    End,
+    /// User started pasting text
+    ///
+    /// Reported by terminals in bracketed paste mode. Pasted characters follow as
+    /// [`Char`](Self::Char) keys.
+    PasteStart,
+    /// User finished pasting text
+    PasteEnd,
+}
+
+/// Character encoding of the terminal input
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Charset {
+    /// UTF-8 encoding
+    #[default]
+    Utf8,
+    /// ISO-8859-1 encoding, used by legacy terminals
+    ///
+    /// Every byte represents a character with the same code point.
+    Latin1,
+}
+
+impl Charset {
+    /// Determines the charset from `LC_CTYPE` locale, e.g. `de_DE.ISO-8859-1`
+    ///
+    /// Locales with unrecognized or missing codeset are assumed to be UTF-8.
+    pub fn from_lc_ctype(lc_ctype: &str) -> Self {
+        let codeset = lc_ctype.split_once('.').map_or("", |(_, codeset)| codeset);
+        let codeset = codeset
+            .split_once('@')
+            .map_or(codeset, |(codeset, _)| codeset);
+        let normalized = codeset
+            .chars()
+            .filter(char::is_ascii_alphanumeric)
+            .map(|x| x.to_ascii_lowercase())
+            .collect::<String>();
+        match normalized.as_str() {
+            "iso88591" | "latin1" => Self::Latin1,
+            _ => Self::Utf8,
+        }
+    }
 }
 
 /// Default terminal implementation based on [termion] crate
 #[cfg(feature = "termion")]
 pub struct Termion<I, O> {
-    input: I,
+    input: DecodedInput<TimedInput<I>>,
     output: O,
+    clock: std::sync::Arc<dyn Clock + Send + Sync>,
+    canonical_fallback: bool,
 }
 
 #[cfg(feature = "termion")]
@@ -112,11 +596,52 @@ where
         if !termion::is_tty(&input.as_fd()) || !termion::is_tty(&output.as_fd()) {
             Err(NotTty)
         } else {
-            Ok(Self { input, output })
+            Ok(Self {
+                input: DecodedInput {
+                    input: TimedInput {
+                        input,
+                        deadline: None,
+                    },
+                    charset: Charset::Utf8,
+                    pending: None,
+                },
+                output,
+                clock: std::sync::Arc::new(crate::clock::SystemClock),
+                canonical_fallback: false,
+            })
         }
     }
 }
 
+#[cfg(feature = "termion")]
+impl<I, O> Termion<I, O> {
+    /// Sets the clock used by time-dependent TUI features
+    ///
+    /// By default, [system clock](crate::clock::SystemClock) is used
+    pub fn with_clock(mut self, clock: std::sync::Arc<dyn Clock + Send + Sync>) -> Self {
+        self.clock = clock;
+        self
+    }
+
+    /// Allows falling back to [canonical mode](Terminal::canonical_fallback) if raw mode
+    /// can't be enabled
+    ///
+    /// Disabled by default
+    pub fn with_canonical_fallback(mut self, enabled: bool) -> Self {
+        self.canonical_fallback = enabled;
+        self
+    }
+
+    /// Sets encoding of the terminal input
+    ///
+    /// Input is transcoded into UTF-8 before it's interpreted, so characters typed on
+    /// a non-UTF-8 terminal are received correctly. UTF-8 is used by default.
+    pub fn with_charset(mut self, charset: Charset) -> Self {
+        self.input.charset = charset;
+        self
+    }
+}
+
 #[cfg(feature = "termion")]
 impl Termion<std::io::Stdin, std::io::Stdout> {
     /// Constructs a terminal from stdin and stdout
@@ -131,7 +656,7 @@ impl Termion<std::io::Stdin, std::io::Stdout> {
 #[cfg(feature = "termion")]
 impl<I, O> io::Read for Termion<I, O>
 where
-    I: io::Read,
+    I: io::Read + std::os::fd::AsFd,
 {
     fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
         self.input.read(buf)
@@ -154,7 +679,7 @@ where
 #[cfg(feature = "termion")]
 impl<I, O> Terminal for Termion<I, O>
 where
-    I: io::Read,
+    I: io::Read + std::os::fd::AsFd,
     O: io::Write + std::os::fd::AsFd,
 {
     fn keys(
@@ -163,24 +688,509 @@ where
         impl Iterator<Item = io::Result<Key>> + '_,
         impl io::Write + '_,
     )> {
+        use termion::event::Event;
         use termion::input::TermRead;
         use termion::raw::IntoRawMode;
         let output = (&mut self.output).into_raw_mode()?;
 
-        let input_keys = (&mut self.input).keys().flat_map(|key| match key {
-            Ok(termion::event::Key::Char(x)) => Some(Ok(Key::Char(x))),
-            Ok(termion::event::Key::Ctrl(x)) => Some(Ok(Key::Ctrl(x))),
-            Ok(termion::event::Key::Null) => Some(Ok(Key::Null)),
-            Ok(termion::event::Key::Esc) => Some(Ok(Key::Esc)),
-            Ok(termion::event::Key::Backspace) => Some(Ok(Key::Backspace)),
+        let input_keys = (&mut self.input).events().flat_map(|event| match event {
+            Ok(Event::Key(termion::event::Key::Char(x))) => Some(Ok(Key::Char(x))),
+            Ok(Event::Key(termion::event::Key::Ctrl(x))) => Some(Ok(Key::Ctrl(x))),
+            Ok(Event::Key(termion::event::Key::Null)) => Some(Ok(Key::Null)),
+            Ok(Event::Key(termion::event::Key::Esc)) => Some(Ok(Key::Esc)),
+            Ok(Event::Key(termion::event::Key::Backspace)) => Some(Ok(Key::Backspace)),
+            Ok(Event::Key(termion::event::Key::Delete)) => Some(Ok(Key::Delete)),
+            Ok(Event::Key(termion::event::Key::Left)) => Some(Ok(Key::Left)),
+            Ok(Event::Key(termion::event::Key::Right)) => Some(Ok(Key::Right)),
+            Ok(Event::Key(termion::event::Key::Up)) => Some(Ok(Key::Up)),
+            Ok(Event::Key(termion::event::Key::Down)) => Some(Ok(Key::Down)),
+            Ok(Event::Key(termion::event::Key::Home)) => Some(Ok(Key::Home)),
+            Ok(Event::Key(termion::event::Key::End)) => Some(Ok(Key::End)),
+            // Termion doesn't recognize bracketed paste sequences
+            Ok(Event::Unsupported(seq)) if seq == PASTE_START => Some(Ok(Key::PasteStart)),
+            Ok(Event::Unsupported(seq)) if seq == PASTE_END => Some(Ok(Key::PasteEnd)),
             Ok(_) => None,
             Err(err) => Some(Err(err)),
         });
 
-        Ok((input_keys, output))
+        Ok((input_keys, output))
+    }
+
+    /// Styling is supported unless `TERM` environment variable is unset or set to `dumb`
+    fn supports_styling(&self) -> bool {
+        std::env::var_os("TERM").is_some_and(|term| term != "dumb")
+    }
+
+    fn clock(&self) -> &dyn Clock {
+        &*self.clock
+    }
+
+    fn canonical_fallback(&self) -> bool {
+        self.canonical_fallback
+    }
+    /// Size of the terminal that stdout is attached to
+    fn size(&self) -> Option<(u16, u16)> {
+        termion::terminal_size().ok()
+    }
+
+    fn set_deadline(&mut self, deadline: Option<Deadline>) -> bool {
+        self.input.input.deadline = deadline.map(|deadline| (deadline, self.clock.clone()));
+        true
+    }
+}
+
+/// Terminal input transcoded from its [charset](Charset) into UTF-8
+#[cfg(feature = "termion")]
+struct DecodedInput<I> {
+    input: I,
+    charset: Charset,
+    /// Second byte of the transcoded character that didn't fit into the buffer
+    pending: Option<u8>,
+}
+
+#[cfg(feature = "termion")]
+impl<I: io::Read> io::Read for DecodedInput<I> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        if let Some(byte) = self.pending.take() {
+            buf[0] = byte;
+            return Ok(1);
+        }
+        match self.charset {
+            Charset::Utf8 => self.input.read(buf),
+            Charset::Latin1 => {
+                // Characters are read one by one as each of them may take two bytes in UTF-8
+                let mut byte = [0u8];
+                if self.input.read(&mut byte)? == 0 {
+                    return Ok(0);
+                }
+                let mut utf8 = [0u8; 2];
+                let encoded = char::from(byte[0]).encode_utf8(&mut utf8).as_bytes();
+                buf[0] = encoded[0];
+                self.pending = encoded.get(1).copied();
+                Ok(1)
+            }
+        }
+    }
+}
+
+/// Terminal input that isn't awaited past the [deadline](Terminal::set_deadline)
+#[cfg(feature = "termion")]
+struct TimedInput<I> {
+    input: I,
+    deadline: Option<(Deadline, std::sync::Arc<dyn Clock + Send + Sync>)>,
+}
+
+#[cfg(feature = "termion")]
+impl<I: io::Read + std::os::fd::AsFd> io::Read for TimedInput<I> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if let Some((deadline, clock)) = &self.deadline {
+            wait_input(self.input.as_fd(), deadline, &**clock)?;
+        }
+        self.input.read(buf)
+    }
+}
+
+/// Waits until `input` has data to read
+///
+/// Returns [`io::ErrorKind::TimedOut`] error once `deadline` is reached
+#[cfg(feature = "termion")]
+fn wait_input(
+    input: std::os::fd::BorrowedFd,
+    deadline: &Deadline,
+    clock: &dyn Clock,
+) -> io::Result<()> {
+    use std::os::fd::AsRawFd;
+    loop {
+        let remaining = deadline.remaining(&clock);
+        if remaining.is_zero() {
+            return Err(io::Error::new(io::ErrorKind::TimedOut, "input timed out"));
+        }
+        // Rounded up, so poll doesn't return right before the deadline
+        let timeout_ms = remaining.as_nanos().div_ceil(1_000_000);
+        let mut fd = libc::pollfd {
+            fd: input.as_raw_fd(),
+            events: libc::POLLIN,
+            revents: 0,
+        };
+        // SAFETY: `fd` is a valid pollfd structure, and the length is 1
+        let ready = unsafe { libc::poll(&mut fd, 1, timeout_ms.try_into().unwrap_or(i32::MAX)) };
+        match ready {
+            -1 => {
+                let err = io::Error::last_os_error();
+                if err.kind() != io::ErrorKind::Interrupted {
+                    return Err(err);
+                }
+            }
+            0 => continue,
+            _ => return Ok(()),
+        }
+    }
+}
+
+/// Terminal implementation based on [crossterm] crate
+///
+/// Unlike [`Termion`], it works on Windows consoles too (`termion` feature needs to be disabled
+/// there as it's enabled by default). Crossterm reads the input from the terminal the process
+/// is attached to, so only stdin/stdout can be used.
+#[cfg(feature = "crossterm")]
+pub struct Crossterm {
+    output: io::Stdout,
+    clock: std::sync::Arc<dyn Clock + Send + Sync>,
+    canonical_fallback: bool,
+    deadline: Option<Deadline>,
+}
+
+#[cfg(feature = "crossterm")]
+impl Crossterm {
+    /// Constructs a terminal from stdin and stdout
+    ///
+    /// Returns error if stdin/stdout do not correspond to TTY terminal
+    /// (could be the case if program is piped)
+    pub fn new_stdio() -> Result<Self, NotTty> {
+        use std::io::IsTerminal;
+        if !io::stdin().is_terminal() || !io::stdout().is_terminal() {
+            return Err(NotTty);
+        }
+        Ok(Self {
+            output: io::stdout(),
+            clock: std::sync::Arc::new(crate::clock::SystemClock),
+            canonical_fallback: false,
+            deadline: None,
+        })
+    }
+
+    /// Sets the clock used by time-dependent TUI features
+    ///
+    /// By default, [system clock](crate::clock::SystemClock) is used
+    pub fn with_clock(mut self, clock: std::sync::Arc<dyn Clock + Send + Sync>) -> Self {
+        self.clock = clock;
+        self
+    }
+
+    /// Allows falling back to [canonical mode](Terminal::canonical_fallback) if raw mode
+    /// can't be enabled
+    ///
+    /// Disabled by default
+    pub fn with_canonical_fallback(mut self, enabled: bool) -> Self {
+        self.canonical_fallback = enabled;
+        self
+    }
+}
+
+/// Waits until crossterm has an event to read
+///
+/// Returns [`io::ErrorKind::TimedOut`] error once `deadline` is reached
+#[cfg(feature = "crossterm")]
+fn wait_event(deadline: Option<&Deadline>, clock: &dyn Clock) -> io::Result<()> {
+    let Some(deadline) = deadline else {
+        return Ok(());
+    };
+    loop {
+        let remaining = deadline.remaining(&clock);
+        if remaining.is_zero() {
+            return Err(io::Error::new(io::ErrorKind::TimedOut, "input timed out"));
+        }
+        if crossterm::event::poll(remaining)? {
+            return Ok(());
+        }
+    }
+}
+
+#[cfg(feature = "crossterm")]
+impl io::Read for Crossterm {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        wait_event(self.deadline.as_ref(), &*self.clock)?;
+        io::stdin().read(buf)
+    }
+}
+
+#[cfg(feature = "crossterm")]
+impl io::Write for Crossterm {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.output.write(buf)
+    }
+    fn flush(&mut self) -> io::Result<()> {
+        self.output.flush()
+    }
+}
+
+#[cfg(feature = "crossterm")]
+impl Terminal for Crossterm {
+    fn keys(
+        &mut self,
+    ) -> io::Result<(
+        impl Iterator<Item = io::Result<Key>> + '_,
+        impl io::Write + '_,
+    )> {
+        use crossterm::event::Event;
+
+        let output = CrosstermRawMode::enable(&mut self.output)?;
+
+        // Pasted text is received at once, it's split into keys
+        let mut pending = std::collections::VecDeque::new();
+        let (clock, deadline) = (&*self.clock, self.deadline);
+        let keys = std::iter::from_fn(move || loop {
+            if let Some(key) = pending.pop_front() {
+                return Some(Ok(key));
+            }
+            if let Err(err) = wait_event(deadline.as_ref(), clock) {
+                return Some(Err(err));
+            }
+            match crossterm::event::read() {
+                Ok(Event::Key(key)) => match crossterm_key(key) {
+                    Some(key) => return Some(Ok(key)),
+                    None => continue,
+                },
+                Ok(Event::Paste(text)) => {
+                    pending.push_back(Key::PasteStart);
+                    pending.extend(text.chars().map(Key::Char));
+                    pending.push_back(Key::PasteEnd);
+                }
+                Ok(_) => continue,
+                Err(err) => return Some(Err(err)),
+            }
+        });
+
+        Ok((keys, output))
+    }
+
+    /// Styling is supported unless `TERM` environment variable is unset or set to `dumb`
+    fn supports_styling(&self) -> bool {
+        std::env::var_os("TERM").is_some_and(|term| term != "dumb")
+    }
+
+    fn clock(&self) -> &dyn Clock {
+        &*self.clock
+    }
+
+    fn canonical_fallback(&self) -> bool {
+        self.canonical_fallback
+    }
+
+    fn size(&self) -> Option<(u16, u16)> {
+        crossterm::terminal::size().ok()
+    }
+
+    fn set_deadline(&mut self, deadline: Option<Deadline>) -> bool {
+        self.deadline = deadline;
+        true
+    }
+}
+
+/// Translates crossterm key event into a [`Key`], `None` if the key isn't recognized
+#[cfg(feature = "crossterm")]
+fn crossterm_key(key: crossterm::event::KeyEvent) -> Option<Key> {
+    use crossterm::event::{KeyCode, KeyEventKind, KeyModifiers};
+    // Windows reports key releases as well
+    if key.kind == KeyEventKind::Release {
+        return None;
+    }
+    Some(match key.code {
+        KeyCode::Char(x) if key.modifiers.contains(KeyModifiers::CONTROL) => Key::Ctrl(x),
+        KeyCode::Char(x) => Key::Char(x),
+        KeyCode::Enter => Key::Char('\n'),
+        KeyCode::Tab => Key::Char('\t'),
+        KeyCode::Null => Key::Null,
+        KeyCode::Esc => Key::Esc,
+        KeyCode::Backspace => Key::Backspace,
+        KeyCode::Delete => Key::Delete,
+        KeyCode::Left => Key::Left,
+        KeyCode::Right => Key::Right,
+        KeyCode::Up => Key::Up,
+        KeyCode::Down => Key::Down,
+        KeyCode::Home => Key::Home,
+        KeyCode::End => Key::End,
+        _ => return None,
+    })
+}
+
+/// Writer to the terminal that keeps it in raw mode until it's dropped
+#[cfg(feature = "crossterm")]
+struct CrosstermRawMode<'a> {
+    output: &'a mut io::Stdout,
+    /// Terminal was in raw mode already, so it's left in raw mode on drop
+    was_raw: bool,
+}
+
+#[cfg(feature = "crossterm")]
+impl<'a> CrosstermRawMode<'a> {
+    fn enable(output: &'a mut io::Stdout) -> io::Result<Self> {
+        let was_raw = crossterm::terminal::is_raw_mode_enabled()?;
+        if !was_raw {
+            crossterm::terminal::enable_raw_mode()?;
+        }
+        Ok(Self { output, was_raw })
+    }
+}
+
+#[cfg(feature = "crossterm")]
+impl io::Write for CrosstermRawMode<'_> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.output.write(buf)
+    }
+    fn flush(&mut self) -> io::Result<()> {
+        self.output.flush()
+    }
+}
+
+#[cfg(feature = "crossterm")]
+impl Drop for CrosstermRawMode<'_> {
+    fn drop(&mut self) {
+        let _ = io::Write::flush(self.output);
+        if !self.was_raw {
+            let _ = crossterm::terminal::disable_raw_mode();
+        }
+    }
+}
+
+/// Terminal that types scripted keys and records the output
+///
+/// Makes it possible to test TUI flows without a real TTY. [Keys](Terminal::keys) are handed
+/// out from the script one by one, keys that weren't consumed remain for the next interaction.
+/// Once the script runs out, there's no more input, or, if a [deadline](Terminal::set_deadline)
+/// is set, the clock is advanced to the deadline and reading times out. Everything written to
+/// the terminal is recorded and can be obtained via [`output`](Self::output).
+///
+/// Terminal uses [`MockClock`](crate::clock::MockClock), so time-dependent behavior is
+/// deterministic. Styling isn't supported unless enabled via [`with_styling`](Self::with_styling).
+///
+/// ### Example
+/// ```rust
+/// use pinentry_tty::{terminal::{Key, ScriptedTerminal}, Tui};
+///
+/// let mut tty = ScriptedTerminal::new([Key::Char('1')]);
+/// let choice = tty.dialog("Proceed?", &[("Yes", true), ("No", false)])?;
+/// assert_eq!(choice, Some(&true));
+/// # Ok::<_, pinentry_tty::terminal::DialogError>(())
+/// ```
+#[cfg(any(test, feature = "test-util"))]
+pub struct ScriptedTerminal {
+    keys: std::collections::VecDeque<Key>,
+    output: Vec<u8>,
+    typed_bytes: Vec<u8>,
+    supports_styling: bool,
+    clock: crate::clock::MockClock,
+    deadline: Option<Deadline>,
+}
+
+#[cfg(any(test, feature = "test-util"))]
+impl ScriptedTerminal {
+    /// Constructs a terminal that types `keys`
+    pub fn new(keys: impl IntoIterator<Item = Key>) -> Self {
+        Self {
+            keys: keys.into_iter().collect(),
+            output: vec![],
+            typed_bytes: vec![],
+            supports_styling: false,
+            clock: crate::clock::MockClock::new(),
+            deadline: None,
+        }
+    }
+
+    /// Sets whether terminal [supports styling](Terminal::supports_styling)
+    pub fn with_styling(mut self, v: bool) -> Self {
+        self.supports_styling = v;
+        self
+    }
+
+    /// Uses given `clock`, e.g. to advance it from the test
+    pub fn with_clock(mut self, clock: crate::clock::MockClock) -> Self {
+        self.clock = clock;
+        self
+    }
+
+    /// Returns everything written to the terminal so far
+    pub fn output(&self) -> &[u8] {
+        &self.output
+    }
+
+    /// Returns number of scripted keys that haven't been consumed yet
+    pub fn remaining_keys(&self) -> usize {
+        self.keys.len()
+    }
+}
+
+/// Outside of raw mode, scripted characters are read as bytes
+#[cfg(any(test, feature = "test-util"))]
+impl io::Read for ScriptedTerminal {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.typed_bytes.is_empty() {
+            if let Some(Key::Char(x)) = self.keys.front() {
+                self.typed_bytes = x.to_string().into_bytes();
+                self.keys.pop_front();
+            }
+        }
+        let len = self.typed_bytes.len().min(buf.len());
+        buf[..len].copy_from_slice(&self.typed_bytes[..len]);
+        self.typed_bytes.drain(..len);
+        Ok(len)
+    }
+}
+
+#[cfg(any(test, feature = "test-util"))]
+impl io::Write for ScriptedTerminal {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.output.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+#[cfg(any(test, feature = "test-util"))]
+impl Terminal for ScriptedTerminal {
+    fn keys(
+        &mut self,
+    ) -> io::Result<(
+        impl Iterator<Item = io::Result<Key>> + '_,
+        impl io::Write + '_,
+    )> {
+        let (keys, clock, deadline) = (&mut self.keys, &self.clock, self.deadline);
+        let keys = std::iter::from_fn(move || {
+            if let Some(key) = keys.pop_front() {
+                return Some(Ok(key));
+            }
+            let deadline = deadline?;
+            clock.advance(deadline.remaining(clock));
+            Some(Err(io::ErrorKind::TimedOut.into()))
+        });
+        Ok((keys, &mut self.output))
+    }
+
+    fn supports_styling(&self) -> bool {
+        self.supports_styling
+    }
+
+    fn clock(&self) -> &dyn Clock {
+        &self.clock
+    }
+
+    fn wait_key(&mut self, timeout: std::time::Duration) -> io::Result<bool> {
+        if self.keys.pop_front().is_some() {
+            Ok(true)
+        } else {
+            self.clock.advance(timeout);
+            Ok(false)
+        }
+    }
+
+    fn set_deadline(&mut self, deadline: Option<Deadline>) -> bool {
+        self.deadline = deadline;
+        true
     }
 }
 
+/// Sent by terminal in bracketed paste mode before pasted text
+#[cfg(feature = "termion")]
+const PASTE_START: &[u8] = b"\x1b[200~";
+/// Sent by terminal in bracketed paste mode after pasted text
+#[cfg(feature = "termion")]
+const PASTE_END: &[u8] = b"\x1b[201~";
+
 /// Provided input/output do not correspond to a TTY terminal
 #[derive(Debug)]
 pub struct NotTty;
@@ -205,16 +1215,37 @@ impl<T: Terminal> Tui for T {
         prompt: impl fmt::Display,
         out: &mut impl PushPop<char>,
     ) -> Result<bool, AskPinError> {
-        write!(self, "{prompt}").map_err(AskPinError::Write)?;
-        self.flush().map_err(AskPinError::Write)?;
+        self.ask_pin_with_paste_policy(prompt, out, PastePolicy::Accept)
+    }
 
-        if read_pin(self, out)? {
-            writeln!(self).map_err(AskPinError::Write)?;
-            Ok(true)
-        } else {
-            writeln!(self, "Aborted.").map_err(AskPinError::Write)?;
-            Ok(false)
-        }
+    fn ask_pin_with_paste_policy(
+        &mut self,
+        prompt: impl fmt::Display,
+        out: &mut impl PushPop<char>,
+        policy: PastePolicy,
+    ) -> Result<bool, AskPinError> {
+        self.ask_pin_with_echo(prompt, out, policy, PinEcho::Hidden)
+    }
+
+    fn ask_pin_with_echo(
+        &mut self,
+        prompt: impl fmt::Display,
+        out: &mut impl PushPop<char>,
+        policy: PastePolicy,
+        echo: PinEcho,
+    ) -> Result<bool, AskPinError> {
+        ask_pin(self, prompt, out, policy, echo, None)
+    }
+
+    fn ask_pin_with_quality(
+        &mut self,
+        prompt: impl fmt::Display,
+        out: &mut impl PushPop<char>,
+        policy: PastePolicy,
+        echo: PinEcho,
+        quality: QualityBar<'_>,
+    ) -> Result<bool, AskPinError> {
+        ask_pin(self, prompt, out, policy, echo, Some(quality))
     }
 
     fn dialog<'a, O>(
@@ -222,6 +1253,48 @@ impl<T: Terminal> Tui for T {
         message: impl fmt::Display,
         options: &'a [(&str, O)],
     ) -> Result<Option<&'a O>, DialogError> {
+        self.dialog_with_layout(message, options, DialogLayout::Lines)
+    }
+
+    fn dialog_with_layout<'a, O>(
+        &mut self,
+        message: impl fmt::Display,
+        options: &'a [(&str, O)],
+        layout: DialogLayout,
+    ) -> Result<Option<&'a O>, DialogError> {
+        self.dialog_with_invalid_choice(message, options, layout, InvalidChoice::Ignore)
+    }
+
+    fn dialog_with_invalid_choice<'a, O>(
+        &mut self,
+        message: impl fmt::Display,
+        options: &'a [(&str, O)],
+        layout: DialogLayout,
+        invalid_choice: InvalidChoice,
+    ) -> Result<Option<&'a O>, DialogError> {
+        self.dialog_with_default(message, options, layout, invalid_choice, None)
+    }
+
+    fn dialog_with_default<'a, O>(
+        &mut self,
+        message: impl fmt::Display,
+        options: &'a [(&str, O)],
+        layout: DialogLayout,
+        invalid_choice: InvalidChoice,
+        default: Option<usize>,
+    ) -> Result<Option<&'a O>, DialogError> {
+        self.dialog_ex(message, options, layout, invalid_choice, default)
+            .map(DialogChoice::selected)
+    }
+
+    fn dialog_ex<'a, O>(
+        &mut self,
+        message: impl fmt::Display,
+        options: &'a [(&str, O)],
+        layout: DialogLayout,
+        invalid_choice: InvalidChoice,
+        default: Option<usize>,
+    ) -> Result<DialogChoice<'a, O>, DialogError> {
         if options.is_empty() {
             return Err(DialogError::TooFewOptions);
         }
@@ -236,35 +1309,325 @@ impl<T: Terminal> Tui for T {
 
         writeln!(self, "{message}").map_err(DialogError::Write)?;
 
-        let result = render_options(self, &options);
+        let default = default.filter(|&i| i < options.len());
+        let result = render_options(self, &options, layout, invalid_choice, default);
         writeln!(self).map_err(DialogError::Write)?;
         result
     }
+
+    fn notify(
+        &mut self,
+        message: impl fmt::Display,
+        duration: std::time::Duration,
+    ) -> io::Result<()> {
+        if !self.supports_styling() {
+            writeln!(self, "{message}")?;
+            self.flush()?;
+            self.wait_key(duration)?;
+            return Ok(());
+        }
+
+        let message = message.to_string();
+        write!(self, "{message}")?;
+        self.flush()?;
+        self.wait_key(duration)?;
+
+        // Cursor is at the last line of the message, lines are cleared bottom up
+        write!(self, "\r{}", ctrl_seq::ClearLine)?;
+        for _ in message.matches('\n') {
+            write!(self, "{}{}", ctrl_seq::CursorUp, ctrl_seq::ClearLine)?;
+        }
+        self.flush()
+    }
+}
+
+/// Implements [`Tui::ask_pin_with_echo`] and [`Tui::ask_pin_with_quality`]
+fn ask_pin(
+    tty: &mut impl Terminal,
+    prompt: impl fmt::Display,
+    out: &mut impl PushPop<char>,
+    policy: PastePolicy,
+    echo: PinEcho,
+    quality: Option<QualityBar>,
+) -> Result<bool, AskPinError> {
+    write!(tty, "{prompt}").map_err(AskPinError::Write)?;
+    tty.flush().map_err(AskPinError::Write)?;
+
+    match read_pin(tty, out, policy, echo, quality) {
+        Ok(true) => {
+            writeln!(tty).map_err(AskPinError::Write)?;
+            Ok(true)
+        }
+        Ok(false) => {
+            writeln!(tty, "Aborted.").map_err(AskPinError::Write)?;
+            Ok(false)
+        }
+        Err(AskPinError::TimedOut) => {
+            writeln!(tty, "Timed out.").map_err(AskPinError::Write)?;
+            Err(AskPinError::TimedOut)
+        }
+        Err(err) => Err(err),
+    }
+}
+
+fn read_pin(
+    tty: &mut impl Terminal,
+    out: &mut impl PushPop<char>,
+    policy: PastePolicy,
+    echo: PinEcho,
+    quality: Option<QualityBar>,
+) -> Result<bool, AskPinError> {
+    let canonical_fallback = tty.canonical_fallback();
+    let detect_paste = policy == PastePolicy::Confirm && tty.supports_styling();
+    let quality = quality.filter(|_| tty.supports_styling());
+    let mut pasted = false;
+    let submitted = match tty.keys() {
+        Ok((keys, mut tty_out)) => {
+            if detect_paste {
+                set_bracketed_paste(&mut tty_out, true)?;
+            }
+            let submitted = match quality {
+                Some(quality) => {
+                    let mut out = TypedPin::new(out);
+                    draw_quality_bar(&mut tty_out, &quality, &out.typed)?;
+                    let submitted = read_pin_keys(
+                        keys,
+                        &mut out,
+                        &mut pasted,
+                        echo,
+                        |tty_out, pin| draw_quality_bar(tty_out, &quality, &pin.typed),
+                        &mut tty_out,
+                    );
+                    // Erase the quality bar
+                    io::Write::write_all(&mut tty_out, b"\x1b[K").map_err(AskPinError::Write)?;
+                    submitted
+                }
+                None => read_pin_keys(keys, out, &mut pasted, echo, |_, _| Ok(()), &mut tty_out),
+            };
+            if detect_paste {
+                set_bracketed_paste(&mut tty_out, false)?;
+            }
+            Some(submitted?)
+        }
+        Err(err) if !canonical_fallback => return Err(AskPinError::RawMode(err)),
+        Err(_) => None,
+    };
+    let Some(submitted) = submitted else {
+        write!(tty, "(input is visible) ").map_err(AskPinError::Write)?;
+        tty.flush().map_err(AskPinError::Write)?;
+        return read_pin_keys(
+            canonical_keys(tty),
+            out,
+            &mut pasted,
+            PinEcho::Hidden,
+            |_, _| Ok(()),
+            io::sink(),
+        );
+    };
+
+    if submitted && pasted && detect_paste {
+        confirm_paste(tty, out)
+    } else {
+        Ok(submitted)
+    }
+}
+
+/// PIN that mirrors the typed characters, so the PIN can be inspected while it's typed
+struct TypedPin<'a, P> {
+    out: &'a mut P,
+    typed: zeroize::Zeroizing<String>,
+    /// Number of characters at the end of the PIN that didn't fit into `typed`
+    untracked: usize,
+}
+
+impl<'a, P> TypedPin<'a, P> {
+    fn new(out: &'a mut P) -> Self {
+        Self {
+            out,
+            typed: zeroize::Zeroizing::new(String::with_capacity(
+                assuan::response::Data::MAX_BYTES,
+            )),
+            untracked: 0,
+        }
+    }
+}
+
+impl<P: PushPop<char>> PushPop<char> for TypedPin<'_, P> {
+    fn push(&mut self, x: char) -> Result<(), char> {
+        self.out.push(x)?;
+        // Mirror never reallocates, so the PIN isn't left in freed memory
+        if PushPop::push(&mut self.typed, x).is_err() {
+            self.untracked += 1;
+        }
+        Ok(())
+    }
+
+    fn pop(&mut self) -> Option<char> {
+        let x = self.out.pop()?;
+        if self.untracked > 0 {
+            self.untracked -= 1;
+        } else {
+            PushPop::pop(&mut self.typed);
+        }
+        Some(x)
+    }
+}
+
+/// Draws the quality bar of `pin` after the cursor, the cursor is left in place
+fn draw_quality_bar(
+    tty_out: &mut impl io::Write,
+    bar: &QualityBar,
+    pin: &str,
+) -> Result<(), AskPinError> {
+    let filled = usize::from((bar.quality)(pin).min(100) / 10);
+    let meter = format!(
+        " {} [{}{}]",
+        bar.label,
+        "#".repeat(filled),
+        "-".repeat(10 - filled)
+    );
+    let width = meter.chars().count();
+    write!(tty_out, "\x1b[K{meter}\x1b[{width}D").map_err(AskPinError::Write)?;
+    tty_out.flush().map_err(AskPinError::Write)
+}
+
+/// Enables or disables bracketed paste mode, in which terminal reports pasting
+/// via [`Key::PasteStart`] and [`Key::PasteEnd`]
+fn set_bracketed_paste(tty_out: &mut impl io::Write, enabled: bool) -> Result<(), AskPinError> {
+    let mode = if enabled { 'h' } else { 'l' };
+    write!(tty_out, "\x1b[?2004{mode}").map_err(AskPinError::Write)?;
+    tty_out.flush().map_err(AskPinError::Write)
+}
+
+/// Asks user to confirm the submitted PIN that was pasted
+///
+/// If user doesn't confirm it, the PIN is erased from `out`
+fn confirm_paste(
+    tty: &mut impl Terminal,
+    out: &mut impl PushPop<char>,
+) -> Result<bool, AskPinError> {
+    write!(tty, "\nPasted input detected. Accept it? [y/N] ").map_err(AskPinError::Write)?;
+    tty.flush().map_err(AskPinError::Write)?;
+
+    let (mut keys, _tty_out) = tty.keys().map_err(AskPinError::RawMode)?;
+    let key = keys.next().transpose().map_err(AskPinError::read)?;
+    let accepted = matches!(key, Some(Key::Char('y' | 'Y')));
+    if !accepted {
+        while out.pop().is_some() {}
+    }
+    Ok(accepted)
 }
 
-fn read_pin(tty: &mut impl Terminal, out: &mut impl PushPop<char>) -> Result<bool, AskPinError> {
-    let (keys, _tty_out) = tty.keys().map_err(AskPinError::RawMode)?;
+/// Reads the PIN into `out`, `on_change` is called whenever the PIN is edited
+fn read_pin_keys<P: PushPop<char>, W: io::Write>(
+    keys: impl Iterator<Item = io::Result<Key>>,
+    out: &mut P,
+    pasted: &mut bool,
+    echo: PinEcho,
+    mut on_change: impl FnMut(&mut W, &P) -> Result<(), AskPinError>,
+    mut tty_out: W,
+) -> Result<bool, AskPinError> {
+    // Number of asterisks currently shown on the screen
+    let mut masked = 0usize;
+    let show = |tty_out: &mut W, s: &str| -> Result<(), AskPinError> {
+        if echo == PinEcho::Masked {
+            tty_out
+                .write_all(s.as_bytes())
+                .map_err(AskPinError::Write)?;
+            tty_out.flush().map_err(AskPinError::Write)?;
+        }
+        Ok(())
+    };
     for k in keys {
-        match k.map_err(AskPinError::Read)? {
+        let k = match k {
+            Ok(k) => k,
+            Err(err) => {
+                show(&mut tty_out, &"\x08 \x08".repeat(masked))?;
+                return Err(AskPinError::read(err));
+            }
+        };
+        match k {
             Key::Char('\n') | Key::Char('\r') => return Ok(true),
+            Key::PasteStart => *pasted = true,
             Key::Char(x) => {
                 out.push(x).map_err(|_| AskPinError::PinTooLong)?;
+                show(&mut tty_out, "*")?;
+                masked += 1;
             }
             Key::Backspace => {
-                let _ = out.pop();
+                if out.pop().is_some() {
+                    show(&mut tty_out, "\x08 \x08")?;
+                    masked -= 1;
+                }
+            }
+            Key::Ctrl('u' | 'U') => {
+                while out.pop().is_some() {
+                    show(&mut tty_out, "\x08 \x08")?;
+                    masked -= 1;
+                }
+            }
+            Key::Ctrl('w' | 'W') => {
+                // Trailing whitespaces are erased along with the last word
+                let mut in_word = false;
+                while let Some(x) = out.pop() {
+                    if x.is_whitespace() && in_word {
+                        // Separator preceding the word is kept. It was just popped, so
+                        // there's room to push it back
+                        out.push(x).map_err(|_| AskPinError::PinTooLong)?;
+                        break;
+                    }
+                    in_word |= !x.is_whitespace();
+                    show(&mut tty_out, "\x08 \x08")?;
+                    masked -= 1;
+                }
             }
             Key::Ctrl('c')
             | Key::Ctrl('C')
             | Key::Ctrl('d')
             | Key::Ctrl('D')
             | Key::Null
-            | Key::Esc => return Ok(false),
+            | Key::Esc => {
+                show(&mut tty_out, &"\x08 \x08".repeat(masked))?;
+                return Ok(false);
+            }
             _ => continue,
         }
+        on_change(&mut tty_out, out)?;
     }
     Err(AskPinError::Read(io::ErrorKind::UnexpectedEof.into()))
 }
 
+/// Reads keys from the terminal in canonical mode
+///
+/// Used when raw mode is unavailable. Terminal sends the input only once user presses Enter,
+/// and the line is edited by the terminal itself, so only regular characters are received.
+fn canonical_keys(input: &mut impl io::Read) -> impl Iterator<Item = io::Result<Key>> + '_ {
+    std::iter::from_fn(move || {
+        let mut buf = zeroize::Zeroizing::new([0u8; 4]);
+        for len in 1..=buf.len() {
+            loop {
+                match input.read(&mut buf[len - 1..len]) {
+                    Ok(0) if len == 1 => return None,
+                    Ok(0) => return Some(Err(io::ErrorKind::UnexpectedEof.into())),
+                    Ok(_) => break,
+                    Err(err) if err.kind() == io::ErrorKind::Interrupted => continue,
+                    Err(err) => return Some(Err(err)),
+                }
+            }
+            match std::str::from_utf8(&buf[..len]) {
+                Ok(x) => return x.chars().next().map(|x| Ok(Key::Char(x))),
+                // Character is incomplete, read the next byte
+                Err(err) if err.error_len().is_none() => continue,
+                Err(_) => break,
+            }
+        }
+        Some(Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "input is not valid UTF-8",
+        )))
+    })
+}
+
 /// Explains why [`ask_pin`](Tui::ask_pin) failed
 #[derive(Debug)]
 #[non_exhaustive]
@@ -277,6 +1640,19 @@ pub enum AskPinError {
     RawMode(io::Error),
     /// User entered too long PIN
     PinTooLong,
+    /// [Deadline](Terminal::set_deadline) was reached before user submitted the PIN
+    TimedOut,
+}
+
+impl AskPinError {
+    /// Wraps error occurred while reading the input, recognizing timeouts
+    fn read(err: io::Error) -> Self {
+        if err.kind() == io::ErrorKind::TimedOut {
+            Self::TimedOut
+        } else {
+            Self::Read(err)
+        }
+    }
 }
 
 impl fmt::Display for AskPinError {
@@ -286,6 +1662,7 @@ impl fmt::Display for AskPinError {
             AskPinError::Write(err) => write!(f, "write to tty: {err}"),
             AskPinError::RawMode(err) => write!(f, "switch to raw mode: {err}"),
             AskPinError::PinTooLong => write!(f, "pin is too long"),
+            AskPinError::TimedOut => write!(f, "timed out"),
         }
     }
 }
@@ -296,7 +1673,7 @@ impl std::error::Error for AskPinError {
             AskPinError::Read(err) => Some(err),
             AskPinError::Write(err) => Some(err),
             AskPinError::RawMode(err) => Some(err),
-            AskPinError::PinTooLong => None,
+            AskPinError::PinTooLong | AskPinError::TimedOut => None,
         }
     }
 }
@@ -308,11 +1685,24 @@ impl From<AskPinError> for io::Error {
                 err.kind()
             }
             AskPinError::PinTooLong => io::ErrorKind::Other,
+            AskPinError::TimedOut => io::ErrorKind::TimedOut,
         };
         io::Error::new(kind, err)
     }
 }
 
+impl assuan::HasErrorCode for AskPinError {
+    fn code(&self) -> assuan::ErrorCode {
+        match self {
+            AskPinError::Read(_) | AskPinError::Write(_) | AskPinError::RawMode(_) => {
+                assuan::ErrorCode::ASS_GENERAL
+            }
+            AskPinError::PinTooLong => assuan::ErrorCode::TOO_LARGE,
+            AskPinError::TimedOut => assuan::ErrorCode::TIMEOUT,
+        }
+    }
+}
+
 struct DialogOption<'a, T> {
     text: &'a str,
     short: Option<char>,
@@ -323,9 +1713,11 @@ impl<'a, T> DialogOption<'a, T> {
     pub fn new(text: &'a str, value: &'a T, existing_options: &[DialogOption<T>]) -> Self {
         let short_already_used =
             |&short: &char| existing_options.iter().any(|o| Some(short) == o.short);
+        // Short character must never be confused with option number: some characters like
+        // roman numerals are both alphabetic and numeric
         let available_short = text
             .chars()
-            .filter(|x| x.is_alphabetic())
+            .filter(|x| x.is_alphabetic() && !x.is_numeric())
             .find(|x| !short_already_used(x));
 
         Self {
@@ -335,15 +1727,20 @@ impl<'a, T> DialogOption<'a, T> {
         }
     }
 
-    pub fn render(&self, tty_out: &mut impl std::io::Write) -> Result<(), DialogError> {
+    /// Renders the option text, underlining its short character if `styled` is set
+    pub fn render(
+        &self,
+        tty_out: &mut impl std::io::Write,
+        styled: bool,
+    ) -> Result<(), DialogError> {
         if let Some(short) = self.short {
-            use ctrl_seq::{NoUnderline, Underline};
             let (left, right) = self
                 .text
                 .split_once(short)
                 .ok_or(BugReason::ShortCharacterNotFound)?;
-            write!(tty_out, "{left}{Underline}{short}{NoUnderline}{right}")
-                .map_err(DialogError::Write)?;
+            write!(tty_out, "{left}").map_err(DialogError::Write)?;
+            write_underlined(tty_out, styled, short)?;
+            write!(tty_out, "{right}").map_err(DialogError::Write)?;
         } else {
             write!(tty_out, "{}", self.text).map_err(DialogError::Write)?;
         }
@@ -351,77 +1748,259 @@ impl<'a, T> DialogOption<'a, T> {
     }
 }
 
+/// Writes `text` underlined if `styled` is set, or as is otherwise
+fn write_underlined(
+    tty_out: &mut impl std::io::Write,
+    styled: bool,
+    text: impl fmt::Display,
+) -> Result<(), DialogError> {
+    use ctrl_seq::{NoUnderline, Underline};
+    if styled {
+        write!(tty_out, "{Underline}{text}{NoUnderline}")
+    } else {
+        write!(tty_out, "{text}")
+    }
+    .map_err(DialogError::Write)
+}
+
 fn render_options<'a, T>(
     tty: &mut impl Terminal,
     options: &[DialogOption<'a, T>],
-) -> Result<Option<&'a T>, DialogError> {
-    use ctrl_seq::{NoUnderline, Underline};
-    use std::io::Write;
-
+    layout: DialogLayout,
+    invalid_choice: InvalidChoice,
+    default: Option<usize>,
+) -> Result<DialogChoice<'a, T>, DialogError> {
     if options.len() > 9 {
         return Err(DialogError::TooManyOptions);
     }
 
-    for (i, option) in (1..).zip(options) {
-        write!(tty, "  {Underline}{i}{NoUnderline} ").map_err(DialogError::Write)?;
-        option.render(tty)?;
+    // Visible width of the option including its number, e.g. `  1 Option`
+    let option_width = |option: &DialogOption<T>| 4 + option.text.chars().count();
+    let cell_width = options.iter().map(option_width).max().unwrap_or(0);
+    let columns = match (layout, tty.size()) {
+        (DialogLayout::Columns, Some((width, _))) => {
+            (usize::from(width) / cell_width).clamp(1, options.len())
+        }
+        _ => 1,
+    };
+
+    let styled = tty.supports_styling();
+    for (row_index, row) in options.chunks(columns).enumerate() {
+        for (column, option) in row.iter().enumerate() {
+            if column > 0 {
+                let padding = cell_width - option_width(&row[column - 1]);
+                write!(tty, "{:padding$}", "").map_err(DialogError::Write)?;
+            }
+            let i = row_index * columns + column + 1;
+            write!(tty, "  ").map_err(DialogError::Write)?;
+            write_underlined(tty, styled, i)?;
+            write!(tty, " ").map_err(DialogError::Write)?;
+            option.render(tty, styled)?;
+        }
         writeln!(tty).map_err(DialogError::Write)?;
     }
 
-    write!(tty, "Type [").map_err(DialogError::Write)?;
+    write_choice_prompt(tty, options, default, styled)?;
+    tty.flush().map_err(DialogError::Write)?;
+
+    let canonical_fallback = tty.canonical_fallback();
+    match tty.keys() {
+        Ok((keys, tty_out)) => {
+            let choice = choose_option(keys, tty_out, options, invalid_choice, default, styled)?;
+            return Ok(chosen_value(choice));
+        }
+        Err(err) if !canonical_fallback => return Err(DialogError::RawMode(err)),
+        Err(_) => (),
+    }
+
+    write!(tty, "(confirm with Enter) ").map_err(DialogError::Write)?;
+    tty.flush().map_err(DialogError::Write)?;
+    let mut keys = canonical_keys(tty);
+    // Terminal echoes the input on its own in canonical mode
+    let choice = choose_option(
+        &mut keys,
+        io::sink(),
+        options,
+        invalid_choice,
+        default,
+        styled,
+    )?;
+    if choice.is_ok_and(|(_value, key)| !is_enter(key)) {
+        // Consume the rest of the line
+        keys.find(|key| !matches!(key, Ok(Key::Char(x)) if *x != '\n'));
+    }
+    Ok(chosen_value(choice))
+}
+
+fn chosen_value<'a, T>(choice: Result<(&'a T, char), AbortKind>) -> DialogChoice<'a, T> {
+    match choice {
+        Ok((value, _key)) => DialogChoice::Selected(value),
+        Err(kind) => DialogChoice::Aborted(kind),
+    }
+}
+
+/// Reads keys until user chooses one of the `options` or aborts the dialog
+/// Writes a prompt listing the keys that choose an option, e.g. `Type [12ab] : `
+///
+/// Default option, if any, is mentioned, e.g. `Type [12ab] (Enter for 2) : `, and its number
+/// is underlined if `styled` is set
+fn write_choice_prompt<T>(
+    out: &mut impl io::Write,
+    options: &[DialogOption<'_, T>],
+    default: Option<usize>,
+    styled: bool,
+) -> Result<(), DialogError> {
+    write!(out, "Type [").map_err(DialogError::Write)?;
     for i in 1..=options.len() {
-        write!(tty, "{i}").map_err(DialogError::Write)?;
+        write_underlined(out, styled && default == Some(i - 1), i)?;
     }
     for short in options
         .iter()
         .flat_map(|o| o.short)
         .map(|s| s.to_lowercase())
     {
-        write!(tty, "{short}").map_err(DialogError::Write)?;
+        write!(out, "{short}").map_err(DialogError::Write)?;
     }
-    write!(tty, "] : ").map_err(DialogError::Write)?;
-    tty.flush().map_err(DialogError::Write)?;
+    write!(out, "]").map_err(DialogError::Write)?;
+    if let Some(default) = default {
+        write!(out, " (Enter for {})", default + 1).map_err(DialogError::Write)?;
+    }
+    write!(out, " : ").map_err(DialogError::Write)
+}
+
+fn is_enter(x: char) -> bool {
+    x == '\n' || x == '\r'
+}
 
-    let (keys, mut tty_out) = tty.keys().map_err(DialogError::RawMode)?;
+/// Finds an option chosen by pressing `x`
+fn find_option<'o, 'a, T>(
+    options: &'o [DialogOption<'a, T>],
+    x: char,
+) -> Option<&'o DialogOption<'a, T>> {
+    if let Some(index) = x.to_digit(10) {
+        let index: usize = index.try_into().ok()?;
+        options.get(index.checked_sub(1)?)
+    } else {
+        options.iter().find(|o| {
+            o.short
+                .map(|s| s.to_lowercase().eq(x.to_lowercase()))
+                .unwrap_or(false)
+        })
+    }
+}
 
+/// Returns the chosen option along with the key that chose it
+fn choose_option<'a, T>(
+    keys: impl Iterator<Item = io::Result<Key>>,
+    mut tty_out: impl io::Write,
+    options: &[DialogOption<'a, T>],
+    invalid_choice: InvalidChoice,
+    default: Option<usize>,
+    styled: bool,
+) -> Result<Result<(&'a T, char), AbortKind>, DialogError> {
+    let mut invalid_attempts = 0;
     for key in keys {
         tty_out.flush().map_err(DialogError::Write)?;
-        match key.map_err(DialogError::Read)? {
+        let key = match key.map_err(DialogError::read) {
+            Ok(key) => key,
+            Err(DialogError::TimedOut) => {
+                write!(tty_out, "Timed out.").map_err(DialogError::Write)?;
+                return Err(DialogError::TimedOut);
+            }
+            Err(err) => return Err(err),
+        };
+        match key {
             Key::Char(x) => {
-                if let Some(index) = x.to_digit(10) {
-                    let Ok(index): Result<usize, _> = index.try_into() else {
-                        continue;
-                    };
-                    let Some(index) = index.checked_sub(1) else {
-                        continue;
-                    };
-                    let Some(option) = options.get(index) else {
-                        continue;
-                    };
+                if let Some(option) = find_option(options, x) {
                     write!(tty_out, "{}", x).map_err(DialogError::Write)?;
-                    return Ok(Some(option.value));
-                } else {
-                    let Some(option) = options.iter().find(|o| {
-                        o.short
-                            .map(|s| s.to_lowercase().eq(x.to_lowercase()))
-                            .unwrap_or(false)
-                    }) else {
-                        continue;
-                    };
-                    write!(tty_out, "{}", x).map_err(DialogError::Write)?;
-                    return Ok(Some(option.value));
+                    return Ok(Ok((option.value, x)));
+                }
+                if let Some(default) = default.filter(|_| is_enter(x)) {
+                    write!(tty_out, "{}", default + 1).map_err(DialogError::Write)?;
+                    return Ok(Ok((options[default].value, x)));
+                }
+                let InvalidChoice::Warn { beep, max_attempts } = invalid_choice else {
+                    continue;
+                };
+                if is_enter(x) {
+                    continue;
+                }
+                invalid_attempts += 1;
+                if beep {
+                    write!(tty_out, "\x07").map_err(DialogError::Write)?;
                 }
+                if max_attempts.is_some_and(|max| invalid_attempts >= max) {
+                    write!(tty_out, "\r\nToo many invalid choices. Aborted.")
+                        .map_err(DialogError::Write)?;
+                    tty_out.flush().map_err(DialogError::Write)?;
+                    return Ok(Err(AbortKind::TooManyInvalidChoices));
+                }
+                // Terminal is in raw mode, so carriage return is needed to start a new line
+                write!(tty_out, "\r\nInvalid choice. ").map_err(DialogError::Write)?;
+                write_choice_prompt(&mut tty_out, options, default, styled)?;
             }
             Key::Ctrl('c' | 'C' | 'd' | 'D') | Key::Null | Key::Esc => {
                 write!(tty_out, "Aborted.").map_err(DialogError::Write)?;
-                return Ok(None);
+                return Ok(Err(match key {
+                    Key::Esc => AbortKind::Esc,
+                    Key::Null => AbortKind::Null,
+                    Key::Ctrl('c' | 'C') => AbortKind::CtrlC,
+                    _ => AbortKind::CtrlD,
+                }));
             }
             _ => {
                 // ignore
             }
         }
     }
-    Ok(None)
+    Ok(Err(AbortKind::Eof))
+}
+
+/// Dialog offering the option chosen last time as the default one
+///
+/// Speeds up a series of similar dialogs within a session, e.g. confirming several actions:
+/// once user has chosen an option, the following [dialogs](Self::dialog) choose it when
+/// Enter is pressed. Options are remembered by their position in the list.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct StickyDialog {
+    last_choice: Option<usize>,
+}
+
+impl StickyDialog {
+    /// Constructs a dialog without default option, until user chooses one
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Index of the option chosen last time, if any
+    pub fn last_choice(&self) -> Option<usize> {
+        self.last_choice
+    }
+
+    /// Asks user to choose among one or several options
+    ///
+    /// Same as [`Tui::dialog`], but pressing Enter chooses the [last choice](Self::last_choice).
+    /// Aborting the dialog keeps the last choice.
+    pub fn dialog<'a, T>(
+        &mut self,
+        tty: &mut impl Tui,
+        message: impl fmt::Display,
+        options: &'a [(&str, T)],
+    ) -> Result<Option<&'a T>, DialogError> {
+        let choice = tty.dialog_with_default(
+            message,
+            options,
+            DialogLayout::Lines,
+            InvalidChoice::Ignore,
+            self.last_choice,
+        )?;
+        if let Some(choice) = choice {
+            self.last_choice = options
+                .iter()
+                .position(|(_, value)| std::ptr::eq(value, choice));
+        }
+        Ok(choice)
+    }
 }
 
 /// Explains why [`dialog`](Tui::dialog) failed
@@ -438,10 +2017,23 @@ pub enum DialogError {
     TooManyOptions,
     /// Too many options were provided as input: [`dialog`](Tui::dialog) can take no more than 9 options
     TooFewOptions,
+    /// [Deadline](Terminal::set_deadline) was reached before user chose an option
+    TimedOut,
     /// Bug occurred
     Bug(Bug),
 }
 
+impl DialogError {
+    /// Wraps error occurred while reading the input, recognizing timeouts
+    fn read(err: io::Error) -> Self {
+        if err.kind() == io::ErrorKind::TimedOut {
+            Self::TimedOut
+        } else {
+            Self::Read(err)
+        }
+    }
+}
+
 /// Error indicating that a bug occurred
 ///
 /// If you encounter this error, please file an issue!
@@ -470,6 +2062,7 @@ impl fmt::Display for DialogError {
                 f,
                 "invalid arguments: at least one option must be specified"
             ),
+            DialogError::TimedOut => write!(f, "timed out"),
             DialogError::Bug(Bug(BugReason::ShortCharacterNotFound)) => {
                 write!(f, "bug occurred: short character not found")
             }
@@ -483,7 +2076,10 @@ impl std::error::Error for DialogError {
             DialogError::Read(err) => Some(err),
             DialogError::Write(err) => Some(err),
             DialogError::RawMode(err) => Some(err),
-            DialogError::TooManyOptions | DialogError::TooFewOptions | DialogError::Bug(_) => None,
+            DialogError::TooManyOptions
+            | DialogError::TooFewOptions
+            | DialogError::TimedOut
+            | DialogError::Bug(_) => None,
         }
     }
 }
@@ -495,13 +2091,31 @@ impl From<DialogError> for io::Error {
                 err.kind()
             }
             DialogError::TooManyOptions | DialogError::TooFewOptions => io::ErrorKind::InvalidInput,
+            DialogError::TimedOut => io::ErrorKind::TimedOut,
             DialogError::Bug(_) => io::ErrorKind::Other,
         };
         io::Error::new(kind, err)
     }
 }
 
-mod ctrl_seq {
+impl assuan::HasErrorCode for DialogError {
+    fn code(&self) -> assuan::ErrorCode {
+        match self {
+            DialogError::Read(_) | DialogError::Write(_) | DialogError::RawMode(_) => {
+                assuan::ErrorCode::ASS_GENERAL
+            }
+            DialogError::TooManyOptions | DialogError::TooFewOptions => {
+                assuan::ErrorCode::ASS_PARAMETER
+            }
+            DialogError::TimedOut => assuan::ErrorCode::TIMEOUT,
+            DialogError::Bug(_) => assuan::ErrorCode::INTERNAL,
+        }
+    }
+}
+
+// Some sequences are only used by the server
+#[cfg_attr(not(feature = "server"), allow(dead_code))]
+pub(crate) mod ctrl_seq {
     use std::fmt;
 
     /// Create a CSI-introduced sequence.
@@ -538,4 +2152,268 @@ mod ctrl_seq {
 
     derive_csi_sequence!("Underlined text.", Underline, "4m");
     derive_csi_sequence!("Undo underlined text.", NoUnderline, "24m");
+    derive_csi_sequence!("Bold text.", Bold, "1m");
+    derive_csi_sequence!("Undo bold text.", NoBold, "22m");
+    derive_csi_sequence!("Red foreground color.", Red, "31m");
+    derive_csi_sequence!("Default foreground color.", DefaultColor, "39m");
+    derive_csi_sequence!("Clear the entire line.", ClearLine, "2K");
+    derive_csi_sequence!("Move the cursor one line up.", CursorUp, "1A");
+    derive_csi_sequence!(
+        "Switch to the alternate screen buffer.",
+        EnterAlternateScreen,
+        "?1049h"
+    );
+    derive_csi_sequence!(
+        "Switch back to the main screen buffer.",
+        LeaveAlternateScreen,
+        "?1049l"
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use assuan::{ErrorCode, HasErrorCode};
+
+    use super::{AskPinError, Charset, DialogError};
+
+    #[test]
+    fn error_codes() {
+        assert_eq!(AskPinError::PinTooLong.code().0, ErrorCode::TOO_LARGE.0);
+        let read_failed = AskPinError::Read(std::io::ErrorKind::UnexpectedEof.into());
+        assert_eq!(read_failed.code().0, ErrorCode::ASS_GENERAL.0);
+        assert_eq!(
+            DialogError::TooFewOptions.code().0,
+            ErrorCode::ASS_PARAMETER.0
+        );
+    }
+
+    #[test]
+    fn scripted_terminal_drives_tui() {
+        use super::{Key, ScriptedTerminal, Tui};
+
+        let options = [("One", 1), ("Two", 2)];
+        let mut tty = ScriptedTerminal::new([Key::Char('1')]);
+        assert_eq!(tty.dialog("Choose", &options).unwrap(), Some(&1));
+        assert_eq!(tty.remaining_keys(), 0);
+
+        let mut tty = ScriptedTerminal::new([Key::Ctrl('c'), Key::Char('2')]);
+        assert_eq!(tty.dialog("Choose", &options).unwrap(), None);
+        assert!(std::str::from_utf8(tty.output())
+            .unwrap()
+            .contains("Aborted."));
+        assert_eq!(tty.remaining_keys(), 1);
+
+        let mut tty = ScriptedTerminal::new([
+            Key::Char('a'),
+            Key::Char('b'),
+            Key::Backspace,
+            Key::Char('c'),
+            Key::Char('\n'),
+        ]);
+        let mut pin = zeroize::Zeroizing::new(String::with_capacity(10));
+        assert!(tty.ask_pin("PIN: ", &mut pin).unwrap());
+        assert_eq!(pin.as_str(), "ac");
+
+        let mut tty = ScriptedTerminal::new([Key::Char('a'), Key::Ctrl('c')]);
+        let mut pin = zeroize::Zeroizing::new(String::with_capacity(10));
+        assert!(!tty.ask_pin("PIN: ", &mut pin).unwrap());
+    }
+
+    #[test]
+    fn quality_bar_follows_typed_pin() {
+        use super::{Key, PastePolicy, PinEcho, QualityBar, ScriptedTerminal, Tui};
+
+        let quality = |pin: &str| (pin.len() * 50) as u8;
+        let bar = QualityBar {
+            label: "Quality:",
+            quality: &quality,
+        };
+        let keys = || {
+            [
+                Key::Char('a'),
+                Key::Char('b'),
+                Key::Backspace,
+                Key::Char('\n'),
+            ]
+        };
+
+        let mut tty = ScriptedTerminal::new(keys()).with_styling(true);
+        let mut pin = zeroize::Zeroizing::new(String::with_capacity(10));
+        assert!(tty
+            .ask_pin_with_quality("PIN: ", &mut pin, PastePolicy::Accept, PinEcho::Hidden, bar)
+            .unwrap());
+        assert_eq!(pin.as_str(), "a");
+        let meter = |bar: &str| format!("\x1b[K Quality: [{bar}]\x1b[22D");
+        assert_eq!(
+            std::str::from_utf8(tty.output()).unwrap(),
+            format!(
+                "PIN: {}{}{}{}\x1b[K\n",
+                meter("----------"),
+                meter("#####-----"),
+                meter("##########"),
+                meter("#####-----"),
+            )
+        );
+
+        // Quality bar can't be erased without styling
+        let mut tty = ScriptedTerminal::new(keys());
+        let mut pin = zeroize::Zeroizing::new(String::with_capacity(10));
+        assert!(tty
+            .ask_pin_with_quality("PIN: ", &mut pin, PastePolicy::Accept, PinEcho::Hidden, bar)
+            .unwrap());
+        assert_eq!(std::str::from_utf8(tty.output()).unwrap(), "PIN: \n");
+    }
+
+    #[test]
+    fn key_is_awaited_until_deadline() {
+        use super::{Key, ScriptedTerminal, Terminal};
+        use crate::clock::{Clock, Deadline, MockClock};
+
+        let clock = MockClock::new();
+        let start = clock.now();
+        let deadline = Deadline::after(&clock, std::time::Duration::from_secs(5));
+        let mut tty = ScriptedTerminal::new([Key::Char('x')]).with_clock(clock.clone());
+
+        let key = tty.read_key_timeout(deadline).unwrap();
+        assert!(matches!(key, Some(Key::Char('x'))));
+        assert_eq!(clock.now(), start);
+
+        assert!(tty.read_key_timeout(deadline).unwrap().is_none());
+        assert_eq!(clock.now() - start, std::time::Duration::from_secs(5));
+    }
+
+    #[test]
+    fn dialog_reports_abort_kind() {
+        use super::{
+            AbortKind, DialogChoice, DialogLayout, InvalidChoice, Key, ScriptedTerminal, Tui,
+        };
+
+        let options = [("One", 1), ("Two", 2)];
+        let choose = |keys: Vec<Key>| {
+            ScriptedTerminal::new(keys)
+                .dialog_ex(
+                    "Choose",
+                    &options,
+                    DialogLayout::Lines,
+                    InvalidChoice::Ignore,
+                    None,
+                )
+                .unwrap()
+        };
+        assert_eq!(
+            choose(vec![Key::Esc]),
+            DialogChoice::Aborted(AbortKind::Esc)
+        );
+        assert_eq!(
+            choose(vec![Key::Ctrl('c')]),
+            DialogChoice::Aborted(AbortKind::CtrlC)
+        );
+        assert_eq!(
+            choose(vec![Key::Ctrl('d')]),
+            DialogChoice::Aborted(AbortKind::CtrlD)
+        );
+        assert_eq!(choose(vec![]), DialogChoice::Aborted(AbortKind::Eof));
+        assert_eq!(choose(vec![Key::Char('2')]), DialogChoice::Selected(&2));
+    }
+
+    #[test]
+    fn charset_from_lc_ctype() {
+        let cases = [
+            ("C", Charset::Utf8),
+            ("en_US.UTF-8", Charset::Utf8),
+            ("de_DE.ISO-8859-1", Charset::Latin1),
+            ("de_DE.iso88591@euro", Charset::Latin1),
+            ("fr_FR.ISO-8859-15", Charset::Utf8),
+        ];
+        for (lc_ctype, charset) in cases {
+            assert_eq!(Charset::from_lc_ctype(lc_ctype), charset, "{lc_ctype}");
+        }
+    }
+
+    #[cfg(feature = "crossterm")]
+    #[test]
+    fn crossterm_keys_are_translated() {
+        use crossterm::event::{KeyCode, KeyEvent, KeyEventKind, KeyModifiers};
+
+        use super::{crossterm_key, Key};
+
+        let key = |code, modifiers| crossterm_key(KeyEvent::new(code, modifiers));
+        assert!(matches!(
+            key(KeyCode::Char('c'), KeyModifiers::CONTROL),
+            Some(Key::Ctrl('c'))
+        ));
+        assert!(matches!(
+            key(KeyCode::Char('A'), KeyModifiers::SHIFT),
+            Some(Key::Char('A'))
+        ));
+        assert!(matches!(
+            key(KeyCode::Enter, KeyModifiers::NONE),
+            Some(Key::Char('\n'))
+        ));
+        assert!(matches!(
+            key(KeyCode::Backspace, KeyModifiers::NONE),
+            Some(Key::Backspace)
+        ));
+        assert!(key(KeyCode::F(1), KeyModifiers::NONE).is_none());
+
+        let mut release = KeyEvent::new(KeyCode::Char('a'), KeyModifiers::NONE);
+        release.kind = KeyEventKind::Release;
+        assert!(crossterm_key(release).is_none());
+    }
+
+    #[cfg(feature = "termion")]
+    #[test]
+    fn input_is_not_awaited_past_deadline() {
+        use std::{io::Read, io::Write, sync::Arc, time::Duration};
+
+        use super::TimedInput;
+        use crate::clock::{Deadline, MockClock};
+
+        let clock = MockClock::new();
+        let (input, mut user) = std::os::unix::net::UnixStream::pair().unwrap();
+        let deadline = Deadline::after(&clock, Duration::from_secs(30));
+        let mut input = TimedInput {
+            input,
+            deadline: Some((deadline, Arc::new(clock.clone()))),
+        };
+
+        user.write_all(b"1").unwrap();
+        let mut buf = [0u8; 4];
+        assert_eq!(input.read(&mut buf).unwrap(), 1);
+
+        clock.advance(Duration::from_secs(30));
+        let err = input.read(&mut buf).unwrap_err();
+        assert_eq!(err.kind(), std::io::ErrorKind::TimedOut);
+    }
+
+    #[cfg(feature = "termion")]
+    #[test]
+    fn latin1_input_is_decoded() {
+        use super::{canonical_keys, DecodedInput, Key};
+
+        let decode = |charset, input: &[u8]| {
+            let mut input = DecodedInput {
+                input,
+                charset,
+                pending: None,
+            };
+            canonical_keys(&mut input)
+                .map(|key| match key {
+                    Ok(Key::Char(x)) => Ok(x),
+                    Ok(_) => panic!("unexpected key"),
+                    Err(err) => Err(err),
+                })
+                .collect::<std::io::Result<String>>()
+        };
+
+        assert_eq!(
+            decode(Charset::Latin1, b"p\xE4\xDF w\xF6rd\n").unwrap(),
+            "p\u{e4}\u{df} w\u{f6}rd\n"
+        );
+        assert_eq!(
+            decode(Charset::Utf8, "p\u{e4}\u{df}".as_bytes()).unwrap(),
+            "p\u{e4}\u{df}"
+        );
+        decode(Charset::Utf8, b"p\xE4\xDF").unwrap_err();
+    }
 }
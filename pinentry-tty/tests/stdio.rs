@@ -0,0 +1,40 @@
+//! Runs pinentry-tty binary against a scripted client
+#![cfg(all(feature = "server", feature = "termion"))]
+
+use std::{
+    io::Write,
+    process::{Command, Stdio},
+};
+
+#[test]
+fn serves_client_over_stdio() {
+    let mut pinentry = Command::new(env!("CARGO_BIN_EXE_pinentry-tty"))
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::inherit())
+        .spawn()
+        .unwrap();
+    let pid = pinentry.id();
+
+    pinentry
+        .stdin
+        .take()
+        .unwrap()
+        .write_all(b"OPTION lc-ctype=C\nSETDESC Unlock the key\nSETPROMPT PIN:\nGETINFO pid\nBYE\n")
+        .unwrap();
+    let output = pinentry.wait_with_output().unwrap();
+
+    assert!(output.status.success());
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap(),
+        format!(
+            "OK how can I serve you?\n\
+            OK success\n\
+            OK success\n\
+            OK success\n\
+            D {pid}\n\
+            OK success\n\
+            OK success\n"
+        )
+    );
+}
@@ -9,6 +9,8 @@
 
 use core::fmt;
 
+use assuan::error_code::Source;
+
 #[doc(no_inline)]
 pub use assuan::{
     self,
@@ -23,16 +25,64 @@ pub use assuan::{
 /// follows the Assuan protocol, receives and recognizes the commands, and so on.
 pub struct PinentryServer<S: PinentryCmds> {
     cmds: S,
+    settings: PinentrySettings,
+    /// Number of `GETPIN` requests received since the last `RESET`
+    pin_attempts: usize,
+    /// Answer to `GETINFO flavor`
+    flavor: String,
+    /// TTY set by `OPTION ttyname`
+    ttyname: Option<String>,
+}
+
+/// State of [`PinentryServer`] configured by the client via `SET*` commands and `OPTION`s
+///
+/// Can be obtained via [`PinentryServer::settings`] and restored via
+/// [`PinentryServer::apply_settings`]. `None` means that the value wasn't set by the client,
+/// and the default one will be used.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct PinentrySettings {
+    /// Detailed description of what PIN is required for, set by `SETDESC`
+    pub desc: Option<String>,
+    /// Text displayed right before PIN input, set by `SETPROMPT`
+    pub prompt: Option<String>,
+    /// Title of the window, set by `SETTITLE`
+    pub window_title: Option<String>,
+
+    /// Text of OK button, set by `SETOK`
+    pub button_ok: Option<String>,
+    /// Text of Not OK button, set by `SETNOTOK`
+    pub button_not_ok: Option<String>,
+    /// Text of Cancel button, set by `SETCANCEL`
+    pub button_cancel: Option<String>,
+
+    /// Error message displayed to the user, set by `SETERROR`
+    pub error_text: Option<String>,
 
-    desc: Option<String>,
-    prompt: Option<String>,
-    window_title: Option<String>,
+    /// Localized text of OK button used when `SETOK` isn't given, set by `OPTION default-ok`
+    pub default_ok: Option<String>,
+    /// Localized text of Cancel button used when `SETCANCEL` isn't given, set by
+    /// `OPTION default-cancel`
+    pub default_cancel: Option<String>,
+    /// Localized prompt used when `SETPROMPT` isn't given, set by `OPTION default-prompt`
+    pub default_prompt: Option<String>,
 
-    button_ok: Option<String>,
-    button_not_ok: Option<String>,
-    button_cancel: Option<String>,
+    /// Time after which the prompt is canceled, set by `SETTIMEOUT`. `None` means no timeout.
+    pub timeout: Option<std::time::Duration>,
 
-    error_text: Option<String>,
+    /// Prompt for typing the PIN once again, set by `SETREPEAT`. When set, `GETPIN` asks for
+    /// the PIN [twice](PinentryCmds::get_pin_repeated).
+    pub repeat_prompt: Option<String>,
+    /// Error message displayed when repeated PIN doesn't match, set by `SETREPEATERROR`
+    pub repeat_error: Option<String>,
+    /// Message [shown](PinentryCmds::show_repeat_ok) once repeated PIN matches, set by
+    /// `SETREPEATOK`
+    pub repeat_ok: Option<String>,
+
+    /// Label of the PIN quality bar, set by `SETQUALITYBAR`. When set, the
+    /// [quality bar](PinentryCmds::set_quality_bar) is shown by `GETPIN`.
+    pub quality_bar: Option<String>,
+    /// Tooltip of the PIN quality bar, set by `SETQUALITYBAR_TT`
+    pub quality_bar_tooltip: Option<String>,
 }
 
 /// Buttons that should be displayed in [confirmation dialog](PinentryCmds::confirm)
@@ -43,6 +93,9 @@ pub struct Buttons<'a> {
     pub not_ok: Option<&'a str>,
     /// Cancel button, suggesting user to abort the operation
     pub cancel: Option<&'a str>,
+    /// Cancel button should be preselected instead of OK, e.g. when confirming a destructive
+    /// operation
+    pub default_cancel: bool,
 }
 
 /// The core of pinentry server: [retrieving pin](Self::get_pin) from the user, and showing the
@@ -57,6 +110,61 @@ pub trait PinentryCmds {
     /// Tells that pinentry was asked to use the given TTY
     fn set_tty(&mut self, path: std::path::PathBuf) -> Result<(), Self::Error>;
 
+    /// Tells the locale of the TTY, i.e. `LC_CTYPE`, e.g. `de_DE.ISO-8859-1`
+    ///
+    /// Backend may use it to determine the encoding of the terminal. Default implementation
+    /// ignores it.
+    fn set_lc_ctype(&mut self, lc_ctype: &str) -> Result<(), Self::Error> {
+        let _ = lc_ctype;
+        Ok(())
+    }
+
+    /// Tells which attempt to enter PIN the following [`get_pin`](Self::get_pin) call is
+    ///
+    /// When PIN is rejected, client asks for it again, usually with an [error](Self::get_pin)
+    /// explaining why. Attempts are counted from 1 and the counter is reset by `RESET`
+    /// request, so backend may show e.g. "Attempt 2" to the user. Default implementation
+    /// ignores it.
+    fn set_pin_attempt(&mut self, attempt: usize) -> Result<(), Self::Error> {
+        let _ = attempt;
+        Ok(())
+    }
+
+    /// Tells how long the following [`get_pin`](Self::get_pin) and [`confirm`](Self::confirm)
+    /// calls may wait for the user
+    ///
+    /// Once `timeout` elapses, the prompt should fail with an error which
+    /// [code](HasErrorCode::code) is [`TIMEOUT`](assuan::ErrorCode::TIMEOUT). `None` means that
+    /// the user is awaited indefinitely. Default implementation ignores it.
+    fn set_timeout(&mut self, timeout: Option<std::time::Duration>) -> Result<(), Self::Error> {
+        let _ = timeout;
+        Ok(())
+    }
+
+    /// Tells whether the following [`get_pin`](Self::get_pin) call should show a bar
+    /// indicating quality of the typed PIN
+    ///
+    /// `label` is `None` if the bar shouldn't be shown. Backend that shows the bar estimates
+    /// the quality via [`pin_quality`](Self::pin_quality). Default implementation ignores it.
+    fn set_quality_bar(
+        &mut self,
+        label: Option<&str>,
+        tooltip: Option<&str>,
+    ) -> Result<(), Self::Error> {
+        let _ = (label, tooltip);
+        Ok(())
+    }
+
+    /// Estimates quality of the `pin` typed so far, from 0 (weakest) to 100 (strongest)
+    ///
+    /// Called by the backend while user types the PIN to render the
+    /// [quality bar](Self::set_quality_bar). `None` means that quality can't be estimated,
+    /// so the bar isn't shown. Default implementation returns `None`.
+    fn pin_quality(&self, pin: &str) -> Option<u8> {
+        let _ = pin;
+        None
+    }
+
     /// Asks user to enter PIN
     ///
     /// # Inputs
@@ -70,7 +178,6 @@ pub trait PinentryCmds {
     /// * `Ok(Some(pin))` if user entered a pin
     /// * `Ok(None)` if user aborted the prompt (e.g. pressed `Ctrl-C` or closed the window)
     /// * `Err(err)` if any unexpected error occurred
-
     fn get_pin(
         &mut self,
         error: Option<&str>,
@@ -79,6 +186,47 @@ pub trait PinentryCmds {
         prompt: &str,
     ) -> Result<Option<SecretData>, Self::Error>;
 
+    /// Asks user to enter PIN twice, used when a new PIN is being set
+    ///
+    /// Inputs are the same as in [`get_pin`](Self::get_pin), the PIN is asked once again with
+    /// `repeat_prompt`. If the PINs don't match, user is asked again from the start with
+    /// `repeat_error` displayed as an error. Returns the PIN once user typed the same PIN twice.
+    ///
+    /// Default implementation calls [`get_pin`](Self::get_pin) for each prompt.
+    /// Once the PINs match, `GETPIN` shows `SETREPEATOK` message, if any, via
+    /// [`show_repeat_ok`](Self::show_repeat_ok).
+    fn get_pin_repeated(
+        &mut self,
+        error: Option<&str>,
+        window_title: &str,
+        desc: Option<&str>,
+        prompt: &str,
+        repeat_prompt: &str,
+        repeat_error: &str,
+    ) -> Result<Option<SecretData>, Self::Error> {
+        let mut error = error;
+        loop {
+            let Some(pin) = self.get_pin(error, window_title, desc, prompt)? else {
+                return Ok(None);
+            };
+            let Some(repeated) = self.get_pin(None, window_title, desc, repeat_prompt)? else {
+                return Ok(None);
+            };
+            if assuan::response::secret_eq(&pin, &repeated) {
+                return Ok(Some(pin));
+            }
+            error = Some(repeat_error);
+        }
+    }
+
+    /// Briefly shows `message` telling user that the repeated PIN matched
+    ///
+    /// Default implementation doesn't show anything.
+    fn show_repeat_ok(&mut self, message: &str) -> Result<(), Self::Error> {
+        let _ = message;
+        Ok(())
+    }
+
     /// Asks user to confirm action
     ///
     /// # Inputs
@@ -115,9 +263,9 @@ pub enum ConfirmChoice {
 macro_rules! define_setters {
     ($($setter_fn:ident $var:ident $($modify:expr)?),*$(,)?) => {$(
         fn $setter_fn(&mut self, $var: Option<&str>) -> Result<Response, HandleError<S::Error>> {
-            self.$var = $var.map(str::to_string);
+            self.settings.$var = $var.map(str::to_string);
             $(
-                if let Some(var) = &mut self.$var {
+                if let Some(var) = &mut self.settings.$var {
                     #[allow(clippy::redundant_closure_call)]
                     let _: () = $modify(var);
                 }
@@ -132,23 +280,51 @@ impl<S: PinentryCmds> PinentryServer<S> {
     pub fn new(cmds: S) -> Self {
         Self {
             cmds,
-            desc: None,
-            prompt: None,
-            window_title: None,
-            button_ok: None,
-            button_not_ok: None,
-            button_cancel: None,
-            error_text: None,
+            settings: PinentrySettings::default(),
+            pin_attempts: 0,
+            flavor: "unknown".into(),
+            ttyname: None,
         }
     }
 
+    /// Sets the flavor of the pinentry, e.g. `tty` or `curses`
+    ///
+    /// Flavor is reported to the client via `GETINFO flavor`. Default is `unknown`.
+    pub fn with_flavor(mut self, flavor: impl Into<String>) -> Self {
+        self.flavor = flavor.into();
+        self
+    }
+
+    /// Returns a snapshot of the settings configured by the client
+    pub fn settings(&self) -> PinentrySettings {
+        self.settings.clone()
+    }
+
+    /// Restores settings previously obtained via [`PinentryServer::settings`]
+    pub fn apply_settings(&mut self, settings: PinentrySettings) {
+        self.settings = settings;
+    }
+
     /// Builds an assuan server ready to serve requests from the client
+    ///
+    /// Server answers `GETINFO` requests that gpg uses to probe the pinentry: `flavor`
+    /// (see [`with_flavor`](Self::with_flavor)), `version` of this crate, and `ttyinfo`
+    /// telling the TTY set by the client.
     pub fn build_assuan_server(
         self,
     ) -> assuan::AssuanServer<Self, impl assuan::router::CmdList<Self>> {
+        let flavor = self.flavor.clone();
         assuan::AssuanServer::new(self)
+            .set_info("flavor", flavor)
+            .set_info("version", env!("CARGO_PKG_VERSION"))
+            .add_getinfo("ttyinfo", |server: &Self| {
+                Ok::<_, std::convert::Infallible>(format!(
+                    "{} - -",
+                    server.ttyname.as_deref().unwrap_or("-")
+                ))
+            })
             .add_command("OPTION", Self::option)
-            .add_command("SETTIMEOUT", Self::not_currently_supported)
+            .add_command("SETTIMEOUT", Self::set_timeout)
             .add_command("SETDESC", Self::set_desc)
             .add_command("SETPROMPT", Self::set_prompt)
             .add_command("SETTITLE", Self::set_window_title)
@@ -156,53 +332,129 @@ impl<S: PinentryCmds> PinentryServer<S> {
             .add_command("SETCANCEL", Self::set_button_cancel)
             .add_command("SETNOTOK", Self::set_button_not_ok)
             .add_command("SETERROR", Self::set_error_text)
-            .add_command("SETQUALITYBAR", Self::not_currently_supported)
-            .add_command("SETQUALITYBAR_TT", Self::not_currently_supported)
+            .add_command("SETREPEAT", Self::set_repeat)
+            .add_command("SETREPEATERROR", Self::set_repeat_error)
+            .add_command("SETREPEATOK", Self::set_repeat_ok)
+            .add_command("SETQUALITYBAR", Self::set_quality_bar)
+            .add_command("SETQUALITYBAR_TT", Self::set_quality_bar_tooltip)
             .add_command("GETPIN", Self::get_pin)
             .add_command("CONFIRM", Self::confirm)
             .add_command("MESSAGE", Self::message)
+            .add_reset_handler(Self::reset)
+    }
+
+    /// Clears the texts set for the previous prompt, so they don't show up in the next one
+    fn reset(&mut self) {
+        self.settings.desc = None;
+        self.settings.prompt = None;
+        self.settings.error_text = None;
+        self.settings.repeat_prompt = None;
+        self.settings.repeat_error = None;
+        self.settings.repeat_ok = None;
+        self.settings.quality_bar = None;
+        self.settings.quality_bar_tooltip = None;
+        self.pin_attempts = 0;
     }
 
     fn get_pin(&mut self, _args: Option<&str>) -> Result<Response, HandleError<S::Error>> {
+        self.pin_attempts += 1;
+        self.cmds
+            .set_pin_attempt(self.pin_attempts)
+            .map_err(HandleError::PinentryCmd)?;
+        self.cmds
+            .set_timeout(self.settings.timeout)
+            .map_err(HandleError::PinentryCmd)?;
         self.cmds
-            .get_pin(
-                self.error_text.as_deref(),
-                self.window_title
-                    .as_ref()
-                    .map(String::as_ref)
-                    .unwrap_or("Enter PIN"),
-                self.desc.as_deref(),
-                self.prompt.as_deref().unwrap_or("PIN: "),
+            .set_quality_bar(
+                self.settings.quality_bar.as_deref(),
+                self.settings.quality_bar_tooltip.as_deref(),
+            )
+            .map_err(HandleError::PinentryCmd)?;
+        let error = self.settings.error_text.as_deref();
+        let window_title = self
+            .settings
+            .window_title
+            .as_ref()
+            .map(String::as_ref)
+            .unwrap_or("Enter PIN");
+        let desc = self.settings.desc.as_deref();
+        let prompt = self
+            .settings
+            .prompt
+            .as_deref()
+            .or(self.settings.default_prompt.as_deref())
+            .unwrap_or("PIN: ");
+
+        let Some(repeat_prompt) = self.settings.repeat_prompt.as_deref() else {
+            return self
+                .cmds
+                .get_pin(error, window_title, desc, prompt)
+                .map_err(HandleError::PinentryCmd)?
+                .ok_or(HandleError::NoPin)
+                .map(Into::into);
+        };
+        let pin = self
+            .cmds
+            .get_pin_repeated(
+                error,
+                window_title,
+                desc,
+                prompt,
+                repeat_prompt,
+                self.settings
+                    .repeat_error
+                    .as_deref()
+                    .unwrap_or("PINs do not match"),
             )
             .map_err(HandleError::PinentryCmd)?
-            .ok_or(HandleError::NoPin)
-            .map(Into::into)
+            .ok_or(HandleError::NoPin)?;
+        if let Some(repeat_ok) = self.settings.repeat_ok.as_deref() {
+            self.cmds
+                .show_repeat_ok(repeat_ok)
+                .map_err(HandleError::PinentryCmd)?;
+        }
+        Ok(Response::from(pin).with_status("PIN_REPEATED", "")?)
     }
 
-    fn _confirm(&mut self, one_button: bool) -> Result<Response, HandleError<S::Error>> {
+    fn _confirm(
+        &mut self,
+        one_button: bool,
+        default_cancel: bool,
+    ) -> Result<Response, HandleError<S::Error>> {
+        let ok = self
+            .settings
+            .button_ok
+            .as_deref()
+            .or(self.settings.default_ok.as_deref())
+            .unwrap_or("OK");
         let buttons = if one_button {
             Buttons {
-                ok: self.button_ok.as_deref().unwrap_or("OK"),
+                ok,
                 not_ok: None,
                 cancel: None,
+                default_cancel: false,
             }
         } else {
             let mut btns = Buttons {
-                ok: self.button_ok.as_deref().unwrap_or("OK"),
-                not_ok: self.button_not_ok.as_ref().map(String::as_ref),
-                cancel: self.button_cancel.as_ref().map(String::as_ref),
+                ok,
+                not_ok: self.settings.button_not_ok.as_ref().map(String::as_ref),
+                cancel: self.settings.button_cancel.as_ref().map(String::as_ref),
+                default_cancel,
             };
             if btns.not_ok.is_none() && btns.cancel.is_none() {
-                btns.cancel = Some("Cancel");
+                btns.cancel = Some(self.settings.default_cancel.as_deref().unwrap_or("Cancel"));
             }
             btns
         };
+        self.cmds
+            .set_timeout(self.settings.timeout)
+            .map_err(HandleError::PinentryCmd)?;
         let response = self
             .cmds
             .confirm(
-                self.error_text.as_deref(),
-                self.window_title.as_deref().unwrap_or("Confirm"),
-                self.desc.as_ref().map(String::as_ref),
+                self.settings.error_text.as_deref(),
+                self.settings.window_title.as_deref().unwrap_or("Confirm"),
+                self.settings.desc.as_ref().map(String::as_ref),
                 buttons,
             )
             .map_err(HandleError::PinentryCmd)?;
@@ -213,15 +465,21 @@ impl<S: PinentryCmds> PinentryServer<S> {
         }
     }
 
+    /// Asks for confirmation, unknown flags are ignored
     fn confirm(&mut self, args: Option<&str>) -> Result<Response, HandleError<S::Error>> {
-        let one_button = args
-            .map(|args| args.trim() == "--one-button")
-            .unwrap_or(false);
-        self._confirm(one_button)
+        let (mut one_button, mut default_cancel) = (false, false);
+        for flag in args.unwrap_or_default().split_whitespace() {
+            match flag {
+                "--one-button" => one_button = true,
+                "--default-cancel" => default_cancel = true,
+                _ => (),
+            }
+        }
+        self._confirm(one_button, default_cancel)
     }
 
     fn message(&mut self, _args: Option<&str>) -> Result<Response, HandleError<S::Error>> {
-        self._confirm(true)
+        self._confirm(true, false)
     }
 
     fn option(&mut self, args: Option<&str>) -> Result<Response, HandleError<S::Error>> {
@@ -236,20 +494,50 @@ impl<S: PinentryCmds> PinentryServer<S> {
                 self.cmds
                     .set_tty(value.into())
                     .map_err(HandleError::PinentryCmd)?;
+                self.ttyname = Some(value.into());
 
                 Ok(Response::ok())
             }
+            "lc-ctype" => {
+                self.cmds
+                    .set_lc_ctype(value)
+                    .map_err(HandleError::PinentryCmd)?;
+
+                Ok(Response::ok())
+            }
+            "default-ok" => self.set_default_ok(Some(value)),
+            "default-cancel" => self.set_default_cancel(Some(value)),
+            "default-prompt" => self.set_default_prompt(Some(value)),
             _ => Ok(Response::ok_with_debug_info("unknown option, ignored")?),
         }
     }
 
-    fn not_currently_supported(
-        &mut self,
-        _args: Option<&str>,
-    ) -> Result<Response, HandleError<S::Error>> {
-        Ok(Response::ok_with_debug_info(
-            "not currently supported, ignored",
-        )?)
+    /// Asks for the PIN twice in the following `GETPIN`, `args` is the prompt for repeating it
+    fn set_repeat(&mut self, args: Option<&str>) -> Result<Response, HandleError<S::Error>> {
+        let mut prompt = args.unwrap_or("Repeat:").to_string();
+        if !prompt.ends_with(' ') {
+            prompt.push(' ');
+        }
+        self.settings.repeat_prompt = Some(prompt);
+        Ok(Response::ok())
+    }
+
+    /// Enables the quality bar in the following `GETPIN`, `args` is the label of the bar
+    fn set_quality_bar(&mut self, args: Option<&str>) -> Result<Response, HandleError<S::Error>> {
+        self.settings.quality_bar = Some(args.unwrap_or("Quality:").to_string());
+        Ok(Response::ok())
+    }
+
+    /// Sets timeout in seconds, `0` disables it
+    fn set_timeout(&mut self, args: Option<&str>) -> Result<Response, HandleError<S::Error>> {
+        let seconds: u64 = args
+            .unwrap_or("0")
+            .trim()
+            .parse()
+            .map_err(|_| HandleError::InvalidTimeout)?;
+        self.settings.timeout =
+            Some(std::time::Duration::from_secs(seconds)).filter(|t| !t.is_zero());
+        Ok(Response::ok())
     }
 
     define_setters! {
@@ -260,6 +548,12 @@ impl<S: PinentryCmds> PinentryServer<S> {
         set_button_not_ok button_not_ok,
         set_button_cancel button_cancel,
         set_error_text error_text,
+        set_repeat_error repeat_error,
+        set_repeat_ok repeat_ok,
+        set_quality_bar_tooltip quality_bar_tooltip,
+        set_default_ok default_ok,
+        set_default_cancel default_cancel,
+        set_default_prompt default_prompt |prompt: &mut String| if !prompt.ends_with(' ') { prompt.push(' ') },
     }
 }
 
@@ -269,6 +563,7 @@ enum HandleError<E> {
     ConfirmRefused,
     ConfirmCancelled,
     NoPin,
+    InvalidTimeout,
     PinentryCmd(E),
 }
 
@@ -279,19 +574,27 @@ impl<E: fmt::Display> fmt::Display for HandleError<E> {
             Self::ConfirmRefused => write!(f, "refused"),
             Self::ConfirmCancelled => write!(f, "canceled"),
             Self::NoPin => write!(f, "no pin given"),
+            Self::InvalidTimeout => write!(f, "timeout must be a number of seconds"),
             Self::PinentryCmd(err) => err.fmt(f),
         }
     }
 }
 
+/// Errors are attributed to pinentry, unless [`PinentryCmds`] error has its own source
 impl<E: HasErrorCode> HasErrorCode for HandleError<E> {
     fn code(&self) -> assuan::ErrorCode {
-        match self {
+        let code = match self {
             HandleError::DebugInfoTooLong(_) => assuan::ErrorCode::INTERNAL,
             HandleError::ConfirmRefused => assuan::ErrorCode::NOT_CONFIRMED,
             HandleError::ConfirmCancelled => assuan::ErrorCode::CANCELED,
             HandleError::NoPin => assuan::ErrorCode::NO_PIN,
+            HandleError::InvalidTimeout => assuan::ErrorCode::ASS_PARAMETER,
             HandleError::PinentryCmd(err) => err.code(),
+        };
+        if code.source() == Source::UNKNOWN {
+            code.with_source(Source::PINENTRY)
+        } else {
+            code
         }
     }
 }
@@ -301,3 +604,545 @@ impl<E> From<assuan::response::TooLong> for HandleError<E> {
         Self::DebugInfoTooLong(err)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::{cell::RefCell, rc::Rc, time::Duration};
+
+    use assuan::response::SecretData;
+
+    use crate::{Buttons, ConfirmChoice, PinentryCmds, PinentryServer, PinentrySettings};
+
+    struct NoopCmds;
+
+    /// Window title, description and prompt the PIN was asked with
+    type AskedPin = (String, Option<String>, String);
+
+    /// OK and Cancel labels the confirmation was asked with, and whether Cancel is the default
+    type AskedButtons = (String, Option<String>, bool);
+
+    /// Label and tooltip of the quality bar the PIN was asked with
+    type AskedQualityBar = (Option<String>, Option<String>);
+
+    /// Returns the PIN and confirms, remembering the prompts they were asked with
+    #[derive(Default)]
+    struct RecordingCmds {
+        asked: Rc<RefCell<Vec<AskedPin>>>,
+        attempts: Rc<RefCell<Vec<usize>>>,
+        buttons: Rc<RefCell<Vec<AskedButtons>>>,
+        timeouts: Rc<RefCell<Vec<Option<Duration>>>>,
+        errors: Rc<RefCell<Vec<Option<String>>>>,
+        quality_bars: Rc<RefCell<Vec<AskedQualityBar>>>,
+        repeat_oks: Rc<RefCell<Vec<String>>>,
+        /// PINs typed by the user, `1234` is typed once they run out
+        pins: Vec<&'static str>,
+    }
+
+    impl PinentryCmds for RecordingCmds {
+        type Error = std::convert::Infallible;
+
+        fn set_tty(&mut self, _path: std::path::PathBuf) -> Result<(), Self::Error> {
+            Ok(())
+        }
+
+        fn set_pin_attempt(&mut self, attempt: usize) -> Result<(), Self::Error> {
+            self.attempts.borrow_mut().push(attempt);
+            Ok(())
+        }
+
+        fn set_timeout(&mut self, timeout: Option<Duration>) -> Result<(), Self::Error> {
+            self.timeouts.borrow_mut().push(timeout);
+            Ok(())
+        }
+
+        fn set_quality_bar(
+            &mut self,
+            label: Option<&str>,
+            tooltip: Option<&str>,
+        ) -> Result<(), Self::Error> {
+            self.quality_bars
+                .borrow_mut()
+                .push((label.map(String::from), tooltip.map(String::from)));
+            Ok(())
+        }
+
+        fn get_pin(
+            &mut self,
+            error: Option<&str>,
+            window_title: &str,
+            desc: Option<&str>,
+            prompt: &str,
+        ) -> Result<Option<SecretData>, Self::Error> {
+            self.asked.borrow_mut().push((
+                window_title.into(),
+                desc.map(String::from),
+                prompt.into(),
+            ));
+            self.errors.borrow_mut().push(error.map(String::from));
+            let mut pin = SecretData::default();
+            if self.pins.is_empty() {
+                pin.append("1234").unwrap();
+            } else {
+                pin.append(self.pins.remove(0)).unwrap();
+            }
+            Ok(Some(pin))
+        }
+
+        fn show_repeat_ok(&mut self, message: &str) -> Result<(), Self::Error> {
+            self.repeat_oks.borrow_mut().push(message.into());
+            Ok(())
+        }
+
+        fn confirm(
+            &mut self,
+            _error: Option<&str>,
+            _window_title: &str,
+            _desc: Option<&str>,
+            buttons: Buttons,
+        ) -> Result<ConfirmChoice, Self::Error> {
+            self.buttons.borrow_mut().push((
+                buttons.ok.into(),
+                buttons.cancel.map(String::from),
+                buttons.default_cancel,
+            ));
+            Ok(ConfirmChoice::Ok)
+        }
+    }
+
+    impl PinentryCmds for NoopCmds {
+        type Error = std::convert::Infallible;
+
+        fn set_tty(&mut self, _path: std::path::PathBuf) -> Result<(), Self::Error> {
+            Ok(())
+        }
+
+        fn get_pin(
+            &mut self,
+            _error: Option<&str>,
+            _window_title: &str,
+            _desc: Option<&str>,
+            _prompt: &str,
+        ) -> Result<Option<SecretData>, Self::Error> {
+            Ok(None)
+        }
+
+        fn confirm(
+            &mut self,
+            _error: Option<&str>,
+            _window_title: &str,
+            _desc: Option<&str>,
+            _buttons: Buttons,
+        ) -> Result<ConfirmChoice, Self::Error> {
+            Ok(ConfirmChoice::Canceled)
+        }
+    }
+
+    #[test]
+    fn settings_round_trip() {
+        let mut server = PinentryServer::new(NoopCmds);
+        server.set_desc(Some("Unlock the key")).unwrap();
+        server.set_prompt(Some("Passphrase:")).unwrap();
+        server.set_button_cancel(Some("Abort")).unwrap();
+
+        let settings = server.settings();
+        assert_eq!(
+            settings,
+            PinentrySettings {
+                desc: Some("Unlock the key".into()),
+                prompt: Some("Passphrase: ".into()),
+                button_cancel: Some("Abort".into()),
+                ..Default::default()
+            }
+        );
+
+        let mut restored = PinentryServer::new(NoopCmds);
+        restored.apply_settings(settings.clone());
+        assert_eq!(restored.settings(), settings);
+    }
+
+    #[test]
+    fn reset_clears_prompt_texts() {
+        let cmds = RecordingCmds::default();
+        let asked = cmds.asked.clone();
+        let mut server = PinentryServer::new(cmds).build_assuan_server();
+
+        let input = "SETTITLE Unlock\n\
+            SETDESC Unlock the key\n\
+            SETPROMPT Passphrase:\n\
+            SETERROR Bad passphrase\n\
+            RESET\n\
+            GETPIN\n";
+        let mut output = vec![];
+        server.serve_client(input.as_bytes(), &mut output).unwrap();
+        assert!(String::from_utf8(output)
+            .unwrap()
+            .ends_with("D 1234\nOK success\n"));
+        assert_eq!(*asked.borrow(), [("Unlock".into(), None, "PIN: ".into())]);
+    }
+
+    #[test]
+    fn pin_attempts_are_counted() {
+        let cmds = RecordingCmds::default();
+        let attempts = cmds.attempts.clone();
+        let mut server = PinentryServer::new(cmds).build_assuan_server();
+
+        let input = "GETPIN\n\
+            SETERROR Bad passphrase (try 2 of 3)\n\
+            GETPIN\n\
+            RESET\n\
+            GETPIN\n";
+        let mut output = vec![];
+        server.serve_client(input.as_bytes(), &mut output).unwrap();
+        assert_eq!(*attempts.borrow(), [1, 2, 1]);
+    }
+
+    #[test]
+    fn timeout_is_passed_to_cmds() {
+        let cmds = RecordingCmds::default();
+        let timeouts = cmds.timeouts.clone();
+        let mut server = PinentryServer::new(cmds).build_assuan_server();
+
+        let input = "GETPIN\n\
+            SETTIMEOUT 30\n\
+            GETPIN\n\
+            CONFIRM\n\
+            SETTIMEOUT soon\n\
+            SETTIMEOUT 0\n\
+            GETPIN\n";
+        let mut output = vec![];
+        server.serve_client(input.as_bytes(), &mut output).unwrap();
+        assert!(String::from_utf8(output)
+            .unwrap()
+            .contains("ERR 83886360 timeout must be a number of seconds\n"));
+        assert_eq!(
+            *timeouts.borrow(),
+            [
+                None,
+                Some(Duration::from_secs(30)),
+                Some(Duration::from_secs(30)),
+                None
+            ]
+        );
+    }
+
+    #[test]
+    fn repeated_pin_is_confirmed() {
+        let cmds = RecordingCmds {
+            pins: vec!["1234", "1235", "123", "1234"],
+            ..Default::default()
+        };
+        let asked = cmds.asked.clone();
+        let errors = cmds.errors.clone();
+        let mut server = PinentryServer::new(cmds).build_assuan_server();
+
+        let input = "SETERROR Too weak\n\
+            SETREPEAT Again:\n\
+            SETREPEATERROR Mismatch\n\
+            GETPIN\n\
+            RESET\n\
+            GETPIN\n";
+        let mut output = vec![];
+        server.serve_client(input.as_bytes(), &mut output).unwrap();
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "OK how can I serve you?\n\
+            OK success\n\
+            OK success\n\
+            OK success\n\
+            S PIN_REPEATED\n\
+            D 1234\n\
+            OK success\n\
+            OK success\n\
+            D 1234\n\
+            OK success\n"
+        );
+        let prompts: Vec<_> = asked.borrow().iter().map(|a| a.2.clone()).collect();
+        assert_eq!(
+            prompts,
+            ["PIN: ", "Again: ", "PIN: ", "Again: ", "PIN: ", "Again: ", "PIN: "]
+        );
+        assert_eq!(
+            *errors.borrow(),
+            [
+                Some("Too weak".into()),
+                None,
+                Some("Mismatch".into()),
+                None,
+                Some("Mismatch".into()),
+                None,
+                None,
+            ]
+        );
+    }
+
+    #[test]
+    fn repeat_ok_is_shown_once_pins_match() {
+        let cmds = RecordingCmds {
+            pins: vec!["1234", "1235", "1234", "1234"],
+            ..Default::default()
+        };
+        let repeat_oks = cmds.repeat_oks.clone();
+        let mut server = PinentryServer::new(cmds).build_assuan_server();
+
+        let input = "SETREPEAT\n\
+            SETREPEATOK PINs match\n\
+            GETPIN\n\
+            GETPIN\n\
+            RESET\n\
+            SETREPEAT\n\
+            GETPIN\n";
+        let mut output = vec![];
+        server.serve_client(input.as_bytes(), &mut output).unwrap();
+        assert_eq!(*repeat_oks.borrow(), ["PINs match", "PINs match"]);
+    }
+
+    #[test]
+    fn quality_bar_is_passed_to_cmds() {
+        let cmds = RecordingCmds::default();
+        let quality_bars = cmds.quality_bars.clone();
+        let mut server = PinentryServer::new(cmds).build_assuan_server();
+
+        let input = "GETPIN\n\
+            SETQUALITYBAR\n\
+            GETPIN\n\
+            SETQUALITYBAR Strength:\n\
+            SETQUALITYBAR_TT Entropy of the passphrase\n\
+            GETPIN\n\
+            RESET\n\
+            GETPIN\n";
+        let mut output = vec![];
+        server.serve_client(input.as_bytes(), &mut output).unwrap();
+        assert_eq!(
+            *quality_bars.borrow(),
+            [
+                (None, None),
+                (Some("Quality:".into()), None),
+                (
+                    Some("Strength:".into()),
+                    Some("Entropy of the passphrase".into())
+                ),
+                (None, None),
+            ]
+        );
+    }
+
+    #[test]
+    fn timed_out_prompts_are_reported() {
+        /// Times out the prompts once timeout is set
+        #[derive(Default)]
+        struct TimingOutCmds {
+            timeout: Option<Duration>,
+        }
+
+        struct TimedOut;
+
+        impl std::fmt::Display for TimedOut {
+            fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+                write!(f, "timed out")
+            }
+        }
+
+        impl assuan::HasErrorCode for TimedOut {
+            fn code(&self) -> assuan::ErrorCode {
+                assuan::ErrorCode::TIMEOUT
+            }
+        }
+
+        impl PinentryCmds for TimingOutCmds {
+            type Error = TimedOut;
+
+            fn set_tty(&mut self, _path: std::path::PathBuf) -> Result<(), Self::Error> {
+                Ok(())
+            }
+
+            fn set_timeout(&mut self, timeout: Option<Duration>) -> Result<(), Self::Error> {
+                self.timeout = timeout;
+                Ok(())
+            }
+
+            fn get_pin(
+                &mut self,
+                _error: Option<&str>,
+                _window_title: &str,
+                _desc: Option<&str>,
+                _prompt: &str,
+            ) -> Result<Option<SecretData>, Self::Error> {
+                match self.timeout {
+                    Some(_) => Err(TimedOut),
+                    None => Ok(None),
+                }
+            }
+
+            fn confirm(
+                &mut self,
+                _error: Option<&str>,
+                _window_title: &str,
+                _desc: Option<&str>,
+                _buttons: Buttons,
+            ) -> Result<ConfirmChoice, Self::Error> {
+                match self.timeout {
+                    Some(_) => Err(TimedOut),
+                    None => Ok(ConfirmChoice::Ok),
+                }
+            }
+        }
+
+        let mut server = PinentryServer::new(TimingOutCmds::default()).build_assuan_server();
+        let input = "SETTIMEOUT 1\nGETPIN\nCONFIRM\nSETTIMEOUT 0\nCONFIRM\n";
+        let mut output = vec![];
+        server.serve_client(input.as_bytes(), &mut output).unwrap();
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "OK how can I serve you?\n\
+            OK success\n\
+            ERR 83886142 timed out\n\
+            ERR 83886142 timed out\n\
+            OK success\n\
+            OK success\n"
+        );
+    }
+
+    #[test]
+    fn getinfo_describes_pinentry() {
+        let mut server = PinentryServer::new(NoopCmds)
+            .with_flavor("tty")
+            .build_assuan_server();
+
+        let input = "GETINFO flavor\n\
+            GETINFO ttyinfo\n\
+            OPTION ttyname=/dev/pts/1\n\
+            GETINFO ttyinfo\n\
+            GETINFO version\n\
+            GETINFO colors\n";
+        let mut output = vec![];
+        server.serve_client(input.as_bytes(), &mut output).unwrap();
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            format!(
+                "OK how can I serve you?\n\
+                D tty\nOK success\n\
+                D - - -\nOK success\n\
+                OK success\n\
+                D /dev/pts/1 - -\nOK success\n\
+                D {}\nOK success\n\
+                ERR 280 unknown info key\n",
+                env!("CARGO_PKG_VERSION")
+            )
+        );
+    }
+
+    #[test]
+    fn default_labels_from_options() {
+        let cmds = RecordingCmds::default();
+        let asked = cmds.asked.clone();
+        let buttons = cmds.buttons.clone();
+        let mut server = PinentryServer::new(cmds).build_assuan_server();
+
+        let input = "OPTION default-ok=Accept\n\
+            OPTION default-cancel=Abort\n\
+            OPTION default-prompt=Passphrase:\n\
+            CONFIRM\n\
+            SETOK Yes\n\
+            CONFIRM\n\
+            GETPIN\n\
+            SETPROMPT Code:\n\
+            GETPIN\n";
+        let mut output = vec![];
+        server.serve_client(input.as_bytes(), &mut output).unwrap();
+        assert_eq!(
+            *buttons.borrow(),
+            [
+                ("Accept".into(), Some("Abort".into()), false),
+                ("Yes".into(), Some("Abort".into()), false),
+            ]
+        );
+        let prompts: Vec<_> = asked.borrow().iter().map(|a| a.2.clone()).collect();
+        assert_eq!(prompts, ["Passphrase: ", "Code: "]);
+    }
+
+    #[test]
+    fn confirm_flags() {
+        let cmds = RecordingCmds::default();
+        let buttons = cmds.buttons.clone();
+        let mut server = PinentryServer::new(cmds).build_assuan_server();
+
+        let input = "CONFIRM --default-cancel\n\
+            CONFIRM --one-button\n\
+            CONFIRM --unknown  --default-cancel --one-button\n\
+            CONFIRM --unknown\n";
+        let mut output = vec![];
+        server.serve_client(input.as_bytes(), &mut output).unwrap();
+        assert_eq!(
+            *buttons.borrow(),
+            [
+                ("OK".into(), Some("Cancel".into()), true),
+                ("OK".into(), None, false),
+                ("OK".into(), None, false),
+                ("OK".into(), Some("Cancel".into()), false),
+            ]
+        );
+    }
+
+    #[test]
+    fn errors_are_attributed_to_pinentry() {
+        let mut server = PinentryServer::new(NoopCmds).build_assuan_server();
+
+        let input = "GETPIN\nCONFIRM\n";
+        let mut output = vec![];
+        server.serve_client(input.as_bytes(), &mut output).unwrap();
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "OK how can I serve you?\n\
+            ERR 83886258 no pin given\n\
+            ERR 83886179 canceled\n"
+        );
+    }
+
+    #[test]
+    fn pipelined_requests_are_served_in_order() {
+        let cmds = RecordingCmds::default();
+        let asked = cmds.asked.clone();
+        let mut server = PinentryServer::new(cmds).build_assuan_server();
+
+        // gpg sends the whole burst in a single write without waiting for responses
+        let input = "OPTION ttyname=/dev/pts/1\n\
+            OPTION lc-ctype=C\n\
+            SETDESC Unlock%0Athe key\n\
+            SETPROMPT Passphrase:\n\
+            GETPIN\n\
+            SETDESC Again\n\
+            GETPIN\n\
+            BYE\n";
+        let mut output = vec![];
+        server.serve_client(input.as_bytes(), &mut output).unwrap();
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "OK how can I serve you?\n\
+            OK success\n\
+            OK success\n\
+            OK success\n\
+            OK success\n\
+            D 1234\n\
+            OK success\n\
+            OK success\n\
+            D 1234\n\
+            OK success\n\
+            OK success\n"
+        );
+        assert_eq!(
+            *asked.borrow(),
+            [
+                (
+                    "Enter PIN".into(),
+                    Some("Unlock\nthe key".into()),
+                    "Passphrase: ".into()
+                ),
+                (
+                    "Enter PIN".into(),
+                    Some("Again".into()),
+                    "Passphrase: ".into()
+                ),
+            ]
+        );
+    }
+}